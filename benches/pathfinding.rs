@@ -0,0 +1,53 @@
+//! Route queries across several chunks of an obstacle course, with the graphs
+//! built from scratch and with them already cached.
+
+use std::collections::HashSet;
+
+use bevy::prelude::*;
+use criterion::{criterion_group, criterion_main, Criterion};
+use terra::game::{
+    pathfinding::{AgentParams, NavCache},
+    tilemap::CHUNK_COLS,
+};
+
+const AGENT: AgentParams = AgentParams { height: 2, jump_height: 3, jump_distance: 3, max_fall: 8 };
+const FLOOR_ROW: i32 = 18;
+
+/// A floor four chunks wide, broken by pits and walls to jump over every few
+/// tiles.
+fn course() -> HashSet<IVec2> {
+    let mut solid = HashSet::new();
+    for x in 0..4 * CHUNK_COLS {
+        match x % 9 {
+            // Pits two tiles wide.
+            3 | 4 => {}
+            // Walls as high as the agent can jump.
+            7 => {
+                for y in FLOOR_ROW - AGENT.jump_height..=FLOOR_ROW {
+                    solid.insert(IVec2::new(x, y));
+                }
+            }
+            _ => {
+                solid.insert(IVec2::new(x, FLOOR_ROW));
+            }
+        }
+    }
+    solid
+}
+
+fn find_path(c: &mut Criterion) {
+    let solid = course();
+    let is_solid = |g: IVec2| solid.contains(&g);
+    let start = IVec2::new(1, FLOOR_ROW - 1);
+    let goal = IVec2::new(4 * CHUNK_COLS - 2, FLOOR_ROW - 1);
+    assert!(NavCache::default().find_path(&is_solid, &AGENT, start, goal).is_some(), "the course has no route");
+
+    c.bench_function("find_path cold", |b| {
+        b.iter(|| NavCache::default().find_path(&is_solid, &AGENT, start, goal))
+    });
+    let mut cache = NavCache::default();
+    c.bench_function("find_path cached", |b| b.iter(|| cache.find_path(&is_solid, &AGENT, start, goal)));
+}
+
+criterion_group!(benches, find_path);
+criterion_main!(benches);
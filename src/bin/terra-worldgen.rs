@@ -0,0 +1,153 @@
+//! Runs the world generator without a window, to tune it: writes a picture of
+//! a range of screens and prints what they are made of.
+//!
+//! The generator config is a RON `WorldGenConfig`; missing fields keep the
+//! game's values, so a file can change a single threshold.
+
+use std::{collections::BTreeMap, path::{Path, PathBuf}};
+
+use bevy::prelude::*;
+use terra::game::{
+    export::{load_atlas, render_region, ExportStyle},
+    tilemap::{TileKind, TileLayer, TileMap, CHUNK_COLS, CHUNK_ROWS},
+    worldgen::{WorldGen, WorldGenConfig, UNDERGROUND_ROW},
+};
+
+const USAGE: &str =
+    "usage: terra-worldgen <seed> <from x> <from y> <to x> <to y> [--config gen.ron] [--out worldgen.png] [--flat]";
+
+/// Cells of one kind of foreground tile, or empty ones.
+type Counts = BTreeMap<&'static str, u64>;
+
+/// What the generated screens are made of.
+#[derive(Default)]
+struct Stats {
+    cells: u64,
+    /// Foreground tiles over the whole range, by name; `empty` for air.
+    kinds: Counts,
+    /// Cells and empty cells below the surface screens.
+    underground: u64,
+    caves: u64,
+    /// Solid kinds of each screen row, top to bottom.
+    depths: BTreeMap<i32, Counts>,
+}
+
+impl Stats {
+    fn add(&mut self, screen_y: i32, g: IVec2, kind: Option<TileKind>) {
+        let name = kind.map_or("empty", |kind| kind.def().name);
+        self.cells += 1;
+        *self.kinds.entry(name).or_default() += 1;
+        if g.y >= UNDERGROUND_ROW {
+            self.underground += 1;
+            if kind.is_none() {
+                self.caves += 1;
+            }
+        }
+        if kind.is_some_and(|kind| kind.def().solid) {
+            *self.depths.entry(screen_y).or_default().entry(name).or_default() += 1;
+        }
+    }
+
+    fn report(&self) -> String {
+        let mut out = String::new();
+        out.push_str(&format!("cells: {}\n\ntiles:\n", self.cells));
+        for (name, count) in &self.kinds {
+            out.push_str(&format!("  {name:<8} {count:>8} {:>6.2}%\n", percent(*count, self.cells)));
+        }
+        out.push_str(&format!(
+            "\ncave ratio: {:.2}% of {} underground cells\n\nsolid tiles by screen row:\n",
+            percent(self.caves, self.underground),
+            self.underground,
+        ));
+        for (screen_y, counts) in &self.depths {
+            let total: u64 = counts.values().sum();
+            let shares: Vec<String> = TileKind::ALL
+                .iter()
+                .filter(|kind| kind.def().solid)
+                .map(|kind| {
+                    let count = counts.get(kind.def().name).copied().unwrap_or(0);
+                    format!("{} {:.1}%", kind.def().name, percent(count, total))
+                })
+                .collect();
+            out.push_str(&format!("  y {screen_y:>3}: {}\n", shares.join(", ")));
+        }
+        out
+    }
+}
+
+fn percent(part: u64, whole: u64) -> f64 {
+    if whole == 0 { 0.0 } else { part as f64 * 100.0 / whole as f64 }
+}
+
+/// Generates every screen of the range the way `move_world` does and counts
+/// the cells each one owns, so the shared border rows are counted once.
+fn survey(worldgen: &WorldGen, from: IVec2, to: IVec2) -> Stats {
+    let (low, high) = (from.min(to), from.max(to));
+    // Nothing is drawn, so the tile map needs no real assets.
+    let mut tilemap = TileMap::new(Handle::default(), Handle::default());
+    let mut stats = Stats::default();
+    for screen_y in low.y..=high.y {
+        for screen_x in low.x..=high.x {
+            tilemap.position = Vec2::new(screen_x as f32, screen_y as f32);
+            tilemap.load_screen(worldgen);
+            for y in 0..CHUNK_ROWS as usize {
+                for x in 0..CHUNK_COLS as usize {
+                    let tile = tilemap.tile_at(TileLayer::Foreground, x, y);
+                    stats.add(screen_y, tilemap.to_global(x, y), tile.map(|tile| tile.kind));
+                }
+            }
+        }
+    }
+    stats
+}
+
+fn read_config(path: &Path) -> Result<WorldGenConfig, String> {
+    let text = std::fs::read_to_string(path).map_err(|err| format!("could not read {}: {err}", path.display()))?;
+    ron::from_str(&text).map_err(|err| format!("bad config {}: {err}", path.display()))
+}
+
+fn run(args: &[String]) -> Result<(), String> {
+    let mut config = WorldGenConfig::default();
+    let mut out = PathBuf::from("worldgen.png");
+    let mut flat = false;
+    let mut positional = Vec::new();
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--config" => config = read_config(Path::new(args.next().ok_or(USAGE)?))?,
+            "--out" => out = PathBuf::from(args.next().ok_or(USAGE)?),
+            "--flat" => flat = true,
+            _ => positional.push(arg.as_str()),
+        }
+    }
+    let [seed, from_x, from_y, to_x, to_y] = positional[..] else {
+        return Err(USAGE.to_string());
+    };
+    let number = |text: &str| text.parse::<i32>().map_err(|_| format!("not a whole number: {text}\n{USAGE}"));
+    let seed = seed.parse::<u32>().map_err(|_| format!("not a seed: {seed}\n{USAGE}"))?;
+    let from = IVec2::new(number(from_x)?, number(from_y)?);
+    let to = IVec2::new(number(to_x)?, number(to_y)?);
+
+    let worldgen = WorldGen::with_config(seed, config);
+    let atlas = if flat { None } else { Some(load_atlas()?) };
+    let style = atlas.as_ref().map_or(ExportStyle::Flat, ExportStyle::Atlas);
+    let tilemap = TileMap::new(Handle::default(), Handle::default());
+    render_region(&tilemap, &worldgen, from, to, style)
+        .save(&out)
+        .map_err(|err| format!("could not write {}: {err}", out.display()))?;
+
+    let report = format!("seed {seed}, screens {from} to {to}\n{config:?}\n\n{}", survey(&worldgen, from, to).report());
+    let stats_path = out.with_extension("txt");
+    std::fs::write(&stats_path, &report).map_err(|err| format!("could not write {}: {err}", stats_path.display()))?;
+    print!("{report}");
+    println!("\nwrote {} and {}", out.display(), stats_path.display());
+    Ok(())
+}
+
+fn main() {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    if let Err(err) = run(&args) {
+        eprintln!("{err}");
+        std::process::exit(1);
+    }
+}
@@ -0,0 +1,239 @@
+use std::{collections::HashMap, fs};
+
+use bevy::{
+    asset::RenderAssetUsages,
+    prelude::*,
+    render::render_resource::{Extent3d, TextureDimension, TextureFormat},
+    sprite::Anchor,
+};
+use noise::{NoiseFn, Perlin};
+use serde::Deserialize;
+
+use crate::game::{
+    clock::WorldClock,
+    lighting::MAX_LIGHT,
+    state::{GameState, GameplaySet},
+    tilemap::{TileMap, TILE_SIZE},
+    worldgen::{Biome, WorldGen},
+    AssetDir, Canvas, InGameCamera, PIXEL_PERFECT_LAYERS,
+};
+
+/// Directory of the background files, in the [`AssetDir`].
+const BACKGROUND_DIR: &str = "backgrounds";
+/// Depth of the rearmost layer; each following layer is drawn in front of it.
+const BACKGROUND_Z: f32 = -20.0;
+
+pub struct BackgroundPlugin;
+
+impl Plugin for BackgroundPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(Startup, load_backgrounds);
+        app.add_systems(PostUpdate, (switch_backgrounds, scroll_backgrounds).chain().in_set(GameplaySet));
+        app.add_systems(OnExit(GameState::InGame), forget_shown_background);
+    }
+}
+
+/// Parallax layers of one biome, as read from `assets/backgrounds/<biome>.ron`.
+#[derive(Deserialize, Clone, Default)]
+pub struct BiomeBackground {
+    /// Layers behind screens at or above the surface.
+    pub surface: Vec<LayerDef>,
+    /// Layers behind underground screens.
+    pub cave: Vec<LayerDef>,
+}
+
+#[derive(Deserialize, Clone)]
+pub struct LayerDef {
+    /// Fraction of the view movement the layer follows: 0 stays put, 1 moves with the tiles.
+    pub parallax: f32,
+    pub kind: LayerKind,
+}
+
+/// How a layer image is drawn. Colours are linear `(r, g, b, a)`.
+#[derive(Deserialize, Clone)]
+pub enum LayerKind {
+    /// Vertical blend over the whole canvas.
+    Gradient { top: (f32, f32, f32, f32), bottom: (f32, f32, f32, f32) },
+    /// Ridge line filled down to the bottom of the canvas.
+    Mountains { colour: (f32, f32, f32, f32), base: u32, height: u32, roughness: f64, seed: u32 },
+    /// Noise blobs scattered over the canvas.
+    Rocks { colour: (f32, f32, f32, f32), scale: f64, threshold: f64, seed: u32 },
+}
+
+#[derive(Resource, Default)]
+struct Backgrounds {
+    biomes: HashMap<Biome, BiomeBackground>,
+    /// Biome and depth the spawned layers belong to.
+    shown: Option<(Biome, bool)>,
+}
+
+#[derive(Component)]
+struct BackgroundLayer {
+    parallax: f32,
+    /// Which of the two side-by-side copies this is.
+    copy: u32,
+    /// Whether the layer darkens with the sky at night.
+    daylit: bool,
+}
+
+fn load_backgrounds(mut commands: Commands, assets: Res<AssetDir>) {
+    let mut backgrounds = Backgrounds::default();
+    for biome in Biome::ALL {
+        let path = assets.0.join(BACKGROUND_DIR).join(format!("{}.ron", biome.name()));
+        let def = match fs::read_to_string(&path).map_err(|e| e.to_string()).and_then(|text| {
+            ron::from_str::<BiomeBackground>(&text).map_err(|e| e.to_string())
+        }) {
+            Ok(def) => def,
+            Err(err) => {
+                warn!("no background for {}: {}: {err}", biome.name(), path.display());
+                BiomeBackground::default()
+            }
+        };
+        backgrounds.biomes.insert(biome, def);
+    }
+    commands.insert_resource(backgrounds);
+}
+
+/// The layers are despawned with the world, so the next one has to spawn its own.
+fn forget_shown_background(mut backgrounds: ResMut<Backgrounds>) {
+    backgrounds.shown = None;
+}
+
+/// Replaces the layers when the player enters another biome or crosses the
+/// surface, and redraws them when the canvas changes size.
+fn switch_backgrounds(
+    mut commands: Commands,
+    mut backgrounds: ResMut<Backgrounds>,
+    mut images: ResMut<Assets<Image>>,
+    canvas: Res<Canvas>,
+    tilemap: Res<TileMap>,
+    worldgen: Res<WorldGen>,
+    layers: Query<Entity, With<BackgroundLayer>>,
+) {
+    let wanted = (worldgen.biome_at(tilemap.position.x as i32), tilemap.position.y > 0.);
+    if backgrounds.shown == Some(wanted) && !canvas.is_changed() {
+        return;
+    }
+    backgrounds.shown = Some(wanted);
+
+    for entity in &layers {
+        commands.entity(entity).despawn();
+    }
+
+    let (biome, underground) = wanted;
+    let Some(def) = backgrounds.biomes.get(&biome) else {
+        return;
+    };
+    let defs = if underground { &def.cave } else { &def.surface };
+    for (i, layer) in defs.iter().enumerate() {
+        let image = images.add(render_layer(&layer.kind, canvas.image_size().x, canvas.image_size().y));
+        for copy in 0..2 {
+            let mut sprite = Sprite::from_image(image.clone());
+            sprite.anchor = Anchor::TopLeft;
+            commands.spawn((
+                sprite,
+                Transform::from_xyz(0.0, 0.0, BACKGROUND_Z + i as f32),
+                BackgroundLayer { parallax: layer.parallax, copy, daylit: !underground },
+                PIXEL_PERFECT_LAYERS,
+                StateScoped(GameState::InGame),
+            ));
+        }
+    }
+}
+
+/// Keeps every layer in view, shifted by its share of the view position and
+/// snapped to whole canvas pixels.
+fn scroll_backgrounds(
+    tilemap: Res<TileMap>,
+    clock: Res<WorldClock>,
+    canvas: Res<Canvas>,
+    camera: Query<&Transform, (With<InGameCamera>, Without<BackgroundLayer>)>,
+    mut layers: Query<(&BackgroundLayer, &mut Transform, &mut Sprite)>,
+) {
+    let Ok(camera) = camera.single() else {
+        return;
+    };
+    // The layers cover the whole rendered image, spare pixel included.
+    let image = canvas.image_size().as_vec2();
+    let width = image.x;
+    let view_left = camera.translation.x - width * 0.5;
+    let view_top = camera.translation.y + image.y * 0.5;
+    let view_x = tilemap.origin().x as f32 * TILE_SIZE as f32 + view_left;
+    let daylight = clock.sky_light() as f32 / MAX_LIGHT as f32;
+
+    for (layer, mut transform, mut sprite) in &mut layers {
+        let offset = (-(view_x * layer.parallax)).round().rem_euclid(width);
+        transform.translation.x = (view_left + offset - layer.copy as f32 * width).round();
+        transform.translation.y = view_top.round();
+        let light = if layer.daylit { daylight } else { 1.0 };
+        sprite.color = Color::linear_rgb(light, light, light);
+    }
+}
+
+fn linear(colour: (f32, f32, f32, f32)) -> [u8; 4] {
+    let srgb = Color::linear_rgba(colour.0, colour.1, colour.2, colour.3).to_srgba();
+    srgb.to_u8_array()
+}
+
+/// Draws a layer into a `width` x `height` image that tiles horizontally.
+pub fn render_layer(kind: &LayerKind, width: u32, height: u32) -> Image {
+    let mut data = vec![0u8; (width * height * 4) as usize];
+    let mut put = |x: u32, y: u32, pixel: [u8; 4]| {
+        let i = ((y * width + x) * 4) as usize;
+        data[i..i + 4].copy_from_slice(&pixel);
+    };
+
+    match kind {
+        LayerKind::Gradient { top, bottom } => {
+            for y in 0..height {
+                let t = y as f32 / (height - 1).max(1) as f32;
+                let lerp = |a: f32, b: f32| a + (b - a) * t;
+                let pixel = linear((
+                    lerp(top.0, bottom.0),
+                    lerp(top.1, bottom.1),
+                    lerp(top.2, bottom.2),
+                    lerp(top.3, bottom.3),
+                ));
+                for x in 0..width {
+                    put(x, y, pixel);
+                }
+            }
+        }
+        LayerKind::Mountains { colour, base, height: peak, roughness, seed } => {
+            let perlin = Perlin::new(*seed);
+            let pixel = linear(*colour);
+            // Sampling around a circle makes the ridge wrap seamlessly.
+            let radius = width as f64 * roughness / std::f64::consts::TAU;
+            for x in 0..width {
+                let angle = x as f64 / width as f64 * std::f64::consts::TAU;
+                let n = perlin.get([angle.cos() * radius, angle.sin() * radius]) * 0.5 + 0.5;
+                let ridge = (*base as f64 - n * *peak as f64).max(0.0) as u32;
+                for y in ridge.min(height)..height {
+                    put(x, y, pixel);
+                }
+            }
+        }
+        LayerKind::Rocks { colour, scale, threshold, seed } => {
+            let perlin = Perlin::new(*seed);
+            let pixel = linear(*colour);
+            let radius = width as f64 * scale / std::f64::consts::TAU;
+            for x in 0..width {
+                let angle = x as f64 / width as f64 * std::f64::consts::TAU;
+                for y in 0..height {
+                    let n = perlin.get([angle.cos() * radius, angle.sin() * radius, y as f64 * scale]);
+                    if n > *threshold {
+                        put(x, y, pixel);
+                    }
+                }
+            }
+        }
+    }
+
+    Image::new(
+        Extent3d { width, height, ..default() },
+        TextureDimension::D2,
+        data,
+        TextureFormat::Rgba8UnormSrgb,
+        RenderAssetUsages::RENDER_WORLD,
+    )
+}
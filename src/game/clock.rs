@@ -0,0 +1,138 @@
+use std::f32::consts::TAU;
+
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::game::{console::{AddConsoleCommand, Args, CommandResult, ConsoleCommand, Param, ParamKind}, lighting::MAX_LIGHT, state::GameplaySet, tilemap::TileMap, InGameCamera};
+
+/// Length of a full day in seconds of game time, unless the save says otherwise.
+pub const DEFAULT_DAY_LENGTH: f32 = 600.0;
+/// Sky light never drops below this, so nights are dark but not pitch black.
+const MIN_SKY_LIGHT: u8 = 3;
+
+const DAY_SKY: Color = Color::srgb(0.45, 0.7, 0.95);
+const DUSK_SKY: Color = Color::srgb(0.85, 0.45, 0.3);
+const NIGHT_SKY: Color = Color::srgb(0.02, 0.02, 0.08);
+
+pub struct ClockPlugin;
+
+impl Plugin for ClockPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<WorldClock>();
+        app.add_systems(Update, (advance_clock, update_sky_colour).chain().in_set(GameplaySet));
+        app.add_console_command(ConsoleCommand {
+            name: "time",
+            help: "shows the time of day, or sets it from an hour in 0 to 24",
+            params: vec![Param::optional("hour", ParamKind::Float)],
+            run: run_time,
+        });
+    }
+}
+
+/// Time of day of the world, advanced with game time.
+///
+/// `time` runs from 0 to 1 over a day: 0 is midnight, 0.25 sunrise, 0.5 noon
+/// and 0.75 sunset.
+#[derive(Resource, Serialize, Deserialize, Debug, Clone)]
+pub struct WorldClock {
+    pub time: f32,
+    pub day: u32,
+    pub day_length: f32,
+    pub paused: bool,
+}
+
+impl Default for WorldClock {
+    fn default() -> Self {
+        Self {
+            time: 0.3,
+            day: 0,
+            day_length: DEFAULT_DAY_LENGTH,
+            paused: false,
+        }
+    }
+}
+
+impl WorldClock {
+    /// Moves the clock forward by `seconds` of game time, rolling over into the next day.
+    pub fn advance(&mut self, seconds: f32) {
+        self.time += seconds / self.day_length.max(1.0);
+        while self.time >= 1.0 {
+            self.time -= 1.0;
+            self.day += 1;
+        }
+    }
+
+    pub fn advance_hours(&mut self, hours: f32) {
+        self.advance(hours / 24.0 * self.day_length);
+    }
+
+    /// Sets the time of day from an hour in `0..24`.
+    pub fn set_hour(&mut self, hour: f32) {
+        self.time = (hour / 24.0).rem_euclid(1.0);
+    }
+
+    pub fn hour(&self) -> f32 {
+        self.time * 24.0
+    }
+
+    /// Height of the sun, from -1 at midnight to 1 at noon.
+    pub fn sun_height(&self) -> f32 {
+        -(self.time * TAU).cos()
+    }
+
+    pub fn is_night(&self) -> bool {
+        self.sun_height() < -0.1
+    }
+
+    /// Light level of cells exposed to the sky.
+    pub fn sky_light(&self) -> u8 {
+        let daylight = (self.sun_height() * 2.0 + 0.5).clamp(0.0, 1.0);
+        let level = MIN_SKY_LIGHT as f32 + daylight * (MAX_LIGHT - MIN_SKY_LIGHT) as f32;
+        level.round() as u8
+    }
+
+    pub fn sky_colour(&self) -> Color {
+        let sun = self.sun_height();
+        if sun >= 0.2 {
+            DAY_SKY
+        } else if sun >= -0.2 {
+            // Blend through a warm horizon colour around sunrise and sunset.
+            let t = (sun + 0.2) / 0.4;
+            if t < 0.5 {
+                NIGHT_SKY.mix(&DUSK_SKY, t * 2.0)
+            } else {
+                DUSK_SKY.mix(&DAY_SKY, (t - 0.5) * 2.0)
+            }
+        } else {
+            NIGHT_SKY
+        }
+    }
+}
+
+fn advance_clock(mut clock: ResMut<WorldClock>, time: Res<Time>) {
+    if clock.paused {
+        return;
+    }
+    clock.advance(time.delta_secs());
+}
+
+/// Clears the canvas with the sky colour on the surface and black underground.
+fn update_sky_colour(
+    clock: Res<WorldClock>,
+    tilemap: Res<TileMap>,
+    mut camera: Query<&mut Camera, With<InGameCamera>>,
+) {
+    let colour = if tilemap.position.y > 0. { Color::BLACK } else { clock.sky_colour() };
+    for mut camera in &mut camera {
+        camera.clear_color = ClearColorConfig::Custom(colour);
+    }
+}
+
+fn run_time(world: &mut World, args: &Args) -> CommandResult {
+    let mut clock = world.resource_mut::<WorldClock>();
+    if args.given(0) {
+        clock.set_hour(args.float(0));
+    }
+    let minutes = (clock.hour() * 60.0) as u32;
+    Ok(format!("day {}, {:02}:{:02}", clock.day, minutes / 60, minutes % 60))
+}
@@ -0,0 +1,512 @@
+//! Developer console: a prompt over the canvas that runs commands against
+//! the world.
+//!
+//! Plugins register their own commands with [`AddConsoleCommand`]. Every
+//! command lists typed parameters, which are checked before it runs and
+//! completed with Tab where the possible values are known. Lines can also
+//! come from a script file, given with `--script` or run with `exec`.
+//!
+//! The console pauses the game while open. Its commands are not recorded in
+//! a [`Replay`](crate::game::replay::Replay), so it stays shut during playback.
+
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+use bevy::{
+    input::{
+        keyboard::{Key, KeyboardInput},
+        ButtonState,
+    },
+    prelude::*,
+    sprite::Anchor,
+};
+
+use crate::game::{
+    enemy::spawn_enemies,
+    hud::{scale_font, CanvasRect, FONT_SIZE, HUD_Z},
+    replay::Playback,
+    settings::Settings,
+    state::{GameState, Pause},
+    Canvas, PixelatedCanvas, HIGH_RES_LAYERS,
+};
+
+/// Output lines kept.
+const MAX_OUTPUT: usize = 200;
+const MAX_HISTORY: usize = 100;
+/// Output lines shown above the prompt.
+const VISIBLE_LINES: usize = 12;
+/// Bevy's default line height, in canvas pixels.
+const LINE_HEIGHT: f32 = FONT_SIZE * 1.2;
+const MARGIN: f32 = 4.0;
+const BACKGROUND: Color = Color::srgba(0.0, 0.0, 0.05, 0.8);
+/// Over the HUD and the world map.
+const CONSOLE_Z: f32 = HUD_Z + 3.0;
+
+pub struct ConsolePlugin;
+
+impl Plugin for ConsolePlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<Console>();
+        app.add_console_command(ConsoleCommand {
+            name: "help",
+            help: "lists the commands, or shows how to use one",
+            params: vec![Param::optional("command", ParamKind::Choice(command_names))],
+            run: run_help,
+        });
+        app.add_console_command(ConsoleCommand { name: "cls", help: "clears the console", params: vec![], run: run_cls });
+        app.add_console_command(ConsoleCommand {
+            name: "exec",
+            help: "runs the commands in a script file",
+            params: vec![Param::new("file", ParamKind::Word)],
+            run: run_exec,
+        });
+        app.add_systems(OnEnter(GameState::InGame), run_startup_script);
+        app.add_systems(OnEnter(Pause::Console), spawn_console);
+        app.add_systems(
+            Update,
+            (toggle_console.run_if(not(resource_exists::<Playback>)), (type_in_console, show_console).chain().run_if(in_state(Pause::Console)))
+                .run_if(in_state(GameState::InGame)),
+        );
+        // After the enemies of a new screen are placed, so `spawn` is not undone.
+        app.add_systems(PostUpdate, run_console.after(spawn_enemies).run_if(in_state(GameState::InGame)));
+    }
+}
+
+/// What a parameter accepts.
+#[derive(Clone, Copy)]
+pub enum ParamKind {
+    Int,
+    Float,
+    /// Any single word.
+    Word,
+    /// One of the words listed by the function, which Tab completes.
+    Choice(fn(&World) -> Vec<String>),
+}
+
+#[derive(Clone, Copy)]
+pub struct Param {
+    pub name: &'static str,
+    pub kind: ParamKind,
+    /// Optional parameters come last and may be left out.
+    pub optional: bool,
+}
+
+impl Param {
+    pub const fn new(name: &'static str, kind: ParamKind) -> Self {
+        Self { name, kind, optional: false }
+    }
+
+    pub const fn optional(name: &'static str, kind: ParamKind) -> Self {
+        Self { name, kind, optional: true }
+    }
+
+    fn parse(&self, world: &World, word: &str) -> Result<Arg, String> {
+        let name = self.name;
+        match self.kind {
+            ParamKind::Int => word.parse().map(Arg::Int).map_err(|_| format!("{name}: expected a whole number, got {word}")),
+            ParamKind::Float => word.parse().map(Arg::Float).map_err(|_| format!("{name}: expected a number, got {word}")),
+            ParamKind::Word => Ok(Arg::Word(word.to_string())),
+            ParamKind::Choice(choices) => {
+                let choices = choices(world);
+                match choices.iter().find(|choice| choice.eq_ignore_ascii_case(word)) {
+                    Some(choice) => Ok(Arg::Word(choice.clone())),
+                    None => Err(format!("{name}: expected one of {}, got {word}", choices.join(", "))),
+                }
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Arg {
+    Int(i32),
+    Float(f32),
+    /// A word, or the choice it matched as spelled in the list.
+    Word(String),
+}
+
+/// Arguments of a command, checked against its parameters and in their order.
+///
+/// The getters panic when the argument is missing or of another kind, which
+/// the parameters rule out for the ones that are not optional.
+#[derive(Debug, Clone, Default)]
+pub struct Args(Vec<Arg>);
+
+impl Args {
+    /// Whether argument `i` was given; optional ones may not be.
+    pub fn given(&self, i: usize) -> bool {
+        i < self.0.len()
+    }
+
+    pub fn int(&self, i: usize) -> i32 {
+        match &self.0[i] {
+            Arg::Int(value) => *value,
+            arg => panic!("argument {i} is not an int: {arg:?}"),
+        }
+    }
+
+    pub fn float(&self, i: usize) -> f32 {
+        match &self.0[i] {
+            Arg::Float(value) => *value,
+            arg => panic!("argument {i} is not a float: {arg:?}"),
+        }
+    }
+
+    pub fn word(&self, i: usize) -> &str {
+        match &self.0[i] {
+            Arg::Word(value) => value,
+            arg => panic!("argument {i} is not a word: {arg:?}"),
+        }
+    }
+}
+
+/// What a command printed, or why it failed.
+pub type CommandResult = Result<String, String>;
+
+pub struct ConsoleCommand {
+    pub name: &'static str,
+    /// One line, shown by `help`.
+    pub help: &'static str,
+    pub params: Vec<Param>,
+    /// Runs with arguments that match `params`.
+    pub run: fn(&mut World, &Args) -> CommandResult,
+}
+
+impl ConsoleCommand {
+    /// The name followed by `<required>` and `[optional]` parameters.
+    pub fn usage(&self) -> String {
+        self.params.iter().fold(self.name.to_string(), |usage, param| {
+            if param.optional { format!("{usage} [{}]", param.name) } else { format!("{usage} <{}>", param.name) }
+        })
+    }
+
+    fn parse(&self, world: &World, words: &[&str]) -> Result<Args, String> {
+        let required = self.params.iter().filter(|param| !param.optional).count();
+        if words.len() < required || words.len() > self.params.len() {
+            return Err(format!("usage: {}", self.usage()));
+        }
+        let args = self.params.iter().zip(words).map(|(param, word)| param.parse(world, word)).collect::<Result<_, _>>()?;
+        Ok(Args(args))
+    }
+}
+
+/// Every command the console knows, sorted by name. Systems may add more at
+/// any time; plugins use [`AddConsoleCommand`].
+#[derive(Resource, Default)]
+pub struct ConsoleCommands(Vec<ConsoleCommand>);
+
+impl ConsoleCommands {
+    /// Adds `command`, replacing one with the same name.
+    pub fn add(&mut self, command: ConsoleCommand) {
+        match self.0.binary_search_by(|other| other.name.cmp(command.name)) {
+            Ok(i) => self.0[i] = command,
+            Err(i) => self.0.insert(i, command),
+        }
+    }
+
+    pub fn get(&self, name: &str) -> Option<&ConsoleCommand> {
+        self.0.iter().find(|command| command.name == name)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &ConsoleCommand> {
+        self.0.iter()
+    }
+}
+
+pub trait AddConsoleCommand {
+    fn add_console_command(&mut self, command: ConsoleCommand) -> &mut Self;
+}
+
+impl AddConsoleCommand for App {
+    fn add_console_command(&mut self, command: ConsoleCommand) -> &mut Self {
+        self.world_mut().get_resource_or_init::<ConsoleCommands>().add(command);
+        self
+    }
+}
+
+/// Text of the console, kept between worlds.
+#[derive(Resource, Default)]
+pub struct Console {
+    /// Printed lines, oldest first.
+    pub output: Vec<String>,
+    input: String,
+    history: Vec<String>,
+    /// Entry of `history` in the input line while going through it.
+    browsing: Option<usize>,
+    /// Lines waiting to run, first to last.
+    queue: Vec<String>,
+    /// Whether Tab was pressed since the last completion.
+    complete: bool,
+}
+
+impl Console {
+    pub fn print(&mut self, text: &str) {
+        self.output.extend(text.lines().map(String::from));
+        let extra = self.output.len().saturating_sub(MAX_OUTPUT);
+        self.output.drain(..extra);
+    }
+
+    /// Queues `line` to run as if typed.
+    pub fn run(&mut self, line: impl Into<String>) {
+        self.queue.push(line.into());
+    }
+
+    /// Puts an older (`older`) or newer line of the history in the input.
+    /// Going past the newest one empties it.
+    fn browse(&mut self, older: bool) {
+        let next = match (self.browsing, older) {
+            (None, true) if !self.history.is_empty() => Some(self.history.len() - 1),
+            (None, _) => return,
+            (Some(i), true) => Some(i.saturating_sub(1)),
+            (Some(i), false) => Some(i + 1).filter(|&i| i < self.history.len()),
+        };
+        self.browsing = next;
+        self.input = next.map_or_else(String::new, |i| self.history[i].clone());
+    }
+}
+
+/// Script given on the command line, run every time a world is entered.
+#[derive(Resource, Debug, Clone)]
+pub struct ConsoleScript(pub PathBuf);
+
+/// `name` as a single console word.
+pub fn command_word(name: &str) -> String {
+    name.replace(' ', "_")
+}
+
+fn command_names(world: &World) -> Vec<String> {
+    world.resource::<ConsoleCommands>().iter().map(|command| command.name.to_string()).collect()
+}
+
+/// Non-empty lines of a script, without `#` comments.
+fn read_script(path: &Path) -> Result<Vec<String>, String> {
+    let text = fs::read_to_string(path).map_err(|err| format!("{}: {err}", path.display()))?;
+    Ok(text.lines().map(str::trim).filter(|line| !line.is_empty() && !line.starts_with('#')).map(String::from).collect())
+}
+
+fn run_help(world: &mut World, args: &Args) -> CommandResult {
+    let commands = world.resource::<ConsoleCommands>();
+    if args.given(0) {
+        let command = commands.get(args.word(0)).expect("checked against the command names");
+        return Ok(format!("{}\n  {}", command.usage(), command.help));
+    }
+    Ok(commands.iter().map(|command| format!("{} - {}", command.usage(), command.help)).collect::<Vec<_>>().join("\n"))
+}
+
+fn run_cls(world: &mut World, _: &Args) -> CommandResult {
+    world.resource_mut::<Console>().output.clear();
+    Ok(String::new())
+}
+
+fn run_exec(world: &mut World, args: &Args) -> CommandResult {
+    let lines = read_script(Path::new(args.word(0)))?;
+    let count = lines.len();
+    world.resource_mut::<Console>().queue.extend(lines);
+    Ok(format!("running {count} lines from {}", args.word(0)))
+}
+
+fn run_startup_script(script: Option<Res<ConsoleScript>>, mut console: ResMut<Console>) {
+    let Some(script) = script else {
+        return;
+    };
+    match read_script(&script.0) {
+        Ok(lines) => console.queue.extend(lines),
+        Err(err) => {
+            warn!("startup script: {err}");
+            console.print(&format!("error: {err}"));
+        }
+    }
+}
+
+fn toggle_console(
+    keyboard_input: Res<ButtonInput<KeyCode>>,
+    settings: Res<Settings>,
+    pause: Res<State<Pause>>,
+    mut next_pause: ResMut<NextState<Pause>>,
+) {
+    if !keyboard_input.just_pressed(settings.keys.console) {
+        return;
+    }
+    match pause.get() {
+        Pause::Running => next_pause.set(Pause::Console),
+        Pause::Console => next_pause.set(Pause::Running),
+        Pause::Paused | Pause::Map => {}
+    }
+}
+
+fn type_in_console(mut keys: EventReader<KeyboardInput>, settings: Res<Settings>, mut console: ResMut<Console>) {
+    for key in keys.read() {
+        // The key opening the console is still in the queue on the first frame.
+        if key.state != ButtonState::Pressed || key.key_code == settings.keys.console {
+            continue;
+        }
+        match &key.logical_key {
+            Key::Enter => {
+                let line = std::mem::take(&mut console.input);
+                console.browsing = None;
+                if line.trim().is_empty() {
+                    continue;
+                }
+                if console.history.last() != Some(&line) {
+                    console.history.push(line.clone());
+                }
+                let extra = console.history.len().saturating_sub(MAX_HISTORY);
+                console.history.drain(..extra);
+                console.run(line);
+            }
+            Key::Backspace => {
+                console.input.pop();
+            }
+            Key::Tab => console.complete = true,
+            Key::ArrowUp => console.browse(true),
+            Key::ArrowDown => console.browse(false),
+            _ => {
+                if let Some(typed) = &key.text {
+                    console.input.extend(typed.chars().filter(|c| !c.is_control()));
+                }
+            }
+        }
+    }
+}
+
+/// Completes the input line, then runs one queued line. One line a frame
+/// lets each see the world the one before left, new screens included.
+fn run_console(world: &mut World) {
+    if std::mem::take(&mut world.resource_mut::<Console>().complete) {
+        complete_input(world);
+    }
+    let mut console = world.resource_mut::<Console>();
+    if console.queue.is_empty() {
+        return;
+    }
+    let line = console.queue.remove(0);
+    console.print(&format!("> {line}"));
+
+    let output = run_line(world, &line);
+    let mut console = world.resource_mut::<Console>();
+    match output {
+        Ok(text) => console.print(&text),
+        Err(err) => {
+            warn!("console: {line}: {err}");
+            console.print(&format!("error: {err}"));
+        }
+    }
+}
+
+fn run_line(world: &mut World, line: &str) -> CommandResult {
+    let words: Vec<&str> = line.split_whitespace().collect();
+    let Some((name, words)) = words.split_first() else {
+        return Ok(String::new());
+    };
+    let Some(command) = world.resource::<ConsoleCommands>().get(name) else {
+        return Err(format!("no command {name}, see help"));
+    };
+    let args = command.parse(world, words)?;
+    let run = command.run;
+    run(world, &args)
+}
+
+/// Completes the last word of the input with a command name or a value of
+/// the parameter being typed, as far as the candidates agree, and lists the
+/// candidates when there are several.
+fn complete_input(world: &mut World) {
+    let input = world.resource::<Console>().input.clone();
+    let mut words: Vec<&str> = input.split_whitespace().collect();
+    if input.is_empty() || input.ends_with(char::is_whitespace) {
+        words.push("");
+    }
+    let Some((last, before)) = words.split_last() else {
+        return;
+    };
+    let candidates = match before.split_first() {
+        None => command_names(world),
+        Some((name, args)) => {
+            let param = world.resource::<ConsoleCommands>().get(name).and_then(|command| command.params.get(args.len()));
+            match param.map(|param| param.kind) {
+                Some(ParamKind::Choice(choices)) => choices(world),
+                _ => Vec::new(),
+            }
+        }
+    };
+    let prefix = last.to_lowercase();
+    let matching: Vec<&String> = candidates.iter().filter(|candidate| candidate.to_lowercase().starts_with(&prefix)).collect();
+    let Some(first) = matching.first() else {
+        return;
+    };
+    let common = matching.iter().map(|candidate| common_prefix(first, candidate)).min().unwrap_or(first.len());
+    let mut completed = first[..common].to_string();
+    if matching.len() == 1 {
+        completed.push(' ');
+    }
+
+    let mut console = world.resource_mut::<Console>();
+    console.input.truncate(input.len() - last.len());
+    console.input += &completed;
+    console.browsing = None;
+    if matching.len() > 1 {
+        console.print(&matching.iter().map(|candidate| candidate.as_str()).collect::<Vec<_>>().join("  "));
+    }
+}
+
+/// Length in bytes of the start `a` and `b` share.
+fn common_prefix(a: &str, b: &str) -> usize {
+    a.char_indices().zip(b.chars()).find(|((_, x), y)| x != y).map_or(a.len().min(b.len()), |((i, _), _)| i)
+}
+
+#[derive(Component)]
+struct ConsoleBackground;
+
+#[derive(Component)]
+struct ConsoleText {
+    /// Whether this is the prompt rather than the output above it.
+    prompt: bool,
+}
+
+fn spawn_console(mut commands: Commands) {
+    let mut sprite = Sprite::from_color(BACKGROUND, Vec2::ONE);
+    sprite.anchor = Anchor::TopLeft;
+    commands.spawn((ConsoleBackground, sprite, Transform::default(), HIGH_RES_LAYERS, StateScoped(Pause::Console)));
+    for prompt in [false, true] {
+        commands.spawn((
+            ConsoleText { prompt },
+            Text2d::default(),
+            TextFont { font_size: FONT_SIZE, ..default() },
+            TextColor(Color::WHITE),
+            Anchor::BottomLeft,
+            Transform::default(),
+            HIGH_RES_LAYERS,
+            StateScoped(Pause::Console),
+        ));
+    }
+}
+
+/// Lays the console over the top of the canvas, prompt at the bottom.
+fn show_console(
+    canvas_tf: Single<&Transform, (With<PixelatedCanvas>, Without<ConsoleBackground>, Without<ConsoleText>)>,
+    canvas: Res<Canvas>,
+    console: Res<Console>,
+    mut background: Single<(&mut Transform, &mut Sprite), With<ConsoleBackground>>,
+    mut texts: Query<(&ConsoleText, &mut Transform, &mut Text2d, &mut TextFont), Without<ConsoleBackground>>,
+) {
+    let rect = CanvasRect::new(&canvas_tf, &canvas);
+    let height = (VISIBLE_LINES + 1) as f32 * LINE_HEIGHT + 2.0 * MARGIN;
+    let (transform, sprite) = &mut *background;
+    rect.place(transform, sprite, Vec2::ZERO, Vec2::new(rect.size.x, height), CONSOLE_Z);
+
+    for (ConsoleText { prompt }, mut transform, mut text, mut font) in &mut texts {
+        let bottom = if *prompt { height - MARGIN } else { height - MARGIN - LINE_HEIGHT };
+        transform.translation = rect.point(Vec2::new(MARGIN, bottom)).extend(CONSOLE_Z + 0.1);
+        scale_font(&mut font, &rect);
+        let label = if *prompt {
+            format!("> {}_", console.input)
+        } else {
+            let shown = console.output.len().saturating_sub(VISIBLE_LINES);
+            console.output[shown..].join("\n")
+        };
+        if text.0 != label {
+            text.0 = label;
+        }
+    }
+}
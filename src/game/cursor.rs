@@ -0,0 +1,92 @@
+//! Where the mouse points, as a canvas pixel, a world position and a tile.
+//!
+//! Picking goes window → outer camera → canvas sprite → canvas pixel →
+//! world, undoing whatever scale the [`Scaling`](super::settings::Scaling)
+//! mode gave the canvas on each axis and the offset of the
+//! [`InGameCamera`].
+
+use bevy::{prelude::*, window::PrimaryWindow};
+use serde::{Deserialize, Serialize};
+
+use crate::game::{raycast::cell_of, Canvas, InGameCamera, PixelatedCanvas};
+
+pub struct CursorPlugin;
+
+impl Plugin for CursorPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<CursorWorld>();
+        // Before anything in `Update` reads it, against the camera of the
+        // frame on screen.
+        app.add_systems(PreUpdate, update_cursor_world);
+    }
+}
+
+/// One point under the cursor in each of the spaces the game uses.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct CursorPoint {
+    /// Visible canvas pixel, top-left origin, y down.
+    pub canvas: Vec2,
+    /// World position, as sprites are placed.
+    pub world: Vec2,
+    /// Cell of the loaded screen, as returned by [`cell_of`].
+    pub tile: IVec2,
+}
+
+/// The point under the cursor, or `None` when the cursor is outside the
+/// window or over the bars around the canvas.
+#[derive(Resource, Debug, Default, Clone, Copy, PartialEq)]
+pub struct CursorWorld(pub Option<CursorPoint>);
+
+impl CursorWorld {
+    pub fn canvas(&self) -> Option<Vec2> {
+        self.0.map(|point| point.canvas)
+    }
+
+    pub fn world(&self) -> Option<Vec2> {
+        self.0.map(|point| point.world)
+    }
+
+    pub fn tile(&self) -> Option<IVec2> {
+        self.0.map(|point| point.tile)
+    }
+}
+
+/// Visible canvas pixel under `cursor`, a window position in logical pixels
+/// (top-left origin, y down), if it is on the canvas.
+///
+/// `canvas_tf` is the transform of the canvas sprite, seen by an outer
+/// camera at the origin of a `window_size` window.
+pub fn window_to_canvas(cursor: Vec2, window_size: Vec2, canvas_tf: &Transform, canvas_size: Vec2) -> Option<Vec2> {
+    // Window -> outer camera, centre origin and y up.
+    let outer = Vec2::new(cursor.x - window_size.x * 0.5, window_size.y * 0.5 - cursor.y);
+    // Outer camera -> canvas sprite, one unit per canvas pixel.
+    let scale = canvas_tf.scale.truncate();
+    if scale.x <= 0.0 || scale.y <= 0.0 {
+        return None;
+    }
+    let local = (outer - canvas_tf.translation.truncate()) / scale;
+    // Sprite -> canvas pixel, top-left origin and y down.
+    let canvas_px = Vec2::new(local.x + canvas_size.x * 0.5, canvas_size.y * 0.5 - local.y);
+    let inside = canvas_px.cmpge(Vec2::ZERO).all() && canvas_px.cmplt(canvas_size).all();
+    inside.then_some(canvas_px)
+}
+
+/// Everything under `cursor`, or `None` when it is off the canvas.
+pub fn pick(cursor: Vec2, window_size: Vec2, canvas_tf: &Transform, canvas: &Canvas, camera: &Transform) -> Option<CursorPoint> {
+    let canvas_px = window_to_canvas(cursor, window_size, canvas_tf, canvas.size_f32())?;
+    let world = canvas.to_world(canvas_px, camera);
+    Some(CursorPoint { canvas: canvas_px, world, tile: cell_of(world) })
+}
+
+fn update_cursor_world(
+    window: Single<&Window, With<PrimaryWindow>>,
+    canvas: Res<Canvas>,
+    canvas_tf: Single<&Transform, With<PixelatedCanvas>>,
+    camera: Single<&Transform, With<InGameCamera>>,
+    mut cursor: ResMut<CursorWorld>,
+) {
+    let point = window.cursor_position().and_then(|position| pick(position, window.size(), &canvas_tf, &canvas, &camera));
+    if cursor.0 != point {
+        cursor.0 = point;
+    }
+}
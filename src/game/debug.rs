@@ -0,0 +1,29 @@
+use bevy::prelude::*;
+
+use crate::game::{clock::WorldClock, state::GameplaySet};
+
+/// Developer shortcuts that poke at world state directly.
+pub struct DebugPlugin;
+
+impl Plugin for DebugPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(Update, debug_clock.in_set(GameplaySet));
+    }
+}
+
+/// F1 pauses the clock, F2 skips an hour, F3 jumps to noon and F4 to midnight.
+fn debug_clock(keyboard_input: Res<ButtonInput<KeyCode>>, mut clock: ResMut<WorldClock>) {
+    if keyboard_input.just_pressed(KeyCode::F1) {
+        clock.paused = !clock.paused;
+        info!("clock {}", if clock.paused { "paused" } else { "running" });
+    }
+    if keyboard_input.just_pressed(KeyCode::F2) {
+        clock.advance_hours(1.0);
+    }
+    if keyboard_input.just_pressed(KeyCode::F3) {
+        clock.set_hour(12.0);
+    }
+    if keyboard_input.just_pressed(KeyCode::F4) {
+        clock.set_hour(0.0);
+    }
+}
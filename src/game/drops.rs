@@ -0,0 +1,137 @@
+use bevy::{prelude::*, sprite::Anchor};
+
+use crate::game::{
+    item::{Inventory, ItemKind, ItemStack},
+    player::Player,
+    state::{GameState, GameplaySet},
+    tilemap::{TileMap, TILE_SIZE},
+    worldgen::WorldGen,
+    PIXEL_PERFECT_LAYERS,
+};
+
+const DROP_SIZE: f32 = 4.0;
+const DROP_GRAVITY: f32 = 600.0;
+const MAX_DROP_SPEED: f32 = 300.0;
+/// Upward speed a new drop pops out with, in pixels per second.
+const POP_SPEED: f32 = 80.0;
+/// Fraction of horizontal speed kept per second on the ground.
+const GROUND_FRICTION: f32 = 0.02;
+const DROP_Z: f32 = 0.15;
+
+pub struct DropsPlugin;
+
+impl Plugin for DropsPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(Update, (move_drops, pick_up_drops).chain().in_set(GameplaySet));
+    }
+}
+
+/// An item lying in the world, waiting to be picked up.
+///
+/// Like [`FallingTile`](crate::game::falling::FallingTile) it lives in global
+/// pixels, so drops outside the loaded screen wait there until the player
+/// comes back.
+#[derive(Component)]
+pub struct DroppedItem {
+    pub stack: ItemStack,
+    /// Global pixel position of the top-left corner, y growing downwards.
+    pub pos: Vec2,
+    /// Pixels per second, y growing downwards.
+    pub velocity: Vec2,
+}
+
+/// Drops `count` items of `kind` from the global tile `g`.
+pub fn drop_item(commands: &mut Commands, g: IVec2, kind: ItemKind, count: u32) {
+    let tile = TILE_SIZE as f32;
+    let pos = g.as_vec2() * tile + Vec2::splat((tile - DROP_SIZE) * 0.5);
+    // Spread drops from the same cell a little so they don't stack up exactly.
+    let spread = ((g.x * 31 + g.y * 17).rem_euclid(7) - 3) as f32 * 10.0;
+    let mut sprite = Sprite::from_color(kind.def().colour, Vec2::splat(DROP_SIZE));
+    sprite.anchor = Anchor::TopLeft;
+    commands.spawn((
+        DroppedItem {
+            stack: ItemStack { kind, count },
+            pos,
+            velocity: Vec2::new(spread, -POP_SPEED),
+        },
+        sprite,
+        Transform::default(),
+        PIXEL_PERFECT_LAYERS,
+        StateScoped(GameState::InGame),
+    ));
+}
+
+fn move_drops(
+    tilemap: Res<TileMap>,
+    worldgen: Res<WorldGen>,
+    mut drops: Query<(&mut DroppedItem, &mut Transform, &mut Visibility)>,
+    time: Res<Time>,
+) {
+    let tile = TILE_SIZE as f32;
+    let dt = time.delta_secs();
+    let origin_px = tilemap.origin().as_vec2() * tile;
+    let cell = |p: Vec2| IVec2::new((p.x / tile).floor() as i32, (p.y / tile).floor() as i32);
+
+    for (mut drop, mut transform, mut visibility) in &mut drops {
+        // Only drops on the loaded screen move.
+        let loaded = tilemap.to_local(cell(drop.pos)).is_some();
+        *visibility = if loaded { Visibility::Inherited } else { Visibility::Hidden };
+        if !loaded {
+            continue;
+        }
+
+        drop.velocity.y = (drop.velocity.y + DROP_GRAVITY * dt).min(MAX_DROP_SPEED);
+        let next = drop.pos + drop.velocity * dt;
+
+        let next_x = Vec2::new(next.x, drop.pos.y);
+        let side = if drop.velocity.x > 0. { next_x.x + DROP_SIZE } else { next_x.x };
+        if tilemap.solid_global(&worldgen, cell(Vec2::new(side, next_x.y + DROP_SIZE * 0.5))) {
+            drop.velocity.x = 0.;
+        } else {
+            drop.pos.x = next.x;
+        }
+
+        let edge = if drop.velocity.y > 0. { next.y + DROP_SIZE } else { next.y };
+        if tilemap.solid_global(&worldgen, cell(Vec2::new(drop.pos.x + DROP_SIZE * 0.5, edge))) {
+            if drop.velocity.y > 0. {
+                // Rest on top of the tile.
+                drop.pos.y = (edge / tile).floor() * tile - DROP_SIZE;
+                drop.velocity.x *= GROUND_FRICTION.powf(dt);
+            }
+            drop.velocity.y = 0.;
+        } else {
+            drop.pos.y = next.y;
+        }
+
+        transform.translation = Vec3::new(drop.pos.x - origin_px.x, origin_px.y - drop.pos.y, DROP_Z);
+    }
+}
+
+/// Moves drops the player touches into their inventory.
+fn pick_up_drops(
+    mut commands: Commands,
+    tilemap: Res<TileMap>,
+    mut player: Query<(&Transform, &mut Inventory), With<Player>>,
+    mut drops: Query<(Entity, &mut DroppedItem)>,
+) {
+    let Ok((player_tf, mut inventory)) = player.single_mut() else {
+        return;
+    };
+    let origin_px = tilemap.origin().as_vec2() * TILE_SIZE as f32;
+    let min = Vec2::new(origin_px.x + player_tf.translation.x, origin_px.y - player_tf.translation.y);
+    let max = min + Vec2::splat(8.);
+
+    for (entity, mut drop) in &mut drops {
+        let overlaps = drop.pos.x < max.x && drop.pos.x + DROP_SIZE > min.x
+            && drop.pos.y < max.y && drop.pos.y + DROP_SIZE > min.y;
+        if !overlaps {
+            continue;
+        }
+        let left = inventory.add(drop.stack.kind, drop.stack.count);
+        if left == 0 {
+            commands.entity(entity).despawn();
+        } else {
+            drop.stack.count = left;
+        }
+    }
+}
@@ -0,0 +1,485 @@
+use std::fs;
+
+use bevy::{prelude::*, sprite::Anchor};
+use rand::{rngs::StdRng, Rng, SeedableRng};
+use serde::Deserialize;
+
+use crate::game::{
+    clock::WorldClock,
+    console::{command_word, AddConsoleCommand, Args, CommandResult, ConsoleCommand, Param, ParamKind},
+    explosion::{Explosion, ExplosionDef},
+    health::{DamageEvent, Died, Health, Hitbox, Knockback},
+    lighting::{update_light_map, LightMap},
+    pathfinding::{AgentParams, NavAction, Pathfinder},
+    player::Player,
+    state::{GameState, GameplaySet},
+    tilemap::{ScreenChanged, TileLayer, TileMap, COLS, ROWS, TCOLS, TILE_SIZE, TROWS},
+    worldgen::WorldGen,
+    AssetDir, PIXEL_PERFECT_LAYERS,
+};
+
+/// Enemy types, in the [`AssetDir`].
+const ENEMY_FILE: &str = "enemies.ron";
+/// Most enemies placed on a freshly generated screen.
+const MAX_PER_SCREEN: usize = 4;
+/// Random cells tried per enemy before giving up.
+const SPAWN_ATTEMPTS: usize = 30;
+/// Enemies never spawn closer than this to the player, in pixels.
+const SAFE_RADIUS: f32 = 48.0;
+const ENEMY_GRAVITY: f32 = -1000.0;
+const HOP_SPEED: f32 = 220.0;
+const HOP_COOLDOWN: f32 = 1.2;
+/// Longest drop a hopping enemy takes to follow a route, in tiles.
+const HOP_MAX_FALL: i32 = 6;
+const ENEMY_Z: f32 = 0.2;
+/// Push given to the player when an enemy touches them, in pixels per second.
+const CONTACT_KNOCKBACK: f32 = 150.0;
+/// Most enemies one `spawn` command places.
+const MAX_SPAWNED: i32 = 20;
+/// Space between the player and the first enemy it spawns, in pixels.
+const SPAWN_GAP: f32 = 8.0;
+
+pub struct EnemyPlugin;
+
+impl Plugin for EnemyPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(Startup, load_enemy_defs);
+        app.add_systems(Update, (update_enemies, enemy_contact_damage, despawn_dead_enemies).chain().in_set(GameplaySet));
+        app.add_systems(PostUpdate, spawn_enemies.after(update_light_map).in_set(GameplaySet));
+        app.add_console_command(ConsoleCommand {
+            name: "spawn",
+            help: "places enemies in a row in front of the player",
+            params: vec![Param::new("enemy", ParamKind::Choice(enemy_names)), Param::optional("count", ParamKind::Int)],
+            run: run_spawn,
+        });
+    }
+}
+
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Behaviour {
+    /// Walks back and forth, turning at walls and ledges.
+    Patrol,
+    /// Waits on the ground and hops toward the player.
+    Hop,
+    /// Flies straight at the player.
+    Fly,
+}
+
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SpawnTime {
+    Any,
+    Day,
+    Night,
+}
+
+/// One enemy type, as read from `assets/enemies.ron`.
+#[derive(Deserialize, Debug, Clone)]
+pub struct EnemyDef {
+    pub name: String,
+    /// Optional image; enemies without one are drawn as a flat `colour` box.
+    #[serde(default)]
+    pub sprite: Option<String>,
+    pub colour: (f32, f32, f32, f32),
+    /// Hitbox in pixels.
+    pub size: (u32, u32),
+    pub health: u32,
+    pub damage: u32,
+    pub speed: f32,
+    pub behaviour: Behaviour,
+    /// Shallowest and deepest screen row it spawns on.
+    pub min_depth: i32,
+    pub max_depth: i32,
+    pub max_light: u8,
+    pub time: SpawnTime,
+    /// Relative spawn chance among the types allowed on a screen.
+    pub weight: u32,
+    /// Blast set off where it dies, if it explodes.
+    #[serde(default)]
+    pub explosion: Option<ExplosionDef>,
+}
+
+#[derive(Resource, Default)]
+pub struct EnemyDefs(pub Vec<EnemyDef>);
+
+#[derive(Component)]
+pub struct Enemy {
+    /// Index into [`EnemyDefs`].
+    pub def: usize,
+    pub size: Vec2,
+    pub velocity: Vec2,
+    pub remainder: Vec2,
+    pub on_ground: bool,
+    /// -1 or 1.
+    pub facing: f32,
+    pub timer: f32,
+}
+
+fn load_enemy_defs(mut commands: Commands, assets: Res<AssetDir>) {
+    let path = assets.0.join(ENEMY_FILE);
+    let defs = fs::read_to_string(&path)
+        .map_err(|e| e.to_string())
+        .and_then(|text| ron::from_str::<Vec<EnemyDef>>(&text).map_err(|e| e.to_string()));
+    match defs {
+        Ok(defs) => commands.insert_resource(EnemyDefs(defs)),
+        Err(err) => {
+            warn!("no enemies: {}: {err}", path.display());
+            commands.insert_resource(EnemyDefs::default());
+        }
+    }
+}
+
+/// Whether a `size` box with its top-left corner at `pos` overlaps a solid tile
+/// or leaves the loaded screen.
+pub fn box_collides(tilemap: &TileMap, pos: Vec2, size: Vec2) -> bool {
+    let right = pos.x + size.x;
+    let bottom = pos.y - size.y;
+    if pos.x < 0. || right > (TCOLS as u32 * TILE_SIZE) as f32 || pos.y > 0. || bottom < -((TROWS as u32 * TILE_SIZE) as f32) {
+        return true;
+    }
+    let xs = [pos.x + 0.5, right - 0.5];
+    let ys = [pos.y - 0.5, bottom + 0.5];
+    xs.iter().any(|&x| ys.iter().any(|&y| tilemap.collide_at(Vec2::new(x, y))))
+}
+
+/// What a body bumped into while moving.
+#[derive(Default)]
+pub struct Bumps {
+    pub wall: bool,
+    pub floor: bool,
+    pub ceiling: bool,
+}
+
+/// Moves a box pixel by pixel, stopping each axis at the first solid tile.
+pub fn move_box(tilemap: &TileMap, pos: &mut Vec2, size: Vec2, velocity: &mut Vec2, remainder: &mut Vec2, dt: f32) -> Bumps {
+    let mut bumps = Bumps::default();
+    *remainder += *velocity * dt;
+    let mov = remainder.round();
+    *remainder -= mov;
+
+    let step = mov.x.signum();
+    for _ in 0..mov.x.abs() as i32 {
+        let next = *pos + Vec2::new(step, 0.);
+        if box_collides(tilemap, next, size) {
+            bumps.wall = true;
+            velocity.x = 0.;
+            remainder.x = 0.;
+            break;
+        }
+        *pos = next;
+    }
+
+    let step = mov.y.signum();
+    for _ in 0..mov.y.abs() as i32 {
+        let next = *pos + Vec2::new(0., step);
+        if box_collides(tilemap, next, size) {
+            if step < 0. { bumps.floor = true; } else { bumps.ceiling = true; }
+            velocity.y = 0.;
+            remainder.y = 0.;
+            break;
+        }
+        *pos = next;
+    }
+
+    if !bumps.floor && velocity.y <= 0. {
+        bumps.floor = box_collides(tilemap, *pos + Vec2::new(0., -1.), size);
+    }
+    bumps
+}
+
+/// What a hopping enemy of type `def` can reach.
+fn hop_params(def: &EnemyDef, size: Vec2) -> AgentParams {
+    let height = (size.y / TILE_SIZE as f32).ceil() as i32;
+    AgentParams::from_physics(height, def.speed, HOP_SPEED, ENEMY_GRAVITY, HOP_MAX_FALL)
+}
+
+/// Direction and take-off speed of the first move on a route from the enemy
+/// with its top-left corner at `pos` to the player centred on `target`; `None`
+/// when there is no route or it is already there.
+fn hop_along_route(pathfinder: &mut Pathfinder, tilemap: &TileMap, def: &EnemyDef, pos: Vec2, size: Vec2, target: Vec2) -> Option<(f32, f32)> {
+    let feet = tilemap.origin() + tilemap.cell_at(Vec2::new(pos.x + size.x * 0.5, pos.y - size.y + 0.5));
+    let goal = tilemap.origin() + tilemap.cell_at(target + Vec2::new(0., -3.5));
+    let step = *pathfinder.find_path(&hop_params(def, size), feet, goal)?.first()?;
+    let dir = (step.to.x - feet.x).signum();
+    if dir == 0 {
+        return None;
+    }
+    // A low hop is enough to walk on or step off a ledge.
+    let lift = match step.action {
+        NavAction::Jump => HOP_SPEED,
+        NavAction::Walk | NavAction::Fall => HOP_SPEED * 0.5,
+    };
+    Some((dir as f32, lift))
+}
+
+fn update_enemies(
+    tilemap: Res<TileMap>,
+    defs: Res<EnemyDefs>,
+    player: Query<&Transform, (With<Player>, Without<Enemy>)>,
+    mut enemies: Query<(&mut Enemy, &mut Transform, &mut Sprite, &mut Knockback)>,
+    mut pathfinder: Pathfinder,
+    time: Res<Time>,
+) {
+    let dt = time.delta_secs();
+    let target = player.single().ok().map(|tf| tf.translation.truncate() + Vec2::new(4., -4.));
+
+    for (mut enemy, mut transform, mut sprite, mut knockback) in &mut enemies {
+        let Some(def) = defs.0.get(enemy.def) else {
+            continue;
+        };
+        let mut pos = transform.translation.truncate();
+        let centre = pos + Vec2::new(enemy.size.x, -enemy.size.y) * 0.5;
+        let toward = target.map_or(enemy.facing, |t| (t.x - centre.x).signum());
+
+        if let Some(push) = knockback.take() {
+            enemy.velocity = push;
+            enemy.on_ground = false;
+        }
+
+        match def.behaviour {
+            // Thrown back: only gravity acts until it recovers.
+            _ if knockback.active() => {
+                if def.behaviour != Behaviour::Fly {
+                    enemy.velocity.y += ENEMY_GRAVITY * dt;
+                }
+            }
+            Behaviour::Patrol => {
+                enemy.velocity.x = enemy.facing * def.speed;
+                enemy.velocity.y += ENEMY_GRAVITY * dt;
+            }
+            Behaviour::Hop => {
+                enemy.velocity.y += ENEMY_GRAVITY * dt;
+                if enemy.on_ground {
+                    enemy.velocity.x = 0.;
+                    enemy.timer -= dt;
+                    if enemy.timer <= 0. {
+                        enemy.timer = HOP_COOLDOWN;
+                        // Straight at the player when there is no route, hoping for the best.
+                        let (dir, lift) = target
+                            .and_then(|t| hop_along_route(&mut pathfinder, &tilemap, def, pos, enemy.size, t))
+                            .unwrap_or((toward, HOP_SPEED));
+                        enemy.facing = dir;
+                        enemy.velocity = Vec2::new(dir * def.speed, lift);
+                    }
+                }
+            }
+            Behaviour::Fly => {
+                // Only chase what it can see.
+                let seen = target.filter(|&t| tilemap.line_of_sight(centre, t));
+                let dir = seen.map_or(Vec2::ZERO, |t| (t - centre).normalize_or_zero());
+                enemy.velocity = dir * def.speed;
+                if dir.x != 0. {
+                    enemy.facing = dir.x.signum();
+                }
+            }
+        }
+
+        let size = enemy.size;
+        let (mut velocity, mut remainder) = (enemy.velocity, enemy.remainder);
+        let bumps = move_box(&tilemap, &mut pos, size, &mut velocity, &mut remainder, dt);
+        enemy.velocity = velocity;
+        enemy.remainder = remainder;
+        enemy.on_ground = bumps.floor;
+
+        if def.behaviour == Behaviour::Patrol {
+            let front = if enemy.facing > 0. { pos.x + size.x + 0.5 } else { pos.x - 0.5 };
+            let ledge = enemy.on_ground && !tilemap.collide_at(Vec2::new(front, pos.y - size.y - 0.5));
+            if bumps.wall || ledge {
+                enemy.facing = -enemy.facing;
+            }
+        }
+
+        sprite.flip_x = enemy.facing < 0.;
+        transform.translation.x = pos.x;
+        transform.translation.y = pos.y;
+    }
+}
+
+fn enemy_contact_damage(
+    defs: Res<EnemyDefs>,
+    player: Query<(Entity, &Transform), With<Player>>,
+    enemies: Query<(&Enemy, &Transform)>,
+    mut damage: EventWriter<DamageEvent>,
+) {
+    let Ok((player, player_tf)) = player.single() else {
+        return;
+    };
+    let player_min = Vec2::new(player_tf.translation.x, player_tf.translation.y - 8.);
+    let player_max = player_min + Vec2::splat(8.);
+    let player_centre = (player_min + player_max) * 0.5;
+    for (enemy, transform) in &enemies {
+        let min = Vec2::new(transform.translation.x, transform.translation.y - enemy.size.y);
+        let max = min + enemy.size;
+        let overlaps = min.x < player_max.x && max.x > player_min.x && min.y < player_max.y && max.y > player_min.y;
+        if overlaps && let Some(def) = defs.0.get(enemy.def) {
+            damage.write(DamageEvent {
+                target: player,
+                amount: def.damage,
+                knockback: Vec2::new((player_centre.x - (min.x + max.x) * 0.5).signum() * CONTACT_KNOCKBACK, CONTACT_KNOCKBACK),
+            });
+        }
+    }
+}
+
+fn despawn_dead_enemies(
+    mut commands: Commands,
+    mut died: EventReader<Died>,
+    defs: Res<EnemyDefs>,
+    enemies: Query<(&Enemy, &Transform)>,
+    mut explosions: EventWriter<Explosion>,
+) {
+    for Died(entity) in died.read() {
+        let Ok((enemy, transform)) = enemies.get(*entity) else {
+            continue;
+        };
+        if let Some(def) = defs.0.get(enemy.def).and_then(|d| d.explosion) {
+            let centre = transform.translation.truncate() + Vec2::new(enemy.size.x, -enemy.size.y) * 0.5;
+            explosions.write(Explosion { centre, def });
+        }
+        commands.entity(*entity).despawn();
+    }
+}
+
+/// Whether `def` may spawn in a cell with light `light` on screen row `depth` at this time.
+fn allowed(def: &EnemyDef, depth: i32, light: u8, clock: &WorldClock) -> bool {
+    let time_ok = match def.time {
+        SpawnTime::Any => true,
+        SpawnTime::Day => !clock.is_night(),
+        SpawnTime::Night => clock.is_night(),
+    };
+    time_ok && (def.min_depth..=def.max_depth).contains(&depth) && light <= def.max_light
+}
+
+/// An enemy of type `index` with its top-left corner at `pos`.
+fn enemy_bundle(asset_server: &AssetServer, index: usize, def: &EnemyDef, pos: Vec2, facing: f32, timer: f32) -> impl Bundle {
+    let size = Vec2::new(def.size.0 as f32, def.size.1 as f32);
+    let colour = Color::linear_rgba(def.colour.0, def.colour.1, def.colour.2, def.colour.3);
+    let mut sprite = match &def.sprite {
+        Some(path) => {
+            let mut sprite = Sprite::from_image(asset_server.load(path));
+            sprite.custom_size = Some(size);
+            sprite
+        }
+        None => Sprite::from_color(colour, size),
+    };
+    sprite.anchor = Anchor::TopLeft;
+
+    (
+        Enemy { def: index, size, velocity: Vec2::ZERO, remainder: Vec2::ZERO, on_ground: false, facing, timer },
+        Name::new(def.name.clone()),
+        Health::new(def.health),
+        Hitbox { size },
+        Knockback::default(),
+        sprite,
+        Transform::from_xyz(pos.x, pos.y, ENEMY_Z),
+        PIXEL_PERFECT_LAYERS,
+        StateScoped(GameState::InGame),
+    )
+}
+
+/// Replaces the enemies of the previous screen with a fresh set for the new one.
+///
+/// Spawns are seeded from the world seed and the screen, so the same screen
+/// under the same conditions always gets the same enemies.
+pub fn spawn_enemies(
+    mut commands: Commands,
+    mut screen_changed: EventReader<ScreenChanged>,
+    defs: Res<EnemyDefs>,
+    tilemap: Res<TileMap>,
+    light_map: Res<LightMap>,
+    worldgen: Res<WorldGen>,
+    clock: Res<WorldClock>,
+    asset_server: Res<AssetServer>,
+    player: Query<&Transform, With<Player>>,
+    enemies: Query<Entity, With<Enemy>>,
+) {
+    let Some(ScreenChanged { position }) = screen_changed.read().last().copied() else {
+        return;
+    };
+    for entity in &enemies {
+        commands.entity(entity).despawn();
+    }
+
+    let screen_hash = (position.x as u32 as u64) << 32 | position.y as u32 as u64;
+    let mut rng = StdRng::seed_from_u64((worldgen.seed as u64).rotate_left(17) ^ screen_hash);
+    let player_pos = player.single().map(|tf| tf.translation.truncate()).unwrap_or(Vec2::new(-1000., 1000.));
+    let tile = TILE_SIZE as f32;
+
+    for _ in 0..rng.gen_range(0..=MAX_PER_SCREEN) {
+        for _ in 0..SPAWN_ATTEMPTS {
+            let x = rng.gen_range(1..=COLS);
+            let y = rng.gen_range(1..=ROWS);
+            if tilemap.tile_at(TileLayer::Foreground, x, y).is_some() {
+                continue;
+            }
+            let light = light_map.level_at(x, y);
+            let candidates: Vec<usize> = (0..defs.0.len())
+                .filter(|&i| allowed(&defs.0[i], position.y, light, &clock))
+                .filter(|&i| defs.0[i].behaviour == Behaviour::Fly || tilemap.tile_at(TileLayer::Foreground, x, y + 1).is_some_and(|t| t.solid()))
+                .collect();
+            let total: u32 = candidates.iter().map(|&i| defs.0[i].weight).sum();
+            if total == 0 {
+                continue;
+            }
+            let mut roll = rng.gen_range(0..total);
+            let Some(&index) = candidates.iter().find(|&&i| {
+                let weight = defs.0[i].weight;
+                if roll < weight { true } else { roll -= weight; false }
+            }) else {
+                continue;
+            };
+
+            let def = &defs.0[index];
+            let size = Vec2::new(def.size.0 as f32, def.size.1 as f32);
+            // Stand on the bottom of the cell.
+            let pos = Vec2::new(x as f32 * tile, -(y as f32 + 1.) * tile + size.y);
+            if pos.distance(player_pos) < SAFE_RADIUS || box_collides(&tilemap, pos, size) {
+                continue;
+            }
+
+            let facing = if rng.gen_bool(0.5) { 1. } else { -1. };
+            let timer = rng.gen_range(0.0..HOP_COOLDOWN);
+            commands.spawn(enemy_bundle(&asset_server, index, def, pos, facing, timer));
+            break;
+        }
+    }
+}
+
+fn enemy_names(world: &World) -> Vec<String> {
+    world.resource::<EnemyDefs>().0.iter().map(|def| command_word(&def.name)).collect()
+}
+
+/// Lines enemies up on the player's feet in the direction they face, facing
+/// them, stopping at the first one that would not fit on the screen.
+fn run_spawn(world: &mut World, args: &Args) -> CommandResult {
+    let name = args.word(0);
+    let count = if args.given(1) { args.int(1) } else { 1 };
+    if !(1..=MAX_SPAWNED).contains(&count) {
+        return Err(format!("count: expected 1 to {MAX_SPAWNED}, got {count}"));
+    }
+    let defs = world.resource::<EnemyDefs>();
+    let index = defs.0.iter().position(|def| command_word(&def.name) == name).expect("checked against the enemy names");
+    let def = defs.0[index].clone();
+    let mut query = world.query::<(&Player, &Transform)>();
+    let (player, player_tf) = query.single(world).map_err(|_| "there is no player".to_string())?;
+    let (facing, feet) = (player.facing, player_tf.translation.truncate() + Vec2::new(0., -(TILE_SIZE as f32)));
+
+    let size = Vec2::new(def.size.0 as f32, def.size.1 as f32);
+    let tilemap = world.resource::<TileMap>();
+    let positions: Vec<Vec2> = (0..count)
+        .map(|i| {
+            let offset = SPAWN_GAP + i as f32 * (size.x + 1.);
+            let x = if facing > 0. { feet.x + TILE_SIZE as f32 + offset } else { feet.x - offset - size.x };
+            Vec2::new(x, feet.y + size.y)
+        })
+        .take_while(|pos| !box_collides(tilemap, *pos, size))
+        .collect();
+    if positions.is_empty() {
+        return Err(format!("no room for a {name} in front of the player"));
+    }
+
+    let asset_server = world.resource::<AssetServer>().clone();
+    for pos in &positions {
+        world.spawn(enemy_bundle(&asset_server, index, &def, *pos, -facing, 0.0));
+    }
+    Ok(format!("spawned {} {name}", positions.len()))
+}
@@ -0,0 +1,136 @@
+use bevy::{prelude::*, sprite::Anchor};
+use serde::Deserialize;
+
+use crate::game::{
+    drops::drop_item,
+    health::{DamageEvent, Health, Hitbox},
+    item::ItemKind,
+    state::{GameState, GameplaySet},
+    tilemap::{TileLayer, TileMap, TILE_SIZE},
+    worldgen::WorldGen,
+    PIXEL_PERFECT_LAYERS,
+};
+
+/// Seconds the flash of an explosion stays on screen.
+const FLASH_TIME: f32 = 0.15;
+const FLASH_Z: f32 = 0.4;
+
+pub struct ExplosionPlugin;
+
+impl Plugin for ExplosionPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_event::<Explosion>();
+        app.add_systems(Update, (explode, fade_flashes).chain().in_set(GameplaySet));
+    }
+}
+
+/// How big and strong an explosion is. Used by bombs and exploding enemies.
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq)]
+pub struct ExplosionDef {
+    /// Radius in tiles.
+    pub radius: f32,
+    /// Blast strength at the centre. It falls off linearly to zero at the
+    /// radius and breaks tiles whose blast resistance it reaches.
+    pub power: f32,
+    /// Damage at the centre, falling off like the power.
+    pub damage: u32,
+    /// Push at the centre, in pixels per second.
+    pub knockback: f32,
+}
+
+/// Asks for an explosion centred on a canvas point of the loaded screen.
+#[derive(Event, Debug, Clone, Copy)]
+pub struct Explosion {
+    pub centre: Vec2,
+    pub def: ExplosionDef,
+}
+
+#[derive(Component)]
+struct Flash {
+    timer: f32,
+}
+
+/// Strength left `distance` tiles away from the centre, from 1 down to 0.
+fn falloff(def: &ExplosionDef, distance: f32) -> f32 {
+    (1.0 - distance / def.radius).max(0.0)
+}
+
+/// Breaks tiles, drops their items and hurts what the blast can see.
+///
+/// Tiles are changed through their global coordinates, so the parts of the
+/// radius that spill into neighbouring screens are edited too and show up
+/// when those screens are loaded.
+fn explode(
+    mut commands: Commands,
+    mut events: EventReader<Explosion>,
+    mut tilemap: ResMut<TileMap>,
+    worldgen: Res<WorldGen>,
+    targets: Query<(Entity, &Transform, &Hitbox), With<Health>>,
+    mut damage: EventWriter<DamageEvent>,
+) {
+    let tile = TILE_SIZE as f32;
+    for explosion in events.read() {
+        let def = &explosion.def;
+        // Centre in global tile units, y growing downwards.
+        let centre = tilemap.origin().as_vec2() + Vec2::new(explosion.centre.x, -explosion.centre.y) / tile;
+        let reach = def.radius.ceil() as i32;
+        let centre_cell = centre.floor().as_ivec2();
+
+        for dy in -reach..=reach {
+            for dx in -reach..=reach {
+                let g = centre_cell + IVec2::new(dx, dy);
+                let distance = (g.as_vec2() + Vec2::splat(0.5)).distance(centre);
+                let strength = def.power * falloff(def, distance);
+                if strength <= 0.0 {
+                    continue;
+                }
+                let Some(target) = tilemap.tile_global(&worldgen, TileLayer::Foreground, g) else {
+                    continue;
+                };
+                if target.kind.def().blast_resistance > strength {
+                    continue;
+                }
+                tilemap.set_global(TileLayer::Foreground, g, None);
+                drop_item(&mut commands, g, ItemKind::from_tile(target.kind), 1);
+            }
+        }
+
+        // Tiles are already gone, so the blast sees through what it broke.
+        for (entity, transform, hitbox) in &targets {
+            let target = transform.translation.truncate() + Vec2::new(hitbox.size.x, -hitbox.size.y) * 0.5;
+            let distance = target.distance(explosion.centre) / tile;
+            let strength = falloff(def, distance);
+            if strength <= 0.0 || !tilemap.line_of_sight(explosion.centre, target) {
+                continue;
+            }
+            let away = (target - explosion.centre).normalize_or(Vec2::Y);
+            damage.write(DamageEvent {
+                target: entity,
+                amount: (def.damage as f32 * strength).ceil() as u32,
+                knockback: away * def.knockback * strength,
+            });
+        }
+
+        let size = def.radius * 2.0 * tile;
+        let mut sprite = Sprite::from_color(Color::srgba(1.0, 0.85, 0.5, 0.8), Vec2::splat(size));
+        sprite.anchor = Anchor::Center;
+        commands.spawn((
+            Flash { timer: FLASH_TIME },
+            sprite,
+            Transform::from_xyz(explosion.centre.x, explosion.centre.y, FLASH_Z),
+            PIXEL_PERFECT_LAYERS,
+            StateScoped(GameState::InGame),
+        ));
+    }
+}
+
+fn fade_flashes(mut commands: Commands, mut flashes: Query<(Entity, &mut Flash, &mut Sprite)>, time: Res<Time>) {
+    for (entity, mut flash, mut sprite) in &mut flashes {
+        flash.timer -= time.delta_secs();
+        if flash.timer <= 0.0 {
+            commands.entity(entity).despawn();
+            continue;
+        }
+        sprite.color.set_alpha(0.8 * flash.timer / FLASH_TIME);
+    }
+}
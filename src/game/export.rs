@@ -0,0 +1,130 @@
+//! Renders a range of screens to a PNG from tile data alone, without a GPU.
+//!
+//! `terra export` uses it to share seeds and to compare generator changes;
+//! the output only depends on the seed, the edits and the atlas, so it also
+//! works for golden-image tests of world generation.
+
+use std::path::Path;
+
+use bevy::prelude::*;
+use image::RgbaImage;
+
+use crate::game::{
+    map::{background_colour, tile_colour},
+    tilemap::{screen_origin, Tile, TileLayer, TileMap, ATLAS_COLUMNS, ATLAS_PATH, TCOLS, TILE_SIZE, TROWS, WALL_SHADE},
+    worldgen::WorldGen,
+};
+
+const USAGE: &str = "usage: terra export <seed> <from x> <from y> <to x> <to y> <out.png> [--flat]";
+
+/// How tiles are drawn.
+#[derive(Clone, Copy)]
+pub enum ExportStyle<'a> {
+    /// One pixel per tile, in the world map colours.
+    Flat,
+    /// `TILE_SIZE` pixels per tile from the tile atlas, tinted like the sprites.
+    Atlas(&'a RgbaImage),
+}
+
+/// Global tiles covered by screens `from` to `to`, both included: the first
+/// one and the size.
+pub fn region_tiles(from: IVec2, to: IVec2) -> (IVec2, UVec2) {
+    let (low, high) = (from.min(to), from.max(to));
+    let first = screen_origin(low);
+    let end = screen_origin(high) + IVec2::new(TCOLS as i32, TROWS as i32);
+    (first, (end - first).as_uvec2())
+}
+
+/// Draws screens `from` to `to`, both included, as the game would have them
+/// with the edits in `tilemap`.
+pub fn render_region(tilemap: &TileMap, worldgen: &WorldGen, from: IVec2, to: IVec2, style: ExportStyle) -> RgbaImage {
+    let (first, tiles) = region_tiles(from, to);
+    let scale = match style {
+        ExportStyle::Flat => 1,
+        ExportStyle::Atlas(_) => TILE_SIZE,
+    };
+    let mut out = RgbaImage::new(tiles.x * scale, tiles.y * scale);
+    for ty in 0..tiles.y {
+        for tx in 0..tiles.x {
+            let g = first + IVec2::new(tx as i32, ty as i32);
+            match style {
+                ExportStyle::Flat => out.put_pixel(tx, ty, image::Rgba(tile_colour(tilemap, worldgen, g))),
+                ExportStyle::Atlas(atlas) => {
+                    let wall = tilemap.tile_global(worldgen, TileLayer::Background, g);
+                    let tile = tilemap.tile_global(worldgen, TileLayer::Foreground, g);
+                    for py in 0..TILE_SIZE {
+                        for px in 0..TILE_SIZE {
+                            let mut colour = Color::from(Srgba::from_u8_array(background_colour(g))).to_linear();
+                            if let Some(wall) = wall {
+                                let tint = (wall.kind.def().tint.to_linear() * WALL_SHADE).with_alpha(1.0);
+                                colour = over(colour, texel(atlas, wall, px, py, tint));
+                            }
+                            if let Some(tile) = tile {
+                                colour = over(colour, texel(atlas, tile, px, py, tile.kind.def().tint.to_linear()));
+                            }
+                            let pixel = Color::from(colour).to_srgba().to_u8_array();
+                            out.put_pixel(tx * scale + px, ty * scale + py, image::Rgba(pixel));
+                        }
+                    }
+                }
+            }
+        }
+    }
+    out
+}
+
+/// Atlas pixel `(px, py)` of a tile's sprite, multiplied by `tint`.
+fn texel(atlas: &RgbaImage, tile: Tile, px: u32, py: u32, tint: LinearRgba) -> LinearRgba {
+    let index = tile.tile_index as u32;
+    let x = (index % ATLAS_COLUMNS) * TILE_SIZE + px;
+    let y = (index / ATLAS_COLUMNS) * TILE_SIZE + py;
+    let Some(pixel) = atlas.get_pixel_checked(x, y) else {
+        return LinearRgba::NONE;
+    };
+    let base = Color::from(Srgba::from_u8_array(pixel.0)).to_linear();
+    LinearRgba::new(base.red * tint.red, base.green * tint.green, base.blue * tint.blue, base.alpha * tint.alpha)
+}
+
+/// `top` drawn over `bottom`, which is opaque.
+fn over(bottom: LinearRgba, top: LinearRgba) -> LinearRgba {
+    let a = top.alpha;
+    LinearRgba::new(
+        top.red * a + bottom.red * (1.0 - a),
+        top.green * a + bottom.green * (1.0 - a),
+        top.blue * a + bottom.blue * (1.0 - a),
+        1.0,
+    )
+}
+
+/// Reads the tile atlas from the assets directory.
+pub fn load_atlas() -> Result<RgbaImage, String> {
+    let path = Path::new("assets").join(ATLAS_PATH);
+    image::open(&path).map(|atlas| atlas.to_rgba8()).map_err(|err| format!("could not read {}: {err}", path.display()))
+}
+
+/// Renders screens of a freshly generated world with seed `seed` to `path`.
+pub fn export_png(seed: u32, from: IVec2, to: IVec2, flat: bool, path: &Path) -> Result<(), String> {
+    let worldgen = WorldGen::new(seed);
+    // Nothing is drawn, so the tile map needs no real assets.
+    let tilemap = TileMap::new(Handle::default(), Handle::default());
+    let atlas = if flat { None } else { Some(load_atlas()?) };
+    let style = atlas.as_ref().map_or(ExportStyle::Flat, ExportStyle::Atlas);
+    let image = render_region(&tilemap, &worldgen, from, to, style);
+    image.save(path).map_err(|err| format!("could not write {}: {err}", path.display()))
+}
+
+/// `terra export`, given the arguments after the subcommand.
+pub fn run_export_command(args: &[String]) -> Result<(), String> {
+    let flat = args.iter().any(|arg| arg == "--flat");
+    let args: Vec<&str> = args.iter().map(String::as_str).filter(|arg| *arg != "--flat").collect();
+    let [seed, from_x, from_y, to_x, to_y, out] = args[..] else {
+        return Err(USAGE.to_string());
+    };
+    let number = |text: &str| text.parse::<i32>().map_err(|_| format!("not a whole number: {text}\n{USAGE}"));
+    let seed = seed.parse::<u32>().map_err(|_| format!("not a seed: {seed}\n{USAGE}"))?;
+    let from = IVec2::new(number(from_x)?, number(from_y)?);
+    let to = IVec2::new(number(to_x)?, number(to_y)?);
+    export_png(seed, from, to, flat, Path::new(out))?;
+    println!("wrote {out}");
+    Ok(())
+}
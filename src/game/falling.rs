@@ -0,0 +1,158 @@
+use bevy::{prelude::*, sprite::Anchor};
+
+use crate::game::{
+    drops::drop_item,
+    health::DamageEvent,
+    item::ItemKind,
+    player::Player,
+    state::{GameState, GameplaySet},
+    tilemap::{Tile, TileChanged, TileLayer, TileMap, TILE_SIZE},
+    worldgen::WorldGen,
+    PIXEL_PERFECT_LAYERS,
+};
+
+/// Downward acceleration of falling tiles, in pixels per second squared.
+const FALL_GRAVITY: f32 = 600.0;
+const MAX_FALL_SPEED: f32 = 300.0;
+/// Damage dealt to the player by a tile landing on them.
+const FALL_DAMAGE: u32 = 10;
+/// Furthest an off-screen tile drops in one go, in tiles.
+const MAX_UNSEEN_DROP: i32 = 256;
+
+pub struct FallingPlugin;
+
+impl Plugin for FallingPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(Update, (detach_falling_tiles, fall_tiles).chain().in_set(GameplaySet));
+    }
+}
+
+/// A tile that lost its support and is dropping as an entity.
+///
+/// Its position is kept in global coordinates so it keeps falling correctly
+/// when the loaded screen changes under it.
+#[derive(Component)]
+pub struct FallingTile {
+    pub tile: Tile,
+    /// Global tile column.
+    pub column: i32,
+    /// Global pixel row of the tile's top edge, growing downwards.
+    pub y: f32,
+    pub velocity: f32,
+    pub hit_player: bool,
+}
+
+/// Turns falling tiles into entities when the cell beneath them is emptied.
+fn detach_falling_tiles(
+    mut commands: Commands,
+    mut events: EventReader<TileChanged>,
+    mut tilemap: ResMut<TileMap>,
+    worldgen: Res<WorldGen>,
+) {
+    for change in events.read() {
+        if change.layer != TileLayer::Foreground {
+            continue;
+        }
+        let g = change.pos;
+        let above = g + IVec2::NEG_Y;
+        let Some(tile) = tilemap.tile_global(&worldgen, TileLayer::Foreground, above) else {
+            continue;
+        };
+        if !tile.kind.def().falls || tilemap.solid_global(&worldgen, g) {
+            continue;
+        }
+
+        tilemap.set_global(TileLayer::Foreground, above, None);
+        if tilemap.to_local(above).is_none() {
+            // Nobody can see it fall: drop it straight onto the ground below.
+            let mut landing = above;
+            while !tilemap.solid_global(&worldgen, landing + IVec2::Y) && landing.y - above.y < MAX_UNSEEN_DROP {
+                landing += IVec2::Y;
+            }
+            land(&mut commands, &mut tilemap, &worldgen, landing, tile);
+            continue;
+        }
+
+        let mut sprite = Sprite {
+            image: tilemap.texture.clone(),
+            texture_atlas: Some(TextureAtlas {
+                layout: tilemap.layout.clone(),
+                index: tile.tile_index,
+            }),
+            color: tile.kind.def().tint,
+            ..Default::default()
+        };
+        sprite.anchor = Anchor::TopLeft;
+
+        commands.spawn((
+            FallingTile {
+                tile,
+                column: above.x,
+                y: (above.y * TILE_SIZE as i32) as f32,
+                velocity: 0.0,
+                hit_player: false,
+            },
+            sprite,
+            Transform::default(),
+            PIXEL_PERFECT_LAYERS,
+            StateScoped(GameState::InGame),
+        ));
+    }
+}
+
+/// Settles `tile` in the global cell `landing`. Whatever non-solid tile was
+/// there, such as a torch, breaks into a drop like it would in an explosion.
+fn land(commands: &mut Commands, tilemap: &mut TileMap, worldgen: &WorldGen, landing: IVec2, tile: Tile) {
+    if let Some(occupant) = tilemap.tile_global(worldgen, TileLayer::Foreground, landing) {
+        drop_item(commands, landing, ItemKind::from_tile(occupant.kind), 1);
+    }
+    tilemap.set_global(TileLayer::Foreground, landing, Some(tile));
+}
+
+/// Drops falling tiles and settles them back into the [`TileMap`] where they land.
+fn fall_tiles(
+    mut commands: Commands,
+    mut tilemap: ResMut<TileMap>,
+    worldgen: Res<WorldGen>,
+    mut falling: Query<(Entity, &mut FallingTile, &mut Transform), Without<Player>>,
+    player: Query<(Entity, &Transform), With<Player>>,
+    mut damage: EventWriter<DamageEvent>,
+    time: Res<Time>,
+) {
+    let tile_size = TILE_SIZE as f32;
+    let origin = tilemap.origin();
+    let origin_px = origin.as_vec2() * tile_size;
+    let player = player.single().ok();
+
+    for (entity, mut falling, mut transform) in &mut falling {
+        falling.velocity = (falling.velocity + FALL_GRAVITY * time.delta_secs()).min(MAX_FALL_SPEED);
+        let mut next_y = falling.y + falling.velocity * time.delta_secs();
+
+        // Cell the bottom edge would move into.
+        let below = IVec2::new(falling.column, ((next_y + tile_size) / tile_size).floor() as i32);
+        if tilemap.solid_global(&worldgen, below) {
+            land(&mut commands, &mut tilemap, &worldgen, below + IVec2::NEG_Y, falling.tile);
+            commands.entity(entity).despawn();
+            continue;
+        }
+
+        // Rest on the player's head instead of passing through them.
+        let local_x = (falling.column - origin.x) as f32 * tile_size;
+        if let Some((player_entity, player_tf)) = player {
+            let player_pos = player_tf.translation;
+            let player_top = origin_px.y - player_pos.y;
+            let overlaps_x = (local_x - player_pos.x).abs() < tile_size;
+            if overlaps_x && next_y + tile_size > player_top && falling.y < player_top {
+                next_y = player_top - tile_size;
+                falling.velocity = 0.0;
+                if !falling.hit_player {
+                    falling.hit_player = true;
+                    damage.write(DamageEvent { target: player_entity, amount: FALL_DAMAGE, knockback: Vec2::ZERO });
+                }
+            }
+        }
+
+        falling.y = next_y;
+        transform.translation = Vec3::new(local_x, origin_px.y - falling.y, 0.0);
+    }
+}
@@ -0,0 +1,216 @@
+//! Runs the [`SimulationPlugin`] without a window, stepped one fixed frame at
+//! a time with scripted input, for the gameplay tests in `tests/`.
+//!
+//! [`HeadlessPlugin`] stands in for the parts of `DefaultPlugins` the
+//! simulation reads: time, input, assets and gizmos. Nothing is rendered and
+//! no asset has to load.
+
+use std::time::Duration;
+
+use bevy::{gizmos::GizmoAsset, prelude::*, state::app::StatesPlugin, time::TimeUpdateStrategy};
+
+use crate::game::{
+    cursor::{CursorPoint, CursorWorld},
+    player::Player,
+    raycast::cell_of,
+    replay::{Divergence, Playback, Recorder, Replay},
+    settings::{Action, Settings},
+    state::{GameState, SelectedWorld},
+    tilemap::{fill_tiles, TileKind, TileMap},
+    place_view, player::move_world, Canvas, InGameCamera, PixelatedCanvas, SimulationPlugin, ViewCentre,
+};
+
+/// Length of one [`Simulation::step`]: a frame at 60 Hz.
+pub const TICK: Duration = Duration::from_nanos(1_000_000_000 / 60);
+
+pub struct HeadlessPlugin;
+
+impl Plugin for HeadlessPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_plugins((MinimalPlugins, StatesPlugin, AssetPlugin::default()));
+        app.init_asset::<Image>();
+        app.init_asset::<TextureAtlasLayout>();
+        // Gizmo groups update their meshes even with nothing to draw them.
+        app.init_asset::<GizmoAsset>();
+        app.init_gizmo_group::<DefaultGizmoConfigGroup>();
+        // Every frame is as long, whatever the machine running the tests.
+        app.insert_resource(TimeUpdateStrategy::ManualDuration(TICK));
+        // Filled by `Simulation::press` instead of window events.
+        app.init_resource::<ButtonInput<KeyCode>>();
+        app.init_resource::<ButtonInput<MouseButton>>();
+        app.init_resource::<CursorWorld>();
+        // Default key bindings, not the ones in the user's settings file.
+        app.insert_resource(Settings::default());
+    }
+}
+
+/// A world in game, stepped by hand.
+pub struct Simulation {
+    pub app: App,
+}
+
+impl Simulation {
+    /// A new world with `seed`, in game with the player spawned.
+    pub fn new(seed: u32) -> Self {
+        Self::start(seed, |_| {})
+    }
+
+    /// Like [`Simulation::new`], recording every tick from the first one.
+    pub fn recording(seed: u32) -> Self {
+        Self::start(seed, |app| {
+            app.init_resource::<Recorder>();
+        })
+    }
+
+    /// A fresh world playing `replay` back from the first tick.
+    pub fn playing(replay: Replay) -> Self {
+        let seed = replay.seed;
+        Self::start(seed, |app| {
+            app.insert_resource(Playback::new(replay));
+        })
+    }
+
+    /// Like [`Simulation::new`] with a `canvas` sized canvas, whose camera and
+    /// sprite follow the [`ViewCentre`] as they do in the window.
+    pub fn viewing(seed: u32, canvas: UVec2) -> Self {
+        Self::start(seed, |app| {
+            let mut settings = app.world_mut().resource_mut::<Settings>();
+            settings.canvas_width = canvas.x;
+            settings.canvas_height = canvas.y;
+            app.insert_resource(Canvas {
+                image: Handle::default(),
+                size: canvas,
+                prescaled: Handle::default(),
+                prescale: 1,
+                subpixel: Vec2::ZERO,
+            });
+            app.world_mut().spawn((InGameCamera, Transform::default()));
+            app.world_mut().spawn((PixelatedCanvas, Sprite::default()));
+            app.add_systems(Update, place_view.after(move_world));
+        })
+    }
+
+    fn start(seed: u32, setup: impl FnOnce(&mut App)) -> Self {
+        let mut app = App::new();
+        app.add_plugins((HeadlessPlugin, SimulationPlugin));
+        setup(&mut app);
+        app.insert_resource(SelectedWorld { name: "headless".to_string(), seed });
+        app.world_mut().resource_mut::<NextState<GameState>>().set(GameState::InGame);
+        app.finish();
+        app.cleanup();
+        let mut simulation = Self { app };
+        simulation.step();
+        simulation
+    }
+
+    /// Runs one frame.
+    pub fn step(&mut self) {
+        self.app.update();
+        // What `InputPlugin` does before the next frame's input comes in.
+        self.app.world_mut().resource_mut::<ButtonInput<KeyCode>>().clear();
+        self.app.world_mut().resource_mut::<ButtonInput<MouseButton>>().clear();
+    }
+
+    pub fn run(&mut self, ticks: u32) {
+        for _ in 0..ticks {
+            self.step();
+        }
+    }
+
+    /// Steps until `done` holds, at most `ticks` times. Returns whether it did.
+    pub fn run_until(&mut self, ticks: u32, mut done: impl FnMut(&mut Self) -> bool) -> bool {
+        for _ in 0..ticks {
+            if done(self) {
+                return true;
+            }
+            self.step();
+        }
+        done(self)
+    }
+
+    /// Holds down the key bound to `action` from the next frame on.
+    pub fn press(&mut self, action: Action) {
+        let key = self.app.world().resource::<Settings>().keys.get(action);
+        self.app.world_mut().resource_mut::<ButtonInput<KeyCode>>().press(key);
+    }
+
+    pub fn release(&mut self, action: Action) {
+        let key = self.app.world().resource::<Settings>().keys.get(action);
+        self.app.world_mut().resource_mut::<ButtonInput<KeyCode>>().release(key);
+    }
+
+    pub fn press_button(&mut self, button: MouseButton) {
+        self.app.world_mut().resource_mut::<ButtonInput<MouseButton>>().press(button);
+    }
+
+    pub fn release_button(&mut self, button: MouseButton) {
+        self.app.world_mut().resource_mut::<ButtonInput<MouseButton>>().release(button);
+    }
+
+    /// Puts the cursor over `world`, a position on the loaded screen, as seen
+    /// with the whole screen in view; `None` takes it off the canvas.
+    pub fn aim(&mut self, world: Option<Vec2>) {
+        let point = world.map(|world| CursorPoint { canvas: Vec2::new(world.x, -world.y), world, tile: cell_of(world) });
+        self.app.world_mut().resource_mut::<CursorWorld>().0 = point;
+    }
+
+    /// The ticks recorded so far by a [`Simulation::recording`].
+    pub fn recorded(&self) -> Replay {
+        let recorder = self.app.world().resource::<Recorder>();
+        recorder.replay.clone().expect("recording starts on entering the game")
+    }
+
+    /// Steps a [`Simulation::playing`] to the end of its replay. Returns the
+    /// first tick where the world differed from the recording, if any.
+    pub fn play_to_end(&mut self) -> Result<(), Divergence> {
+        while !self.app.world().resource::<Playback>().finished() {
+            self.step();
+        }
+        match self.app.world().resource::<Playback>().diverged {
+            Some(divergence) => Err(divergence),
+            None => Ok(()),
+        }
+    }
+
+    /// The player and its position on the loaded screen.
+    pub fn player(&mut self) -> (Player, Vec2) {
+        let world = self.app.world_mut();
+        let (player, transform) = world
+            .query::<(&Player, &Transform)>()
+            .single(world)
+            .expect("the player is spawned on entering the game");
+        (player.clone(), transform.translation.truncate())
+    }
+
+    pub fn view(&self) -> Vec2 {
+        self.app.world().resource::<ViewCentre>().0
+    }
+
+    /// World position of the top-left corner of what a [`Simulation::viewing`]
+    /// shows: the corner of the camera's pixel-aligned image plus the part of
+    /// it the canvas sprite skips.
+    pub fn shown_corner(&mut self) -> Vec2 {
+        let world = self.app.world_mut();
+        let camera = world.query_filtered::<&Transform, With<InGameCamera>>().single(world).expect("a viewing simulation").translation;
+        let canvas = world.resource::<Canvas>();
+        let image = canvas.image_size().as_vec2();
+        let corner = Vec2::new(camera.x - image.x * 0.5, camera.y + image.y * 0.5);
+        corner + Vec2::new(canvas.subpixel.x, -canvas.subpixel.y)
+    }
+
+    pub fn tilemap(&self) -> &TileMap {
+        self.app.world().resource::<TileMap>()
+    }
+
+    /// Position of the loaded screen.
+    pub fn screen(&self) -> IVec2 {
+        self.tilemap().position.as_ivec2()
+    }
+
+    /// Sets the foreground of every global tile from `from` to `to`, both
+    /// included, and drains any liquid there, to build a known layout over
+    /// the generated one.
+    pub fn fill(&mut self, from: IVec2, to: IVec2, kind: Option<TileKind>) {
+        fill_tiles(self.app.world_mut(), from, to, kind);
+    }
+}
@@ -0,0 +1,114 @@
+use bevy::prelude::*;
+
+use crate::game::state::GameplaySet;
+
+/// Seconds an entity ignores further damage after being hit.
+const INVULNERABLE_TIME: f32 = 0.5;
+/// Seconds a knocked back entity loses control of its movement.
+const KNOCKBACK_TIME: f32 = 0.2;
+
+pub struct HealthPlugin;
+
+impl Plugin for HealthPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_event::<DamageEvent>();
+        app.add_event::<Died>();
+        app.add_systems(PostUpdate, apply_damage.in_set(GameplaySet));
+    }
+}
+
+#[derive(Component, Debug, Clone)]
+pub struct Health {
+    pub current: u32,
+    pub max: u32,
+    /// Remaining seconds of invulnerability.
+    pub cooldown: f32,
+}
+
+impl Health {
+    pub fn new(max: u32) -> Self {
+        Self { current: max, max, cooldown: 0.0 }
+    }
+
+    pub fn is_dead(&self) -> bool {
+        self.current == 0
+    }
+}
+
+/// Area that attacks can hit, as a box hanging from the entity's translation
+/// (its top-left corner, like the sprites).
+#[derive(Component, Debug, Clone, Copy)]
+pub struct Hitbox {
+    pub size: Vec2,
+}
+
+impl Hitbox {
+    pub fn contains(&self, top_left: Vec2, point: Vec2) -> bool {
+        point.x >= top_left.x && point.x <= top_left.x + self.size.x
+            && point.y <= top_left.y && point.y >= top_left.y - self.size.y
+    }
+}
+
+/// Push taken from the last hit. Entities without it ignore knockback.
+#[derive(Component, Debug, Clone, Copy, Default)]
+pub struct Knockback {
+    pending: Option<Vec2>,
+    /// Remaining seconds without control.
+    timer: f32,
+}
+
+impl Knockback {
+    /// Takes the push received since the last call, if any.
+    pub fn take(&mut self) -> Option<Vec2> {
+        self.pending.take()
+    }
+
+    /// Whether the entity is still being thrown back and should not steer.
+    pub fn active(&self) -> bool {
+        self.timer > 0.0
+    }
+}
+
+/// Asks for `amount` damage to be dealt to `target`, pushing it by `knockback`
+/// (a velocity change in pixels per second).
+#[derive(Event, Debug, Clone, Copy)]
+pub struct DamageEvent {
+    pub target: Entity,
+    pub amount: u32,
+    pub knockback: Vec2,
+}
+
+/// Sent once when an entity's health reaches zero.
+#[derive(Event, Debug, Clone, Copy)]
+pub struct Died(pub Entity);
+
+fn apply_damage(
+    mut events: EventReader<DamageEvent>,
+    mut healths: Query<(&mut Health, Option<&mut Knockback>)>,
+    mut died: EventWriter<Died>,
+    time: Res<Time>,
+) {
+    for (mut health, knockback) in &mut healths {
+        health.cooldown = (health.cooldown - time.delta_secs()).max(0.0);
+        if let Some(mut knockback) = knockback {
+            knockback.timer = (knockback.timer - time.delta_secs()).max(0.0);
+        }
+    }
+    for event in events.read() {
+        let Ok((mut health, knockback)) = healths.get_mut(event.target) else {
+            continue;
+        };
+        if health.cooldown > 0.0 || health.is_dead() {
+            continue;
+        }
+        health.current = health.current.saturating_sub(event.amount);
+        health.cooldown = INVULNERABLE_TIME;
+        if let Some(mut knockback) = knockback.filter(|_| event.knockback != Vec2::ZERO) {
+            knockback.pending = Some(event.knockback);
+            knockback.timer = KNOCKBACK_TIME;
+        }
+        if health.is_dead() {
+            died.write(Died(event.target));
+        }
+    }
+}
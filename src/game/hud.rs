@@ -0,0 +1,342 @@
+//! Overlay drawn by the [`OuterCamera`](super::OuterCamera) on top of the canvas.
+//!
+//! Everything is laid out in canvas pixels and mapped through the canvas
+//! transform, so it stays aligned with the game at any window size and
+//! scaling mode while text is still rendered at full resolution.
+
+use bevy::{
+    asset::RenderAssetUsages,
+    prelude::*,
+    render::render_resource::{Extent3d, TextureDimension, TextureFormat},
+    sprite::Anchor,
+};
+
+use crate::game::{
+    cursor::CursorWorld,
+    health::Health,
+    item::{Inventory, HOTBAR_SLOTS},
+    player::{Player, PLAYER_HEALTH},
+    settings::Settings,
+    speedrun::{format_time, RunState, Speedrun},
+    state::GameState,
+    tilemap::{TileMap, TCOLS, TILE_SIZE, TROWS},
+    Canvas, InGameCamera, PixelatedCanvas, HIGH_RES_LAYERS,
+};
+
+/// Health shown by one heart.
+const HEART_HEALTH: u32 = 10;
+const HEARTS: usize = (PLAYER_HEALTH / HEART_HEALTH) as usize;
+const HEART: [&str; 6] = [
+    ".##.##.",
+    "#######",
+    "#######",
+    ".#####.",
+    "..###..",
+    "...#...",
+];
+const HEART_FULL: Color = Color::srgb(0.9, 0.15, 0.2);
+const HEART_HALF: Color = Color::srgb(0.55, 0.15, 0.2);
+const HEART_EMPTY: Color = Color::srgba(0.15, 0.1, 0.1, 0.7);
+
+/// Margin around the HUD, in canvas pixels.
+const MARGIN: f32 = 4.0;
+const SLOT_SIZE: f32 = 14.0;
+const SLOT_GAP: f32 = 1.0;
+const ICON_SIZE: f32 = 8.0;
+const SLOT_COLOUR: Color = Color::srgba(0.1, 0.1, 0.15, 0.7);
+const SELECTED_COLOUR: Color = Color::srgba(0.9, 0.85, 0.5, 0.8);
+const CURSOR_COLOUR: Color = Color::srgba(1.0, 1.0, 1.0, 0.6);
+const NEW_BEST_COLOUR: Color = Color::srgb(1.0, 0.85, 0.3);
+/// Font size in canvas pixels.
+pub const FONT_SIZE: f32 = 6.0;
+
+/// Above the canvas sprite, which sits at z 0.
+pub const HUD_Z: f32 = 1.0;
+
+pub struct HudPlugin;
+
+impl Plugin for HudPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(OnEnter(GameState::InGame), setup_hud);
+        // Keeps following the canvas while paused.
+        app.add_systems(
+            Update,
+            (update_hearts, update_hotbar, update_readout, update_timer, update_cursor_highlight)
+                .run_if(in_state(GameState::InGame)),
+        );
+    }
+}
+
+#[derive(Component)]
+struct HudHeart(usize);
+
+#[derive(Component)]
+struct HudSlot(usize);
+
+#[derive(Component)]
+struct HudIcon(usize);
+
+#[derive(Component)]
+struct HudCount(usize);
+
+#[derive(Component)]
+struct HudReadout;
+
+/// Speedrun time, under the hearts.
+#[derive(Component)]
+struct HudTimer;
+
+#[derive(Component)]
+struct CursorHighlight;
+
+/// Where the canvas is drawn on screen.
+#[derive(Clone, Copy)]
+pub struct CanvasRect {
+    /// Size of the canvas in canvas pixels.
+    pub size: Vec2,
+    pub scale: Vec2,
+    /// Outer camera position of the canvas's top-left corner.
+    top_left: Vec2,
+}
+
+impl CanvasRect {
+    pub fn new(canvas_tf: &Transform, canvas: &Canvas) -> Self {
+        let size = canvas.size_f32();
+        let scale = canvas_tf.scale.truncate();
+        let half = Vec2::new(size.x, -size.y) * 0.5 * scale;
+        Self { size, scale, top_left: canvas_tf.translation.truncate() - half }
+    }
+
+    /// Outer camera position of a canvas pixel (top-left origin, y down).
+    pub fn point(&self, canvas_px: Vec2) -> Vec2 {
+        self.top_left + Vec2::new(canvas_px.x, -canvas_px.y) * self.scale
+    }
+
+    /// Places a top-left anchored sprite of `size` canvas pixels at `canvas_px`.
+    pub fn place(&self, transform: &mut Transform, sprite: &mut Sprite, canvas_px: Vec2, size: Vec2, z: f32) {
+        transform.translation = self.point(canvas_px).extend(z);
+        sprite.custom_size = Some(size * self.scale);
+    }
+}
+
+/// White mask of a pixel pattern, to be tinted by the sprite colour.
+fn mask_image(rows: &[&str]) -> Image {
+    let (width, height) = (rows[0].len() as u32, rows.len() as u32);
+    let data = rows
+        .iter()
+        .flat_map(|row| row.chars())
+        .flat_map(|c| if c == '#' { [255, 255, 255, 255] } else { [0, 0, 0, 0] })
+        .collect();
+    Image::new(
+        Extent3d { width, height, depth_or_array_layers: 1 },
+        TextureDimension::D2,
+        data,
+        TextureFormat::Rgba8UnormSrgb,
+        RenderAssetUsages::RENDER_WORLD,
+    )
+}
+
+/// One-pixel outline of a tile.
+fn outline_rows() -> Vec<String> {
+    let size = TILE_SIZE as usize;
+    (0..size)
+        .map(|y| (0..size).map(|x| if x == 0 || y == 0 || x == size - 1 || y == size - 1 { '#' } else { '.' }).collect())
+        .collect()
+}
+
+fn hud_sprite(image: Handle<Image>, colour: Color) -> (Sprite, StateScoped<GameState>) {
+    let mut sprite = Sprite::from_image(image);
+    sprite.color = colour;
+    sprite.anchor = Anchor::TopLeft;
+    (sprite, StateScoped(GameState::InGame))
+}
+
+fn hud_text(text: &str) -> (Text2d, TextFont, TextColor, StateScoped<GameState>) {
+    (Text2d::new(text), TextFont { font_size: FONT_SIZE, ..default() }, TextColor(Color::WHITE), StateScoped(GameState::InGame))
+}
+
+fn setup_hud(mut commands: Commands, mut images: ResMut<Assets<Image>>) {
+    let heart = images.add(mask_image(&HEART));
+    let outline_rows = outline_rows();
+    let outline = images.add(mask_image(&outline_rows.iter().map(String::as_str).collect::<Vec<_>>()));
+
+    for i in 0..HEARTS {
+        commands.spawn((HudHeart(i), hud_sprite(heart.clone(), HEART_FULL), Transform::default(), HIGH_RES_LAYERS));
+    }
+    for i in 0..HOTBAR_SLOTS {
+        commands.spawn((HudSlot(i), hud_sprite(Handle::default(), SLOT_COLOUR), Transform::default(), HIGH_RES_LAYERS));
+        commands.spawn((HudIcon(i), hud_sprite(Handle::default(), Color::NONE), Transform::default(), HIGH_RES_LAYERS));
+        commands.spawn((HudCount(i), hud_text(""), Anchor::BottomRight, Transform::default(), HIGH_RES_LAYERS));
+    }
+    commands.spawn((HudReadout, hud_text(""), Anchor::BottomLeft, Transform::default(), HIGH_RES_LAYERS));
+    commands.spawn((
+        HudTimer,
+        hud_text(""),
+        TextLayout::new_with_justify(JustifyText::Right),
+        Anchor::TopRight,
+        Transform::default(),
+        HIGH_RES_LAYERS,
+    ));
+    commands.spawn((CursorHighlight, hud_sprite(outline, CURSOR_COLOUR), Transform::default(), HIGH_RES_LAYERS));
+}
+
+/// Keeps text crisp by scaling the font with the canvas instead of the transform.
+pub fn scale_font(font: &mut TextFont, rect: &CanvasRect) {
+    let size = FONT_SIZE * rect.scale.y;
+    if font.font_size != size {
+        font.font_size = size;
+    }
+}
+
+fn update_hearts(
+    canvas_tf: Single<&Transform, (With<PixelatedCanvas>, Without<HudHeart>)>,
+    canvas: Res<Canvas>,
+    player: Query<&Health, With<Player>>,
+    mut hearts: Query<(&HudHeart, &mut Transform, &mut Sprite)>,
+) {
+    let rect = CanvasRect::new(&canvas_tf, &canvas);
+    let current = player.single().map_or(0, |health| health.current);
+    let size = Vec2::new(HEART[0].len() as f32, HEART.len() as f32);
+    let right = rect.size.x - MARGIN - HEARTS as f32 * (size.x + 1.0);
+
+    for (HudHeart(i), mut transform, mut sprite) in &mut hearts {
+        let pos = Vec2::new(right + *i as f32 * (size.x + 1.0), MARGIN);
+        rect.place(&mut transform, &mut sprite, pos, size, HUD_Z);
+        let start = *i as u32 * HEART_HEALTH;
+        sprite.color = if current >= start + HEART_HEALTH {
+            HEART_FULL
+        } else if current > start {
+            HEART_HALF
+        } else {
+            HEART_EMPTY
+        };
+    }
+}
+
+fn update_hotbar(
+    canvas_tf: Single<&Transform, (With<PixelatedCanvas>, Without<HudSlot>, Without<HudIcon>, Without<HudCount>)>,
+    canvas: Res<Canvas>,
+    player: Query<&Inventory, With<Player>>,
+    mut slots: Query<(&HudSlot, &mut Transform, &mut Sprite), (Without<HudIcon>, Without<HudCount>)>,
+    mut icons: Query<(&HudIcon, &mut Transform, &mut Sprite), (Without<HudSlot>, Without<HudCount>)>,
+    mut counts: Query<(&HudCount, &mut Transform, &mut Text2d, &mut TextFont), (Without<HudSlot>, Without<HudIcon>)>,
+) {
+    let rect = CanvasRect::new(&canvas_tf, &canvas);
+    let Ok(inventory) = player.single() else {
+        return;
+    };
+    let slot_pos = |i: usize| Vec2::new(MARGIN + i as f32 * (SLOT_SIZE + SLOT_GAP), MARGIN);
+
+    for (HudSlot(i), mut transform, mut sprite) in &mut slots {
+        rect.place(&mut transform, &mut sprite, slot_pos(*i), Vec2::splat(SLOT_SIZE), HUD_Z);
+        sprite.color = if *i == inventory.selected { SELECTED_COLOUR } else { SLOT_COLOUR };
+    }
+    for (HudIcon(i), mut transform, mut sprite) in &mut icons {
+        let pos = slot_pos(*i) + Vec2::splat((SLOT_SIZE - ICON_SIZE) * 0.5);
+        rect.place(&mut transform, &mut sprite, pos, Vec2::splat(ICON_SIZE), HUD_Z + 0.1);
+        sprite.color = inventory.slots[*i].map_or(Color::NONE, |stack| stack.kind.def().colour);
+    }
+    for (HudCount(i), mut transform, mut text, mut font) in &mut counts {
+        transform.translation = rect.point(slot_pos(*i) + Vec2::splat(SLOT_SIZE)).extend(HUD_Z + 0.2);
+        scale_font(&mut font, &rect);
+        let label = match inventory.slots[*i] {
+            Some(stack) if stack.count > 1 => stack.count.to_string(),
+            _ => String::new(),
+        };
+        if text.0 != label {
+            text.0 = label;
+        }
+    }
+}
+
+/// Screen, tile coordinates and depth of the player.
+fn update_readout(
+    canvas_tf: Single<&Transform, (With<PixelatedCanvas>, Without<HudReadout>)>,
+    canvas: Res<Canvas>,
+    tilemap: Res<TileMap>,
+    player: Query<&Transform, (With<Player>, Without<HudReadout>)>,
+    mut readout: Single<(&mut Transform, &mut Text2d, &mut TextFont), With<HudReadout>>,
+) {
+    let rect = CanvasRect::new(&canvas_tf, &canvas);
+    let (transform, text, font) = &mut *readout;
+    transform.translation = rect.point(Vec2::new(MARGIN, rect.size.y - MARGIN)).extend(HUD_Z);
+    scale_font(font, &rect);
+
+    let Ok(player_tf) = player.single() else {
+        return;
+    };
+    let centre = player_tf.translation.truncate() + Vec2::new(4., -4.);
+    let g = tilemap.origin() + tilemap.cell_at(centre);
+    let depth = tilemap.position.y as i32;
+    let level = if depth > 0 { format!("depth {depth}") } else { "surface".to_string() };
+    let label = format!("screen {}, {}  tile {}, {}  {}", tilemap.position.x, tilemap.position.y, g.x, g.y, level);
+    if text.0 != label {
+        text.0 = label;
+    }
+}
+
+/// Run time, how the last split compares with the best run, and the best
+/// time before the start.
+fn update_timer(
+    canvas_tf: Single<&Transform, (With<PixelatedCanvas>, Without<HudTimer>)>,
+    canvas: Res<Canvas>,
+    settings: Res<Settings>,
+    run: Option<Res<Speedrun>>,
+    mut timer: Single<(&mut Transform, &mut Text2d, &mut TextFont, &mut TextColor), With<HudTimer>>,
+) {
+    let rect = CanvasRect::new(&canvas_tf, &canvas);
+    let (transform, text, font, colour) = &mut *timer;
+    let below_hearts = MARGIN + HEART.len() as f32 + 2.0;
+    transform.translation = rect.point(Vec2::new(rect.size.x - MARGIN, below_hearts)).extend(HUD_Z);
+    scale_font(font, &rect);
+
+    let mut label = String::new();
+    if let Some(run) = run.filter(|_| settings.speedrun) {
+        label = format_time(run.time, false);
+        match run.state {
+            RunState::Ready => {
+                if let Some(best) = &run.best {
+                    label += &format!("\nbest {}", format_time(best.time, false));
+                }
+            }
+            RunState::Running => {
+                if let Some(delta) = run.last_split_delta() {
+                    label += &format!("\n{}", format_time(delta, true));
+                }
+            }
+            RunState::Finished if run.new_best => label += "\nnew best",
+            RunState::Finished if run.invalid => label += "\nnot a record",
+            RunState::Finished => {}
+        }
+        let new_colour = if run.new_best { NEW_BEST_COLOUR } else { Color::WHITE };
+        if colour.0 != new_colour {
+            colour.0 = new_colour;
+        }
+    }
+    if text.0 != label {
+        text.0 = label;
+    }
+}
+
+/// Outlines the tile under the mouse.
+fn update_cursor_highlight(
+    cursor: Res<CursorWorld>,
+    canvas_tf: Single<&Transform, (With<PixelatedCanvas>, Without<CursorHighlight>)>,
+    canvas: Res<Canvas>,
+    camera: Single<&Transform, (With<InGameCamera>, Without<CursorHighlight>)>,
+    mut highlight: Single<(&mut Transform, &mut Sprite, &mut Visibility), With<CursorHighlight>>,
+) {
+    let rect = CanvasRect::new(&canvas_tf, &canvas);
+    let (transform, sprite, visibility) = &mut *highlight;
+    let cell = cursor
+        .tile()
+        .filter(|cell| cell.x >= 0 && cell.y >= 0 && cell.x < TCOLS as i32 && cell.y < TROWS as i32);
+    let Some(cell) = cell else {
+        **visibility = Visibility::Hidden;
+        return;
+    };
+    **visibility = Visibility::Inherited;
+    let tile = TILE_SIZE as f32;
+    let corner = canvas.to_canvas(Vec2::new(cell.x as f32, -cell.y as f32) * tile, &camera);
+    rect.place(transform, sprite, corner, Vec2::splat(tile), HUD_Z);
+}
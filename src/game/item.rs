@@ -0,0 +1,358 @@
+use bevy::{input::mouse::MouseWheel, prelude::*};
+use serde::{Deserialize, Serialize};
+
+use crate::game::{console::{command_word, AddConsoleCommand, Args, CommandResult, ConsoleCommand, Param, ParamKind}, explosion::ExplosionDef, player::Player, melee::MeleeDef, projectile::{ProjectileDef, TileImpact}, state::GameplaySet, tilemap::TileKind};
+
+/// Slots in the player's hotbar.
+pub const HOTBAR_SLOTS: usize = 9;
+
+pub struct ItemPlugin;
+
+impl Plugin for ItemPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(Update, select_hotbar_slot.in_set(GameplaySet));
+        app.add_console_command(ConsoleCommand {
+            name: "give",
+            help: "puts items in the player's hotbar",
+            params: vec![Param::new("item", ParamKind::Choice(item_names)), Param::optional("count", ParamKind::Int)],
+            run: run_give,
+        });
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum ItemKind {
+    Dirt,
+    Stone,
+    Sand,
+    Gravel,
+    Torch,
+    Bow,
+    Arrow,
+    ThrowingKnife,
+    Blaster,
+    Sword,
+    Spear,
+    Bomb,
+    Dynamite,
+}
+
+/// A weapon that shoots projectiles toward the cursor.
+pub struct RangedDef {
+    pub projectile: ProjectileDef,
+    /// Item used up per shot, if any.
+    pub ammo: Option<ItemKind>,
+    /// Whether firing uses up one of the item itself (thrown weapons).
+    pub thrown: bool,
+    /// Seconds between two shots.
+    pub cooldown: f32,
+}
+
+/// Static properties shared by every item of an [`ItemKind`].
+pub struct ItemDef {
+    pub name: &'static str,
+    pub max_stack: u32,
+    /// Flat colour used for the item's icon.
+    pub colour: Color,
+    pub ranged: Option<RangedDef>,
+    pub melee: Option<MeleeDef>,
+}
+
+const fn block(name: &'static str, colour: Color) -> ItemDef {
+    ItemDef { name, max_stack: 999, colour, ranged: None, melee: None }
+}
+
+const DIRT: ItemDef = block("dirt", Color::srgb(0.55, 0.38, 0.24));
+const STONE: ItemDef = block("stone", Color::srgb(0.5, 0.5, 0.55));
+const SAND: ItemDef = block("sand", Color::srgb(0.95, 0.85, 0.55));
+const GRAVEL: ItemDef = block("gravel", Color::srgb(0.55, 0.5, 0.5));
+const TORCH: ItemDef = block("torch", Color::srgb(1.0, 0.8, 0.3));
+const ARROW: ItemDef = ItemDef { name: "arrow", max_stack: 999, colour: Color::srgb(0.8, 0.75, 0.6), ranged: None, melee: None };
+const BOW: ItemDef = ItemDef {
+    name: "bow",
+    max_stack: 1,
+    colour: Color::srgb(0.6, 0.4, 0.2),
+    ranged: Some(RangedDef {
+        projectile: ProjectileDef {
+            speed: 260.0,
+            gravity: -300.0,
+            lifetime: 3.0,
+            damage: 12,
+            size: Vec2::new(3.0, 1.0),
+            colour: Color::srgb(0.8, 0.75, 0.6),
+            impact: TileImpact::Stick,
+        },
+        ammo: Some(ItemKind::Arrow),
+        thrown: false,
+        cooldown: 0.5,
+    }),
+    melee: None,
+};
+const THROWING_KNIFE: ItemDef = ItemDef {
+    name: "throwing knife",
+    max_stack: 99,
+    colour: Color::srgb(0.8, 0.8, 0.85),
+    ranged: Some(RangedDef {
+        projectile: ProjectileDef {
+            speed: 200.0,
+            gravity: -450.0,
+            lifetime: 2.0,
+            damage: 9,
+            size: Vec2::new(2.0, 2.0),
+            colour: Color::srgb(0.8, 0.8, 0.85),
+            impact: TileImpact::Stick,
+        },
+        ammo: None,
+        thrown: true,
+        cooldown: 0.3,
+    }),
+    melee: None,
+};
+const BLASTER: ItemDef = ItemDef {
+    name: "blaster",
+    max_stack: 1,
+    colour: Color::srgb(0.3, 0.9, 0.9),
+    ranged: Some(RangedDef {
+        projectile: ProjectileDef {
+            speed: 400.0,
+            gravity: 0.0,
+            lifetime: 0.8,
+            damage: 6,
+            size: Vec2::new(2.0, 2.0),
+            colour: Color::srgb(0.5, 1.0, 1.0),
+            impact: TileImpact::Destroy,
+        },
+        ammo: None,
+        thrown: false,
+        cooldown: 0.25,
+    }),
+    melee: None,
+};
+const SWORD: ItemDef = ItemDef {
+    name: "sword",
+    max_stack: 1,
+    colour: Color::srgb(0.85, 0.85, 0.9),
+    ranged: None,
+    melee: Some(MeleeDef { damage: 15, knockback: 150.0, reach: 12.0, arc: 2.0, swing_time: 0.3 }),
+};
+const SPEAR: ItemDef = ItemDef {
+    name: "spear",
+    max_stack: 1,
+    colour: Color::srgb(0.7, 0.55, 0.35),
+    ranged: None,
+    melee: Some(MeleeDef { damage: 12, knockback: 100.0, reach: 20.0, arc: 0.6, swing_time: 0.45 }),
+};
+const BOMB: ItemDef = ItemDef {
+    name: "bomb",
+    max_stack: 99,
+    colour: Color::srgb(0.2, 0.2, 0.25),
+    ranged: Some(RangedDef {
+        projectile: ProjectileDef {
+            speed: 150.0,
+            gravity: -450.0,
+            lifetime: 1.5,
+            damage: 0,
+            size: Vec2::new(3.0, 3.0),
+            colour: Color::srgb(0.2, 0.2, 0.25),
+            impact: TileImpact::Explode(ExplosionDef { radius: 3.0, power: 3.0, damage: 40, knockback: 250.0 }),
+        },
+        ammo: None,
+        thrown: true,
+        cooldown: 0.6,
+    }),
+    melee: None,
+};
+const DYNAMITE: ItemDef = ItemDef {
+    name: "dynamite",
+    max_stack: 99,
+    colour: Color::srgb(0.85, 0.2, 0.15),
+    ranged: Some(RangedDef {
+        projectile: ProjectileDef {
+            speed: 120.0,
+            gravity: -450.0,
+            lifetime: 2.5,
+            damage: 0,
+            size: Vec2::new(2.0, 4.0),
+            colour: Color::srgb(0.85, 0.2, 0.15),
+            impact: TileImpact::Explode(ExplosionDef { radius: 6.0, power: 5.0, damage: 80, knockback: 400.0 }),
+        },
+        ammo: None,
+        thrown: true,
+        cooldown: 1.0,
+    }),
+    melee: None,
+};
+
+impl ItemKind {
+    pub const ALL: [ItemKind; 13] = [
+        ItemKind::Dirt,
+        ItemKind::Stone,
+        ItemKind::Sand,
+        ItemKind::Gravel,
+        ItemKind::Torch,
+        ItemKind::Bow,
+        ItemKind::Arrow,
+        ItemKind::ThrowingKnife,
+        ItemKind::Blaster,
+        ItemKind::Sword,
+        ItemKind::Spear,
+        ItemKind::Bomb,
+        ItemKind::Dynamite,
+    ];
+
+    pub fn def(&self) -> &'static ItemDef {
+        match self {
+            ItemKind::Dirt => &DIRT,
+            ItemKind::Stone => &STONE,
+            ItemKind::Sand => &SAND,
+            ItemKind::Gravel => &GRAVEL,
+            ItemKind::Torch => &TORCH,
+            ItemKind::Bow => &BOW,
+            ItemKind::Arrow => &ARROW,
+            ItemKind::ThrowingKnife => &THROWING_KNIFE,
+            ItemKind::Blaster => &BLASTER,
+            ItemKind::Sword => &SWORD,
+            ItemKind::Spear => &SPEAR,
+            ItemKind::Bomb => &BOMB,
+            ItemKind::Dynamite => &DYNAMITE,
+        }
+    }
+
+    /// Item dropped when a tile of `kind` is destroyed.
+    pub fn from_tile(kind: TileKind) -> ItemKind {
+        match kind {
+            TileKind::Dirt => ItemKind::Dirt,
+            TileKind::Stone => ItemKind::Stone,
+            TileKind::Sand => ItemKind::Sand,
+            TileKind::Gravel => ItemKind::Gravel,
+            TileKind::Torch => ItemKind::Torch,
+        }
+    }
+
+    /// Tile placed when this item is built, if it is a block.
+    pub fn to_tile(self) -> Option<TileKind> {
+        match self {
+            ItemKind::Dirt => Some(TileKind::Dirt),
+            ItemKind::Stone => Some(TileKind::Stone),
+            ItemKind::Sand => Some(TileKind::Sand),
+            ItemKind::Gravel => Some(TileKind::Gravel),
+            ItemKind::Torch => Some(TileKind::Torch),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ItemStack {
+    pub kind: ItemKind,
+    pub count: u32,
+}
+
+/// Hotbar of the player.
+#[derive(Component, Debug, Clone)]
+pub struct Inventory {
+    pub slots: [Option<ItemStack>; HOTBAR_SLOTS],
+    pub selected: usize,
+    /// Seconds until the selected item can be used again.
+    pub cooldown: f32,
+}
+
+impl Default for Inventory {
+    fn default() -> Self {
+        Self { slots: [None; HOTBAR_SLOTS], selected: 0, cooldown: 0.0 }
+    }
+}
+
+impl Inventory {
+    /// What a new player carries.
+    pub fn starter() -> Self {
+        let mut inventory = Self::default();
+        inventory.add(ItemKind::Bow, 1);
+        inventory.add(ItemKind::Arrow, 50);
+        inventory.add(ItemKind::ThrowingKnife, 20);
+        inventory.add(ItemKind::Blaster, 1);
+        inventory.add(ItemKind::Sword, 1);
+        inventory.add(ItemKind::Spear, 1);
+        inventory.add(ItemKind::Bomb, 10);
+        inventory.add(ItemKind::Dynamite, 3);
+        inventory
+    }
+
+    pub fn selected_item(&self) -> Option<ItemStack> {
+        self.slots[self.selected]
+    }
+
+    /// Adds `count` items, topping up existing stacks first. Returns what did not fit.
+    pub fn add(&mut self, kind: ItemKind, mut count: u32) -> u32 {
+        let max = kind.def().max_stack;
+        for stack in self.slots.iter_mut().flatten().filter(|s| s.kind == kind) {
+            let moved = count.min(max - stack.count.min(max));
+            stack.count += moved;
+            count -= moved;
+        }
+        for slot in self.slots.iter_mut().filter(|s| s.is_none()) {
+            if count == 0 {
+                break;
+            }
+            let moved = count.min(max);
+            *slot = Some(ItemStack { kind, count: moved });
+            count -= moved;
+        }
+        count
+    }
+
+    /// Takes one `kind` item out, preferring the last stack. Returns whether there was one.
+    pub fn remove_one(&mut self, kind: ItemKind) -> bool {
+        let Some(slot) = self.slots.iter_mut().rev().find(|s| s.is_some_and(|s| s.kind == kind)) else {
+            return false;
+        };
+        if let Some(stack) = slot {
+            stack.count -= 1;
+            if stack.count == 0 {
+                *slot = None;
+            }
+        }
+        true
+    }
+}
+
+/// Number keys pick a hotbar slot and the mouse wheel cycles through them.
+fn select_hotbar_slot(
+    keyboard_input: Res<ButtonInput<KeyCode>>,
+    mut wheel: EventReader<MouseWheel>,
+    mut inventories: Query<&mut Inventory>,
+) {
+    const KEYS: [KeyCode; HOTBAR_SLOTS] = [
+        KeyCode::Digit1, KeyCode::Digit2, KeyCode::Digit3,
+        KeyCode::Digit4, KeyCode::Digit5, KeyCode::Digit6,
+        KeyCode::Digit7, KeyCode::Digit8, KeyCode::Digit9,
+    ];
+    let scroll: f32 = wheel.read().map(|e| e.y).sum();
+    for mut inventory in &mut inventories {
+        if let Some(slot) = KEYS.iter().position(|k| keyboard_input.just_pressed(*k)) {
+            inventory.selected = slot;
+        }
+        if scroll > 0. {
+            inventory.selected = (inventory.selected + HOTBAR_SLOTS - 1) % HOTBAR_SLOTS;
+        } else if scroll < 0. {
+            inventory.selected = (inventory.selected + 1) % HOTBAR_SLOTS;
+        }
+    }
+}
+
+fn item_names(_: &World) -> Vec<String> {
+    ItemKind::ALL.iter().map(|kind| command_word(kind.def().name)).collect()
+}
+
+fn run_give(world: &mut World, args: &Args) -> CommandResult {
+    let name = args.word(0);
+    let kind = ItemKind::ALL.into_iter().find(|kind| command_word(kind.def().name) == name).expect("checked against the item names");
+    let count = if args.given(1) { args.int(1) } else { 1 };
+    let count = u32::try_from(count).map_err(|_| format!("count: expected at least 0, got {count}"))?;
+    let mut query = world.query_filtered::<&mut Inventory, With<Player>>();
+    let mut inventory = query.single_mut(world).map_err(|_| "there is no player".to_string())?;
+    match inventory.add(kind, count) {
+        0 => Ok(format!("gave {count} {name}")),
+        left => Ok(format!("gave {} {name}, no room for {left}", count - left)),
+    }
+}
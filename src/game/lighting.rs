@@ -0,0 +1,268 @@
+use std::collections::VecDeque;
+
+use bevy::{prelude::*, sprite::Anchor};
+
+use crate::game::{clock::WorldClock, liquid::LiquidMap, state::{GameState, GameplaySet}, tilemap::{TileGrid, TileLayer, TileMap, TCOLS, TILE_SIZE, TROWS}, PIXEL_PERFECT_LAYERS};
+
+/// Brightest light level a cell can have.
+pub const MAX_LIGHT: u8 = 15;
+/// Light lost per step through an empty cell.
+const AIR_FALLOFF: u8 = 1;
+/// Depth of the darkening overlay, above tiles and the player.
+const OVERLAY_Z: f32 = 10.0;
+
+pub type LightGrid = [[u8; TCOLS]; TROWS];
+
+pub struct LightingPlugin;
+
+impl Plugin for LightingPlugin {
+    fn build(&self, app: &mut App) {
+        app.insert_resource(LightMap { levels: [[0; TCOLS]; TROWS], sky: 0, screen: None, tiles: [[None; TCOLS]; TROWS], walls: [[None; TCOLS]; TROWS], emitters: [[0; TCOLS]; TROWS] });
+        app.add_systems(OnEnter(GameState::InGame), spawn_light_overlay);
+        app.add_systems(PostUpdate, (update_light_map, apply_light_overlay).chain().in_set(GameplaySet));
+    }
+}
+
+/// Per-tile light levels of the current screen, from 0 (dark) to [`MAX_LIGHT`].
+#[derive(Resource)]
+pub struct LightMap {
+    pub levels: LightGrid,
+    /// Sky light the levels were computed with.
+    pub sky: u8,
+    /// Screen the levels were computed for.
+    pub screen: Option<Vec2>,
+    /// Tiles the levels were computed with.
+    pub tiles: TileGrid,
+    /// Walls the levels were computed with.
+    pub walls: TileGrid,
+    /// Non-tile light sources the levels were computed with.
+    pub emitters: LightGrid,
+}
+
+impl LightMap {
+    pub fn level_at(&self, x: usize, y: usize) -> u8 {
+        if x >= TCOLS || y >= TROWS {
+            0
+        } else {
+            self.levels[y][x]
+        }
+    }
+}
+
+/// Black cell drawn over tile `(x, y)` whose alpha darkens what is below it.
+#[derive(Component)]
+struct LightOverlay {
+    x: usize,
+    y: usize,
+}
+
+/// Flood-fills light over `tiles`.
+///
+/// Every column is lit at `sky` from the top down to its first opaque tile or
+/// background wall, and emissive tiles and `emitters` (light from anything that
+/// is not a tile, such as lava) light their cells. Light then spreads to the four neighbours,
+/// losing [`AIR_FALLOFF`] through empty cells and the tile's opacity through
+/// anything else.
+pub fn compute_light(tiles: &TileGrid, walls: &TileGrid, emitters: &LightGrid, sky: u8) -> LightGrid {
+    let mut levels = source_light(tiles, walls, emitters, sky);
+    let mut queue = VecDeque::new();
+    for (y, row) in levels.iter().enumerate() {
+        for (x, level) in row.iter().enumerate() {
+            if *level > 0 {
+                queue.push_back((x, y));
+            }
+        }
+    }
+    spread(&mut levels, tiles, queue);
+    levels
+}
+
+/// Updates `levels`, computed by [`compute_light`] before the cells in
+/// `changed` were edited, to what it would compute now.
+///
+/// Only the light that may have come through the changed cells is taken back:
+/// from each of them it is cleared outward for as long as it keeps getting
+/// dimmer, then the cleared cells get their own light again and the
+/// brighter cells around them spread back in.
+pub fn relight(
+    levels: &mut LightGrid,
+    tiles: &TileGrid,
+    walls: &TileGrid,
+    emitters: &LightGrid,
+    sky: u8,
+    changed: &[(usize, usize)],
+) {
+    let sources = source_light(tiles, walls, emitters, sky);
+    let mut removal = VecDeque::new();
+    for &(x, y) in changed {
+        // An edit can also change how far down the sky reaches in its column.
+        let below = if sky > 0 { TROWS } else { y + 1 };
+        for (y, row) in levels.iter_mut().enumerate().take(below).skip(y) {
+            if removal.iter().all(|&(cell, _)| cell != (x, y)) {
+                removal.push_back(((x, y), row[x]));
+                row[x] = 0;
+            }
+        }
+    }
+
+    let mut removed = Vec::new();
+    let mut queue = VecDeque::new();
+    while let Some(((x, y), level)) = removal.pop_front() {
+        removed.push((x, y));
+        for (nx, ny) in neighbours(x, y) {
+            let neighbour = levels[ny][nx];
+            if neighbour != 0 && neighbour < level {
+                levels[ny][nx] = 0;
+                removal.push_back(((nx, ny), neighbour));
+            } else {
+                queue.push_back((nx, ny));
+            }
+        }
+    }
+
+    for (x, y) in removed {
+        if sources[y][x] > levels[y][x] {
+            levels[y][x] = sources[y][x];
+            queue.push_back((x, y));
+        }
+    }
+    spread(levels, tiles, queue);
+}
+
+/// Light each cell gets by itself: the sky down to the first opaque tile or
+/// wall of its column, or its own emission or emitter's.
+fn source_light(tiles: &TileGrid, walls: &TileGrid, emitters: &LightGrid, sky: u8) -> LightGrid {
+    let mut levels = [[0u8; TCOLS]; TROWS];
+
+    if sky > 0 {
+        for x in 0..TCOLS {
+            for y in 0..TROWS {
+                if walls[y][x].is_some() || tiles[y][x].is_some_and(|t| t.kind.def().opacity > AIR_FALLOFF) {
+                    break;
+                }
+                levels[y][x] = sky;
+            }
+        }
+    }
+
+    for y in 0..TROWS {
+        for x in 0..TCOLS {
+            let emission = tiles[y][x].map_or(0, |t| t.kind.def().emission).max(emitters[y][x]);
+            levels[y][x] = levels[y][x].max(emission);
+        }
+    }
+
+    levels
+}
+
+/// The cells next to `(x, y)` that are on the screen.
+fn neighbours(x: usize, y: usize) -> impl Iterator<Item = (usize, usize)> {
+    [(x.wrapping_sub(1), y), (x + 1, y), (x, y.wrapping_sub(1)), (x, y + 1)]
+        .into_iter()
+        .filter(|&(nx, ny)| nx < TCOLS && ny < TROWS)
+}
+
+/// Spreads light outward from the cells in `queue` until nothing gets brighter.
+fn spread(levels: &mut LightGrid, tiles: &TileGrid, mut queue: VecDeque<(usize, usize)>) {
+    while let Some((x, y)) = queue.pop_front() {
+        let level = levels[y][x];
+        for (nx, ny) in neighbours(x, y) {
+            let falloff = tiles[ny][nx].map_or(AIR_FALLOFF, |t| t.kind.def().opacity.max(AIR_FALLOFF));
+            let next = level.saturating_sub(falloff);
+            if next > levels[ny][nx] {
+                levels[ny][nx] = next;
+                queue.push_back((nx, ny));
+            }
+        }
+    }
+}
+
+fn spawn_light_overlay(mut commands: Commands) {
+    for y in 0..TROWS {
+        for x in 0..TCOLS {
+            let mut sprite = Sprite::from_color(Color::BLACK, Vec2::splat(TILE_SIZE as f32));
+            sprite.anchor = Anchor::TopLeft;
+            commands.spawn((
+                sprite,
+                Transform::from_xyz(
+                    x as f32 * TILE_SIZE as f32,
+                    -(y as f32 * TILE_SIZE as f32),
+                    OVERLAY_Z,
+                ),
+                LightOverlay { x, y },
+                PIXEL_PERFECT_LAYERS,
+                StateScoped(GameState::InGame),
+            ));
+        }
+    }
+}
+
+/// Keeps the [`LightMap`] up to date: relights around the tiles that differ
+/// from the ones it was computed with and the cells whose light from liquids
+/// changed, and recomputes it all for a new screen or sky light.
+pub(crate) fn update_light_map(
+    tilemap: Res<TileMap>,
+    clock: Res<WorldClock>,
+    liquids: Res<LiquidMap>,
+    mut light_map: ResMut<LightMap>,
+) {
+    // Screens below the surface row never see the sky.
+    let sky = if tilemap.position.y > 0. { 0 } else { clock.sky_light() };
+    let mut emitters = [[0; TCOLS]; TROWS];
+    for (y, row) in emitters.iter_mut().enumerate() {
+        for (x, emission) in row.iter_mut().enumerate() {
+            *emission = liquids.get(tilemap.to_global(x, y)).map_or(0, |l| l.kind.emission());
+        }
+    }
+    if !tilemap.is_changed() && sky == light_map.sky && emitters == light_map.emitters {
+        return;
+    }
+    let tiles = tilemap.grid(TileLayer::Foreground);
+    let walls = tilemap.grid(TileLayer::Background);
+    let mut levels = light_map.levels;
+    // A world entered again may start on the screen the old one was left on.
+    if tilemap.is_added() || light_map.screen != Some(tilemap.position) || sky != light_map.sky {
+        levels = compute_light(tiles, walls, &emitters, sky);
+        light_map.screen = Some(tilemap.position);
+        light_map.sky = sky;
+    } else {
+        let changed: Vec<(usize, usize)> = (0..TROWS)
+            .flat_map(|y| (0..TCOLS).map(move |x| (x, y)))
+            .filter(|&(x, y)| {
+                tiles[y][x] != light_map.tiles[y][x]
+                    || walls[y][x] != light_map.walls[y][x]
+                    || emitters[y][x] != light_map.emitters[y][x]
+            })
+            .collect();
+        if changed.is_empty() {
+            return;
+        }
+        relight(&mut levels, tiles, walls, &emitters, sky, &changed);
+    }
+    if *tiles != light_map.tiles {
+        light_map.tiles = *tiles;
+    }
+    if *walls != light_map.walls {
+        light_map.walls = *walls;
+    }
+    if emitters != light_map.emitters {
+        light_map.emitters = emitters;
+    }
+    if levels != light_map.levels {
+        light_map.levels = levels;
+    }
+}
+
+fn apply_light_overlay(
+    light_map: Res<LightMap>,
+    mut overlays: Query<(&LightOverlay, &mut Sprite)>,
+) {
+    if !light_map.is_changed() {
+        return;
+    }
+    for (cell, mut sprite) in &mut overlays {
+        let level = light_map.level_at(cell.x, cell.y);
+        let darkness = 1.0 - level as f32 / MAX_LIGHT as f32;
+        sprite.color = Color::BLACK.with_alpha(darkness);
+    }
+}
@@ -0,0 +1,322 @@
+use std::collections::{HashMap, HashSet};
+
+use bevy::{prelude::*, sprite::Anchor};
+use serde::{Deserialize, Serialize};
+
+use crate::game::{
+    state::{GameState, GameplaySet},
+    tilemap::{screen_origin, Tile, TileChanged, TileKind, TileLayer, TileMap, TCOLS, TILE_SIZE, TROWS},
+    worldgen::WorldGen,
+    PIXEL_PERFECT_LAYERS,
+};
+
+/// Fill level of a full cell.
+pub const MAX_LEVEL: u8 = 8;
+/// Fixed updates between two simulation steps.
+const STEP_EVERY: u32 = 3;
+/// Drawn between tiles and the player.
+const LIQUID_Z: f32 = 0.5;
+
+pub struct LiquidPlugin;
+
+impl Plugin for LiquidPlugin {
+    fn build(&self, app: &mut App) {
+        app.insert_resource(LiquidMap::default());
+        app.add_systems(Update, (seed_liquids, wake_liquids).in_set(GameplaySet));
+        app.add_systems(FixedUpdate, simulate_liquids.in_set(GameplaySet));
+        app.add_systems(PostUpdate, draw_liquids.in_set(GameplaySet));
+        app.add_systems(OnExit(GameState::InGame), reset_liquids);
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum LiquidKind {
+    Water,
+    Lava,
+}
+
+impl LiquidKind {
+    pub fn colour(&self) -> Color {
+        match self {
+            LiquidKind::Water => Color::srgba(0.2, 0.4, 0.9, 0.7),
+            LiquidKind::Lava => Color::srgb(1.0, 0.4, 0.1),
+        }
+    }
+
+    /// Simulation steps between two moves; lava is sluggish.
+    pub fn flow_interval(&self) -> u32 {
+        match self {
+            LiquidKind::Water => 1,
+            LiquidKind::Lava => 4,
+        }
+    }
+
+    pub fn emission(&self) -> u8 {
+        match self {
+            LiquidKind::Water => 0,
+            LiquidKind::Lava => 12,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Liquid {
+    pub kind: LiquidKind,
+    pub level: u8,
+}
+
+/// Every liquid cell of the world by global tile coordinates.
+///
+/// Only cells in `active` are simulated; a cell is dropped from it once it stops
+/// moving and woken again when something around it changes.
+#[derive(Resource)]
+pub struct LiquidMap {
+    pub cells: HashMap<IVec2, Liquid>,
+    active: HashSet<IVec2>,
+    /// Screens whose generated liquid has already been added.
+    pub seeded: HashSet<IVec2>,
+    step: u32,
+    ticks: u32,
+    sprites: [[Option<Entity>; TCOLS]; TROWS],
+}
+
+impl Default for LiquidMap {
+    fn default() -> Self {
+        Self {
+            cells: HashMap::new(),
+            active: HashSet::new(),
+            seeded: HashSet::new(),
+            step: 0,
+            ticks: 0,
+            sprites: [[None; TCOLS]; TROWS],
+        }
+    }
+}
+
+impl LiquidMap {
+    /// Whether another already seeded screen around `screen` covers global tile `g`.
+    fn seeded_by_neighbour(&self, screen: IVec2, g: IVec2) -> bool {
+        (-1..=1)
+            .flat_map(|dy| (-1..=1).map(move |dx| screen + IVec2::new(dx, dy)))
+            .filter(|s| *s != screen && self.seeded.contains(s))
+            .any(|s| {
+                let local = g - screen_origin(s);
+                local.x >= 0 && local.y >= 0 && local.x < TCOLS as i32 && local.y < TROWS as i32
+            })
+    }
+
+    pub fn get(&self, g: IVec2) -> Option<Liquid> {
+        self.cells.get(&g).copied()
+    }
+
+    pub fn level_at(&self, g: IVec2) -> u8 {
+        self.get(g).map_or(0, |l| l.level)
+    }
+
+    pub fn set(&mut self, g: IVec2, liquid: Option<Liquid>) {
+        match liquid {
+            Some(liquid) if liquid.level > 0 => {
+                self.cells.insert(g, liquid);
+            }
+            _ => {
+                self.cells.remove(&g);
+            }
+        }
+        self.wake_around(g);
+    }
+
+    /// Marks `g` and its neighbours for simulation.
+    pub fn wake_around(&mut self, g: IVec2) {
+        for offset in [IVec2::ZERO, IVec2::X, IVec2::NEG_X, IVec2::Y, IVec2::NEG_Y] {
+            if self.cells.contains_key(&(g + offset)) {
+                self.active.insert(g + offset);
+            }
+        }
+    }
+
+    pub fn is_active(&self, g: IVec2) -> bool {
+        self.active.contains(&g)
+    }
+
+    /// Drops every liquid and forgets which screens were seeded, so they get
+    /// their generated liquid again.
+    pub fn clear(&mut self) {
+        self.cells.clear();
+        self.active.clear();
+        self.seeded.clear();
+    }
+}
+
+/// Whether liquid may occupy a cell: only completely empty cells hold liquid.
+fn open(tilemap: &TileMap, worldgen: &WorldGen, g: IVec2) -> bool {
+    tilemap.tile_global(worldgen, TileLayer::Foreground, g).is_none()
+}
+
+/// Advances the simulation by one step over the active cells.
+///
+/// Liquid first falls into the cell below, then levels out with its sideways
+/// neighbours one unit at a time. Water touching lava turns the lava into stone.
+pub fn step_liquids(liquids: &mut LiquidMap, tilemap: &mut TileMap, worldgen: &WorldGen) {
+    liquids.step = liquids.step.wrapping_add(1);
+    let mut cells: Vec<IVec2> = liquids.active.drain().collect();
+    // Bottom rows first, so a column drains in one step.
+    cells.sort_by_key(|g| (-g.y, g.x));
+    let side_first = if liquids.step.is_multiple_of(2) { 1 } else { -1 };
+
+    for g in cells {
+        let Some(mut liquid) = liquids.get(g) else {
+            continue;
+        };
+        if !liquids.step.is_multiple_of(liquid.kind.flow_interval()) {
+            liquids.active.insert(g);
+            continue;
+        }
+        let start = liquid;
+
+        let below = g + IVec2::Y;
+        if open(tilemap, worldgen, below) {
+            match liquids.get(below) {
+                Some(other) if other.kind != liquid.kind => {
+                    react(liquids, tilemap, g, below);
+                    continue;
+                }
+                other => {
+                    let below_level = other.map_or(0, |l| l.level);
+                    let flow = liquid.level.min(MAX_LEVEL - below_level);
+                    if flow > 0 {
+                        liquid.level -= flow;
+                        liquids.set(below, Some(Liquid { kind: liquid.kind, level: below_level + flow }));
+                    }
+                }
+            }
+        }
+
+        for side in [side_first, -side_first] {
+            let next = g + IVec2::new(side, 0);
+            if liquid.level <= 1 || !open(tilemap, worldgen, next) {
+                continue;
+            }
+            match liquids.get(next) {
+                Some(other) if other.kind != liquid.kind => {
+                    react(liquids, tilemap, g, next);
+                    break;
+                }
+                other => {
+                    let next_level = other.map_or(0, |l| l.level);
+                    if liquid.level > next_level + 1 {
+                        liquid.level -= 1;
+                        liquids.set(next, Some(Liquid { kind: liquid.kind, level: next_level + 1 }));
+                    }
+                }
+            }
+        }
+
+        if !liquids.cells.contains_key(&g) {
+            // Consumed by a reaction.
+            continue;
+        }
+        if liquid != start {
+            liquids.set(g, Some(liquid));
+            liquids.wake_around(g + IVec2::NEG_Y);
+        }
+    }
+}
+
+/// Water and lava meeting at `a` and `b`: the lava cell hardens into stone.
+fn react(liquids: &mut LiquidMap, tilemap: &mut TileMap, a: IVec2, b: IVec2) {
+    let lava = if liquids.get(a).is_some_and(|l| l.kind == LiquidKind::Lava) { a } else { b };
+    liquids.set(lava, None);
+    tilemap.set_global(TileLayer::Foreground, lava, Some(Tile { tile_index: 0, kind: TileKind::Stone }));
+}
+
+fn simulate_liquids(
+    mut liquids: ResMut<LiquidMap>,
+    mut tilemap: ResMut<TileMap>,
+    worldgen: Res<WorldGen>,
+) {
+    liquids.ticks = liquids.ticks.wrapping_add(1);
+    if !liquids.ticks.is_multiple_of(STEP_EVERY) || liquids.active.is_empty() {
+        return;
+    }
+    step_liquids(&mut liquids, &mut tilemap, &worldgen);
+}
+
+/// Adds the generated liquid of a screen the first time it is loaded.
+fn seed_liquids(mut liquids: ResMut<LiquidMap>, tilemap: Res<TileMap>, worldgen: Res<WorldGen>) {
+    if !tilemap.is_changed() {
+        return;
+    }
+    let screen = tilemap.position.as_ivec2();
+    if !liquids.seeded.insert(screen) {
+        return;
+    }
+    // Border cells shared with a screen seeded earlier keep whatever flowed in or out of them.
+    for y in 0..TROWS {
+        for x in 0..TCOLS {
+            let g = tilemap.to_global(x, y);
+            if tilemap.edits.contains_key(&(TileLayer::Foreground, g))
+                || liquids.cells.contains_key(&g)
+                || liquids.seeded_by_neighbour(screen, g)
+            {
+                continue;
+            }
+            if let Some(kind) = worldgen.liquid_at(g) {
+                liquids.set(g, Some(Liquid { kind, level: MAX_LEVEL }));
+            }
+        }
+    }
+}
+
+/// Forgets the liquids of the world being left.
+fn reset_liquids(mut commands: Commands) {
+    commands.insert_resource(LiquidMap::default());
+}
+
+/// Lets liquid flow again when tiles around it are removed or placed.
+fn wake_liquids(mut events: EventReader<TileChanged>, mut liquids: ResMut<LiquidMap>) {
+    for change in events.read().filter(|c| c.layer == TileLayer::Foreground) {
+        liquids.wake_around(change.pos);
+        liquids.wake_around(change.pos + IVec2::NEG_Y);
+    }
+}
+
+fn draw_liquids(
+    mut commands: Commands,
+    mut liquids: ResMut<LiquidMap>,
+    tilemap: Res<TileMap>,
+    mut sprites: Query<(&mut Sprite, &mut Transform)>,
+) {
+    if !liquids.is_changed() && !tilemap.is_changed() {
+        return;
+    }
+    for y in 0..TROWS {
+        for x in 0..TCOLS {
+            let liquid = liquids.get(tilemap.to_global(x, y));
+            let existing = liquids.sprites[y][x];
+            let Some(liquid) = liquid else {
+                if let Some(ent) = existing {
+                    commands.entity(ent).despawn();
+                    liquids.sprites[y][x] = None;
+                }
+                continue;
+            };
+
+            let height = liquid.level as f32 / MAX_LEVEL as f32 * TILE_SIZE as f32;
+            let bottom = Vec3::new(
+                x as f32 * TILE_SIZE as f32,
+                -((y + 1) as f32 * TILE_SIZE as f32),
+                LIQUID_Z,
+            );
+            if let Some((mut sprite, mut transform)) = existing.and_then(|e| sprites.get_mut(e).ok()) {
+                sprite.color = liquid.kind.colour();
+                sprite.custom_size = Some(Vec2::new(TILE_SIZE as f32, height));
+                transform.translation = bottom;
+            } else {
+                let mut sprite = Sprite::from_color(liquid.kind.colour(), Vec2::new(TILE_SIZE as f32, height));
+                sprite.anchor = Anchor::BottomLeft;
+                let e = commands.spawn((sprite, Transform::from_translation(bottom), PIXEL_PERFECT_LAYERS, StateScoped(GameState::InGame))).id();
+                liquids.sprites[y][x] = Some(e);
+            }
+        }
+    }
+}
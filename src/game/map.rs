@@ -0,0 +1,353 @@
+//! World map and minimap, drawn on the CPU at one pixel per tile.
+//!
+//! Every discovered screen keeps a small picture of its tiles, redrawn when
+//! one of them changes. The minimap and the full-screen map copy from those
+//! pictures into their own images while they are shown, so undiscovered
+//! parts of the world are never generated just to be drawn.
+
+use std::collections::{HashMap, HashSet};
+
+use bevy::{
+    asset::RenderAssetUsages,
+    input::mouse::MouseWheel,
+    prelude::*,
+    render::render_resource::{Extent3d, TextureDimension, TextureFormat},
+    sprite::Anchor,
+};
+
+use crate::game::{
+    cursor::CursorWorld,
+    hud::{CanvasRect, HUD_Z},
+    pathfinding::chunk_of,
+    player::Player,
+    settings::Settings,
+    state::{GameState, Pause},
+    tilemap::{screen_origin, ScreenChanged, TileChanged, TileLayer, TileMap, CHUNK_COLS, CHUNK_ROWS, TILE_SIZE, WALL_SHADE},
+    worldgen::{WorldGen, UNDERGROUND_ROW},
+    Canvas, PixelatedCanvas, HIGH_RES_LAYERS,
+};
+
+/// Size of the minimap in tiles, shown one canvas pixel each.
+const MINIMAP_SIZE: UVec2 = UVec2::new(48, 27);
+/// Canvas pixels from the top of the canvas, below the hearts.
+const MINIMAP_TOP: f32 = 12.0;
+const MARGIN: f32 = 4.0;
+/// Canvas pixels per tile at each zoom level of the world map.
+const ZOOMS: [f32; 5] = [0.25, 0.5, 1.0, 2.0, 4.0];
+const DEFAULT_ZOOM: usize = 2;
+/// Speed of panning with the arrow keys, in canvas pixels per second.
+const PAN_SPEED: f32 = 120.0;
+/// Screens drawn per frame, so loading a well-explored world does not stall.
+const CHUNKS_PER_FRAME: usize = 4;
+const MINIMAP_Z: f32 = HUD_Z + 0.5;
+/// Over the rest of the HUD.
+const WORLD_MAP_Z: f32 = HUD_Z + 2.0;
+
+const UNDISCOVERED: [u8; 4] = [12, 12, 18, 230];
+const SKY: [u8; 4] = [90, 130, 190, 255];
+const CAVE: [u8; 4] = [30, 24, 22, 255];
+const MARKER: [u8; 4] = [255, 60, 60, 255];
+const MARKER_CENTRE: [u8; 4] = [255, 255, 255, 255];
+
+pub struct MapPlugin;
+
+impl Plugin for MapPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(OnEnter(GameState::InGame), spawn_maps);
+        app.add_systems(OnEnter(Pause::Map), centre_world_map);
+        // The maps keep updating while the world map pauses the game.
+        app.add_systems(
+            Update,
+            (
+                discover_screens,
+                draw_chunks,
+                toggle_world_map,
+                pan_world_map.run_if(in_state(Pause::Map)),
+                (draw_minimap, draw_world_map),
+            )
+                .chain()
+                .run_if(in_state(GameState::InGame)),
+        );
+    }
+}
+
+/// Screens the player has been to, saved with the world.
+#[derive(Resource, Debug, Default, Clone)]
+pub struct Discovered(pub HashSet<IVec2>);
+
+/// A picture of every discovered screen, `CHUNK_COLS` by `CHUNK_ROWS`
+/// pixels, split up the way [`chunk_of`] splits the world.
+#[derive(Resource, Default)]
+struct MapTiles {
+    chunks: HashMap<IVec2, Vec<[u8; 4]>>,
+}
+
+impl MapTiles {
+    /// Pixel of global tile `g`.
+    fn pixel(&self, g: IVec2) -> [u8; 4] {
+        let chunk = chunk_of(g);
+        let Some(pixels) = self.chunks.get(&chunk) else {
+            return UNDISCOVERED;
+        };
+        let local = g - screen_origin(chunk);
+        pixels[(local.y * CHUNK_COLS + local.x) as usize]
+    }
+}
+
+/// Where the world map looks and how close.
+#[derive(Resource)]
+struct WorldMapView {
+    /// Global tile at the middle of the map.
+    centre: Vec2,
+    /// Index into [`ZOOMS`].
+    zoom: usize,
+    /// Canvas pixel the mouse was dragging from last frame.
+    drag: Option<Vec2>,
+}
+
+#[derive(Component)]
+struct Minimap;
+
+#[derive(Component)]
+struct WorldMap;
+
+fn to_bytes(colour: Color) -> [u8; 4] {
+    colour.to_srgba().to_u8_array()
+}
+
+/// Colour of an empty cell with no wall: sky or cave.
+pub fn background_colour(g: IVec2) -> [u8; 4] {
+    if g.y < UNDERGROUND_ROW { SKY } else { CAVE }
+}
+
+/// Map colour of global tile `g`: the tile, else its wall, else the background.
+pub fn tile_colour(tilemap: &TileMap, worldgen: &WorldGen, g: IVec2) -> [u8; 4] {
+    if let Some(tile) = tilemap.tile_global(worldgen, TileLayer::Foreground, g) {
+        return to_bytes(tile.kind.def().map_colour);
+    }
+    if let Some(wall) = tilemap.tile_global(worldgen, TileLayer::Background, g) {
+        return to_bytes((wall.kind.def().map_colour.to_linear() * WALL_SHADE).with_alpha(1.0).into());
+    }
+    background_colour(g)
+}
+
+fn draw_chunk(tilemap: &TileMap, worldgen: &WorldGen, chunk: IVec2) -> Vec<[u8; 4]> {
+    let origin = screen_origin(chunk);
+    let mut pixels = Vec::with_capacity((CHUNK_COLS * CHUNK_ROWS) as usize);
+    for y in 0..CHUNK_ROWS {
+        for x in 0..CHUNK_COLS {
+            pixels.push(tile_colour(tilemap, worldgen, origin + IVec2::new(x, y)));
+        }
+    }
+    pixels
+}
+
+/// Fills `data`, the pixels of a `size` image, with the map around `centre`
+/// at `zoom` pixels per tile, with a marker on `player`. Both are in global
+/// tiles.
+fn compose(tiles: &MapTiles, centre: Vec2, zoom: f32, size: UVec2, player: Option<Vec2>, data: &mut [u8]) {
+    let half = size.as_vec2() * 0.5;
+    for y in 0..size.y {
+        for x in 0..size.x {
+            let offset = (Vec2::new(x as f32, y as f32) + Vec2::splat(0.5) - half) / zoom;
+            let g = (centre + offset).floor().as_ivec2();
+            let i = ((y * size.x + x) * 4) as usize;
+            data[i..i + 4].copy_from_slice(&tiles.pixel(g));
+        }
+    }
+
+    let Some(player) = player else {
+        return;
+    };
+    let at = ((player - centre) * zoom + half).floor().as_ivec2();
+    let marker = [(IVec2::ZERO, MARKER_CENTRE), (IVec2::X, MARKER), (IVec2::NEG_X, MARKER), (IVec2::Y, MARKER), (IVec2::NEG_Y, MARKER)];
+    for (offset, colour) in marker {
+        let p = at + offset;
+        if p.x >= 0 && p.y >= 0 && p.x < size.x as i32 && p.y < size.y as i32 {
+            let i = ((p.y as u32 * size.x + p.x as u32) * 4) as usize;
+            data[i..i + 4].copy_from_slice(&colour);
+        }
+    }
+}
+
+fn map_image(size: UVec2) -> Image {
+    Image::new_fill(
+        Extent3d { width: size.x, height: size.y, depth_or_array_layers: 1 },
+        TextureDimension::D2,
+        &UNDISCOVERED,
+        TextureFormat::Rgba8UnormSrgb,
+        RenderAssetUsages::MAIN_WORLD | RenderAssetUsages::RENDER_WORLD,
+    )
+}
+
+/// Global tile position of the player's centre.
+fn player_tile(tilemap: &TileMap, transform: &Transform) -> Vec2 {
+    let tile = TILE_SIZE as f32;
+    let centre = transform.translation.truncate() + Vec2::new(tile, -tile) * 0.5;
+    tilemap.origin().as_vec2() + Vec2::new(centre.x, -centre.y) / tile
+}
+
+fn spawn_maps(mut commands: Commands, mut images: ResMut<Assets<Image>>, canvas: Res<Canvas>) {
+    commands.insert_resource(MapTiles::default());
+    commands.insert_resource(WorldMapView { centre: Vec2::ZERO, zoom: DEFAULT_ZOOM, drag: None });
+
+    commands.spawn((Minimap, map_sprite(images.add(map_image(MINIMAP_SIZE))), Visibility::Inherited));
+    commands.spawn((WorldMap, map_sprite(images.add(map_image(canvas.size))), Visibility::Hidden));
+}
+
+/// Placed on the canvas every frame by the systems drawing it.
+fn map_sprite(image: Handle<Image>) -> impl Bundle {
+    let mut sprite = Sprite::from_image(image);
+    sprite.anchor = Anchor::TopLeft;
+    (sprite, Transform::default(), HIGH_RES_LAYERS, StateScoped(GameState::InGame))
+}
+
+/// Marks every screen the player enters.
+fn discover_screens(mut events: EventReader<ScreenChanged>, mut discovered: ResMut<Discovered>) {
+    for event in events.read() {
+        discovered.0.insert(event.position);
+    }
+}
+
+/// Draws newly discovered screens and redraws those whose tiles changed.
+fn draw_chunks(
+    mut changes: EventReader<TileChanged>,
+    mut tiles: ResMut<MapTiles>,
+    discovered: Res<Discovered>,
+    tilemap: Res<TileMap>,
+    worldgen: Res<WorldGen>,
+) {
+    // A new seed redraws everything.
+    if worldgen.is_changed() {
+        tiles.chunks.clear();
+    }
+    for change in changes.read() {
+        tiles.chunks.remove(&chunk_of(change.pos));
+    }
+    let missing: Vec<IVec2> =
+        discovered.0.iter().filter(|chunk| !tiles.chunks.contains_key(*chunk)).take(CHUNKS_PER_FRAME).copied().collect();
+    for chunk in missing {
+        let pixels = draw_chunk(&tilemap, &worldgen, chunk);
+        tiles.chunks.insert(chunk, pixels);
+    }
+}
+
+fn toggle_world_map(
+    keyboard_input: Res<ButtonInput<KeyCode>>,
+    settings: Res<Settings>,
+    pause: Res<State<Pause>>,
+    mut next_pause: ResMut<NextState<Pause>>,
+) {
+    if !keyboard_input.just_pressed(settings.keys.map) {
+        return;
+    }
+    match pause.get() {
+        Pause::Running => next_pause.set(Pause::Map),
+        Pause::Map => next_pause.set(Pause::Running),
+        Pause::Paused | Pause::Console => {}
+    }
+}
+
+/// Opens the world map on the player.
+fn centre_world_map(mut view: ResMut<WorldMapView>, tilemap: Res<TileMap>, player: Query<&Transform, With<Player>>) {
+    view.centre = player.single().map_or(tilemap.origin().as_vec2(), |transform| player_tile(&tilemap, transform));
+    view.drag = None;
+}
+
+/// Arrow keys and dragging with the left button pan, the wheel zooms.
+fn pan_world_map(
+    keyboard_input: Res<ButtonInput<KeyCode>>,
+    mouse: Res<ButtonInput<MouseButton>>,
+    mut wheel: EventReader<MouseWheel>,
+    cursor: Res<CursorWorld>,
+    mut view: ResMut<WorldMapView>,
+    time: Res<Time>,
+) {
+    let zoom = ZOOMS[view.zoom];
+    let mut direction = Vec2::ZERO;
+    for (key, step) in [
+        (KeyCode::ArrowLeft, Vec2::NEG_X),
+        (KeyCode::ArrowRight, Vec2::X),
+        (KeyCode::ArrowUp, Vec2::NEG_Y),
+        (KeyCode::ArrowDown, Vec2::Y),
+    ] {
+        if keyboard_input.pressed(key) {
+            direction += step;
+        }
+    }
+    view.centre += direction * PAN_SPEED * time.delta_secs() / zoom;
+
+    let held = cursor.canvas().filter(|_| mouse.pressed(MouseButton::Left));
+    if let (Some(from), Some(to)) = (view.drag, held) {
+        view.centre -= (to - from) / zoom;
+    }
+    view.drag = held;
+
+    let scroll: f32 = wheel.read().map(|event| event.y).sum();
+    if scroll > 0.0 {
+        view.zoom = (view.zoom + 1).min(ZOOMS.len() - 1);
+    } else if scroll < 0.0 {
+        view.zoom = view.zoom.saturating_sub(1);
+    }
+}
+
+fn draw_minimap(
+    tiles: Res<MapTiles>,
+    tilemap: Res<TileMap>,
+    pause: Res<State<Pause>>,
+    mut images: ResMut<Assets<Image>>,
+    canvas: Res<Canvas>,
+    canvas_tf: Single<&Transform, (With<PixelatedCanvas>, Without<Minimap>)>,
+    player: Query<&Transform, (With<Player>, Without<Minimap>)>,
+    mut minimap: Single<(&mut Transform, &mut Sprite, &mut Visibility), With<Minimap>>,
+) {
+    let (transform, sprite, visibility) = &mut *minimap;
+    // Hidden under the world map.
+    let player_tf = player.single().ok().filter(|_| *pause.get() != Pause::Map);
+    let Some(player_tf) = player_tf else {
+        **visibility = Visibility::Hidden;
+        return;
+    };
+    **visibility = Visibility::Inherited;
+
+    let rect = CanvasRect::new(&canvas_tf, &canvas);
+    let size = MINIMAP_SIZE.as_vec2();
+    rect.place(transform, sprite, Vec2::new(rect.size.x - MARGIN - size.x, MINIMAP_TOP), size, MINIMAP_Z);
+
+    let player = player_tile(&tilemap, player_tf);
+    if let Some(data) = images.get_mut(&sprite.image).and_then(|image| image.data.as_mut()) {
+        compose(&tiles, player, 1.0, MINIMAP_SIZE, Some(player), data);
+    }
+}
+
+fn draw_world_map(
+    tiles: Res<MapTiles>,
+    tilemap: Res<TileMap>,
+    view: Res<WorldMapView>,
+    pause: Res<State<Pause>>,
+    mut images: ResMut<Assets<Image>>,
+    canvas: Res<Canvas>,
+    canvas_tf: Single<&Transform, (With<PixelatedCanvas>, Without<WorldMap>)>,
+    player: Query<&Transform, (With<Player>, Without<WorldMap>)>,
+    mut world_map: Single<(&mut Transform, &mut Sprite, &mut Visibility), With<WorldMap>>,
+) {
+    let (transform, sprite, visibility) = &mut *world_map;
+    if *pause.get() != Pause::Map {
+        **visibility = Visibility::Hidden;
+        return;
+    }
+    **visibility = Visibility::Inherited;
+
+    let rect = CanvasRect::new(&canvas_tf, &canvas);
+    rect.place(transform, sprite, Vec2::ZERO, rect.size, WORLD_MAP_Z);
+
+    // Follows the canvas if its size changed since the map was made.
+    let fits = images.get(&sprite.image).is_some_and(|image| image.size() == canvas.size);
+    if !fits {
+        sprite.image = images.add(map_image(canvas.size));
+    }
+    let player = player.single().ok().map(|transform| player_tile(&tilemap, transform));
+    if let Some(data) = images.get_mut(&sprite.image).and_then(|image| image.data.as_mut()) {
+        compose(&tiles, view.centre, ZOOMS[view.zoom], canvas.size, player, data);
+    }
+}
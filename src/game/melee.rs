@@ -0,0 +1,158 @@
+use bevy::{prelude::*, sprite::Anchor};
+
+use crate::game::{
+    health::{DamageEvent, Health, Hitbox},
+    item::{Inventory, ItemKind},
+    player::Player,
+    state::{GameState, GameplaySet},
+    tilemap::TileMap,
+    PIXEL_PERFECT_LAYERS,
+};
+
+/// Spacing of the points checked along a blade, in pixels.
+const HIT_STEP: f32 = 2.0;
+const BLADE_WIDTH: f32 = 2.0;
+const SWING_Z: f32 = 0.25;
+/// Fraction of the knockback that goes upwards, so grounded targets get lifted.
+const KNOCKBACK_LIFT: f32 = 0.5;
+
+pub struct MeleePlugin;
+
+impl Plugin for MeleePlugin {
+    fn build(&self, app: &mut App) {
+        app.add_event::<MeleeHit>();
+        app.add_systems(Update, (start_swing, update_swings).chain().in_set(GameplaySet));
+    }
+}
+
+/// A weapon swung in an arc in front of its user.
+#[derive(Debug, Clone, Copy)]
+pub struct MeleeDef {
+    pub damage: u32,
+    /// Speed given to whatever is hit, in pixels per second.
+    pub knockback: f32,
+    /// Blade length from the user's centre, in pixels.
+    pub reach: f32,
+    /// Angle swept, in radians, from above the facing direction to below it.
+    pub arc: f32,
+    /// Seconds a swing lasts; also the time before the next one.
+    pub swing_time: f32,
+}
+
+/// Sent for every target a swing connects with, for sounds, particles and
+/// damage numbers.
+#[derive(Event, Debug, Clone, Copy)]
+pub struct MeleeHit {
+    pub attacker: Entity,
+    pub target: Entity,
+    pub weapon: ItemKind,
+    pub damage: u32,
+    /// Where the blade touched the target.
+    pub point: Vec2,
+}
+
+/// A swing in progress. Its transform follows the owner's centre.
+#[derive(Component)]
+pub struct Swing {
+    pub owner: Entity,
+    pub weapon: ItemKind,
+    pub elapsed: f32,
+    /// -1 or 1, fixed when the swing starts.
+    pub facing: f32,
+    /// Targets already hit; each is hit at most once per swing.
+    pub hit: Vec<Entity>,
+}
+
+impl Swing {
+    /// Direction the blade points at, `t` going from 0 to 1 over the swing.
+    pub fn direction(&self, def: &MeleeDef, t: f32) -> Vec2 {
+        let dir = Vec2::from_angle(def.arc * (0.5 - t));
+        Vec2::new(dir.x * self.facing, dir.y)
+    }
+}
+
+/// Centre of an entity hanging from its top-left translation, like the player.
+fn centre_of(transform: &Transform, size: Vec2) -> Vec2 {
+    transform.translation.truncate() + Vec2::new(size.x, -size.y) * 0.5
+}
+
+/// Left click with a melee item swings it toward where the player faces.
+fn start_swing(
+    mut commands: Commands,
+    mouse: Res<ButtonInput<MouseButton>>,
+    mut player: Query<(Entity, &Player, &mut Inventory)>,
+    swings: Query<&Swing>,
+) {
+    let Ok((entity, player, mut inventory)) = player.single_mut() else {
+        return;
+    };
+    if !mouse.pressed(MouseButton::Left) || inventory.cooldown > 0.0 || swings.iter().any(|s| s.owner == entity) {
+        return;
+    }
+    let Some(item) = inventory.selected_item() else {
+        return;
+    };
+    let Some(melee) = &item.kind.def().melee else {
+        return;
+    };
+    inventory.cooldown = melee.swing_time;
+
+    let mut sprite = Sprite::from_color(item.kind.def().colour, Vec2::new(melee.reach, BLADE_WIDTH));
+    sprite.anchor = Anchor::CenterLeft;
+    commands.spawn((
+        Swing { owner: entity, weapon: item.kind, elapsed: 0.0, facing: player.facing, hit: Vec::new() },
+        sprite,
+        Transform::from_xyz(0., 0., SWING_Z),
+        PIXEL_PERFECT_LAYERS,
+        StateScoped(GameState::InGame),
+    ));
+}
+
+fn update_swings(
+    mut commands: Commands,
+    tilemap: Res<TileMap>,
+    mut swings: Query<(Entity, &mut Swing, &mut Transform), Without<Hitbox>>,
+    targets: Query<(Entity, &Transform, &Hitbox), With<Health>>,
+    mut damage: EventWriter<DamageEvent>,
+    mut hits: EventWriter<MeleeHit>,
+    time: Res<Time>,
+) {
+    for (entity, mut swing, mut transform) in &mut swings {
+        let def = swing.weapon.def().melee.as_ref();
+        let owner = targets.get(swing.owner).ok();
+        let (Some(def), Some((_, owner_tf, owner_box))) = (def, owner) else {
+            commands.entity(entity).despawn();
+            continue;
+        };
+        swing.elapsed += time.delta_secs();
+        if swing.elapsed >= def.swing_time {
+            commands.entity(entity).despawn();
+            continue;
+        }
+
+        let centre = centre_of(owner_tf, owner_box.size);
+        let dir = swing.direction(def, swing.elapsed / def.swing_time);
+        transform.translation.x = centre.x;
+        transform.translation.y = centre.y;
+        transform.rotation = Quat::from_rotation_z(dir.to_angle());
+
+        // Walk the blade outwards; it cannot reach through tiles.
+        let reach = tilemap.raycast(centre, dir, def.reach).map_or(def.reach, |hit| hit.distance);
+        let steps = (reach / HIT_STEP).ceil().max(1.0) as usize;
+        for i in 1..=steps {
+            let point = centre + dir * reach * i as f32 / steps as f32;
+            for (target, target_tf, hitbox) in &targets {
+                if target == swing.owner || swing.hit.contains(&target) {
+                    continue;
+                }
+                if !hitbox.contains(target_tf.translation.truncate(), point) {
+                    continue;
+                }
+                swing.hit.push(target);
+                let knockback = Vec2::new(swing.facing, KNOCKBACK_LIFT).normalize() * def.knockback;
+                damage.write(DamageEvent { target, amount: def.damage, knockback });
+                hits.write(MeleeHit { attacker: swing.owner, target, weapon: swing.weapon, damage: def.damage, point });
+            }
+        }
+    }
+}
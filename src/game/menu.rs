@@ -0,0 +1,528 @@
+use bevy::{
+    input::{
+        keyboard::{Key, KeyboardInput},
+        ButtonState,
+    },
+    prelude::*,
+};
+
+use crate::game::{
+    save::{list_worlds, valid_world_name, world_dir, write_world_save, WorldSave},
+    settings::{Action, Scaling, Settings, CANVAS_SIZES, MAX_CANVAS_SCALE, WINDOW_SIZES},
+    state::{GameState, Pause, SelectedWorld},
+};
+
+const BACKGROUND: Color = Color::srgb(0.06, 0.06, 0.1);
+const PAUSE_BACKGROUND: Color = Color::srgba(0.0, 0.0, 0.0, 0.6);
+const BUTTON: Color = Color::srgb(0.18, 0.18, 0.25);
+const BUTTON_HOVERED: Color = Color::srgb(0.28, 0.28, 0.38);
+const BUTTON_PRESSED: Color = Color::srgb(0.4, 0.4, 0.55);
+const FOCUSED_FIELD: Color = Color::srgb(0.3, 0.3, 0.2);
+const ERROR_TEXT: Color = Color::srgb(1.0, 0.45, 0.4);
+const TITLE_SIZE: f32 = 48.0;
+const TEXT_SIZE: f32 = 20.0;
+
+pub struct MenuPlugin;
+
+impl Plugin for MenuPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_state::<Options>();
+        app.enable_state_scoped_entities::<Options>();
+        app.init_resource::<WorldForm>();
+        app.init_resource::<Rebinding>();
+        app.add_systems(OnEnter(GameState::MainMenu), spawn_main_menu);
+        app.add_systems(OnEnter(GameState::WorldSelect), spawn_world_select);
+        app.add_systems(OnEnter(Pause::Paused), spawn_pause_menu);
+        app.add_systems(OnEnter(Options::Open), spawn_options);
+        app.add_systems(OnExit(Options::Open), cancel_rebinding);
+        app.add_systems(Update, (colour_buttons, press_buttons, handle_escape, rebind_key).chain());
+        app.add_systems(Update, show_settings.run_if(in_state(Options::Open)));
+        app.add_systems(
+            Update,
+            (type_in_form, show_form).chain().run_if(in_state(GameState::WorldSelect)),
+        );
+    }
+}
+
+/// Whether the options panel is shown over the current menu.
+#[derive(States, Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+enum Options {
+    #[default]
+    Closed,
+    Open,
+}
+
+/// Settings changed with the arrows of the options panel.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Setting {
+    WindowSize,
+    Fullscreen,
+    Resolution,
+    Scaling,
+    CanvasScale,
+    Volume,
+    Speedrun,
+}
+
+impl Setting {
+    const ALL: [Setting; 7] = [
+        Setting::WindowSize,
+        Setting::Fullscreen,
+        Setting::Resolution,
+        Setting::Scaling,
+        Setting::CanvasScale,
+        Setting::Volume,
+        Setting::Speedrun,
+    ];
+
+    fn describe(&self, settings: &Settings) -> String {
+        match self {
+            Setting::WindowSize => format!("Window: {}x{}", settings.window_width, settings.window_height),
+            Setting::Fullscreen => format!("Fullscreen: {}", if settings.fullscreen { "on" } else { "off" }),
+            Setting::Resolution => format!("Resolution: {}x{}", settings.canvas_width, settings.canvas_height),
+            Setting::Scaling => format!("Scaling: {}", settings.scaling.name()),
+            Setting::CanvasScale if settings.scaling != Scaling::Integer => "Scale: -".to_string(),
+            Setting::CanvasScale if settings.canvas_scale == 0 => "Scale: fit window".to_string(),
+            Setting::CanvasScale => format!("Scale: {}x", settings.canvas_scale),
+            Setting::Volume => format!("Volume: {}%", (settings.volume * 100.0).round()),
+            Setting::Speedrun => format!("Speedrun: {}", if settings.speedrun { "on" } else { "off" }),
+        }
+    }
+
+    /// Steps the setting `delta` notches, wrapping around where it makes sense.
+    fn adjust(&self, settings: &mut Settings, delta: i32) {
+        match self {
+            Setting::WindowSize => {
+                let current = UVec2::new(settings.window_width, settings.window_height);
+                let index = WINDOW_SIZES.iter().position(|s| *s == current).map_or(0, |i| i as i32 + delta);
+                let size = WINDOW_SIZES[index.rem_euclid(WINDOW_SIZES.len() as i32) as usize];
+                settings.window_width = size.x;
+                settings.window_height = size.y;
+            }
+            Setting::Fullscreen => settings.fullscreen = !settings.fullscreen,
+            Setting::Speedrun => settings.speedrun = !settings.speedrun,
+            Setting::Resolution => {
+                let current = settings.canvas_size();
+                let index = CANVAS_SIZES.iter().position(|s| *s == current).map_or(0, |i| i as i32 + delta);
+                let size = CANVAS_SIZES[index.rem_euclid(CANVAS_SIZES.len() as i32) as usize];
+                settings.canvas_width = size.x;
+                settings.canvas_height = size.y;
+            }
+            Setting::Scaling => {
+                let index = Scaling::ALL.iter().position(|s| *s == settings.scaling).unwrap_or(0) as i32 + delta;
+                settings.scaling = Scaling::ALL[index.rem_euclid(Scaling::ALL.len() as i32) as usize];
+            }
+            Setting::CanvasScale => {
+                let choices = MAX_CANVAS_SCALE as i32 + 1;
+                settings.canvas_scale = (settings.canvas_scale as i32 + delta).rem_euclid(choices) as u32;
+            }
+            Setting::Volume => {
+                let notches = ((settings.volume * 10.0).round() as i32 + delta).clamp(0, 10);
+                settings.volume = notches as f32 / 10.0;
+            }
+        }
+    }
+}
+
+/// Action waiting for a key press to be bound to it.
+#[derive(Resource, Default)]
+struct Rebinding(Option<Action>);
+
+/// Text showing the value of a setting.
+#[derive(Component)]
+struct SettingText(Setting);
+
+/// Text showing the key bound to an action.
+#[derive(Component)]
+struct BindingText(Action);
+
+/// What a menu button does when clicked.
+#[derive(Component, Debug, Clone)]
+enum MenuButton {
+    Play,
+    Quit,
+    Options,
+    CloseOptions,
+    Adjust(Setting, i32),
+    Rebind(Action),
+    Load(SelectedWorld),
+    Focus(Field),
+    Create,
+    Back,
+    Resume,
+    SaveAndQuit,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+enum Field {
+    #[default]
+    Name,
+    Seed,
+}
+
+/// Text typed into the new world form.
+#[derive(Resource, Default)]
+struct WorldForm {
+    name: String,
+    /// Left empty for a random seed.
+    seed: String,
+    focus: Field,
+    error: Option<String>,
+}
+
+/// Text showing the value of a form field.
+#[derive(Component)]
+struct FieldText(Field);
+
+#[derive(Component)]
+struct FormError;
+
+fn root(state: impl Component, colour: Color) -> impl Bundle {
+    (
+        Node {
+            width: Val::Percent(100.0),
+            height: Val::Percent(100.0),
+            flex_direction: FlexDirection::Column,
+            justify_content: JustifyContent::Center,
+            align_items: AlignItems::Center,
+            row_gap: Val::Px(10.0),
+            ..default()
+        },
+        BackgroundColor(colour),
+        state,
+    )
+}
+
+fn label(text: impl Into<String>, size: f32) -> impl Bundle {
+    (Text::new(text), TextFont { font_size: size, ..default() }, TextColor(Color::WHITE))
+}
+
+fn spawn_button(parent: &mut ChildSpawnerCommands, text: impl Into<String>, action: MenuButton) {
+    parent
+        .spawn((
+            Button,
+            Node {
+                width: Val::Px(280.0),
+                padding: UiRect::all(Val::Px(8.0)),
+                justify_content: JustifyContent::Center,
+                ..default()
+            },
+            BackgroundColor(BUTTON),
+            action,
+        ))
+        .with_children(|button| {
+            button.spawn(label(text, TEXT_SIZE));
+        });
+}
+
+fn spawn_main_menu(mut commands: Commands) {
+    commands.spawn(root(StateScoped(GameState::MainMenu), BACKGROUND)).with_children(|menu| {
+        menu.spawn(label("terra", TITLE_SIZE));
+        spawn_button(menu, "Play", MenuButton::Play);
+        spawn_button(menu, "Options", MenuButton::Options);
+        spawn_button(menu, "Quit", MenuButton::Quit);
+    });
+}
+
+fn spawn_world_select(mut commands: Commands, mut form: ResMut<WorldForm>) {
+    *form = WorldForm::default();
+    let worlds = list_worlds();
+    commands.spawn(root(StateScoped(GameState::WorldSelect), BACKGROUND)).with_children(|menu| {
+        menu.spawn(label("Worlds", TITLE_SIZE));
+        if worlds.is_empty() {
+            menu.spawn(label("No saved worlds yet", TEXT_SIZE));
+        }
+        for world in worlds {
+            spawn_button(menu, format!("{} (seed {})", world.name, world.seed), MenuButton::Load(world));
+        }
+
+        menu.spawn(label("New world", TEXT_SIZE));
+        for field in [Field::Name, Field::Seed] {
+            menu.spawn((
+                Button,
+                Node { width: Val::Px(280.0), padding: UiRect::all(Val::Px(8.0)), ..default() },
+                BackgroundColor(BUTTON),
+                MenuButton::Focus(field),
+            ))
+            .with_children(|button| {
+                button.spawn((label("", TEXT_SIZE), FieldText(field)));
+            });
+        }
+        menu.spawn((Text::new(""), TextFont { font_size: TEXT_SIZE, ..default() }, TextColor(ERROR_TEXT), FormError));
+        spawn_button(menu, "Create", MenuButton::Create);
+        spawn_button(menu, "Back", MenuButton::Back);
+    });
+}
+
+fn spawn_pause_menu(mut commands: Commands) {
+    commands.spawn(root(StateScoped(Pause::Paused), PAUSE_BACKGROUND)).with_children(|menu| {
+        menu.spawn(label("Paused", TITLE_SIZE));
+        spawn_button(menu, "Resume", MenuButton::Resume);
+        spawn_button(menu, "Options", MenuButton::Options);
+        spawn_button(menu, "Save and quit", MenuButton::SaveAndQuit);
+    });
+}
+
+fn spawn_small_button(parent: &mut ChildSpawnerCommands, text: &str, action: MenuButton) {
+    parent
+        .spawn((
+            Button,
+            Node {
+                width: Val::Px(40.0),
+                padding: UiRect::all(Val::Px(8.0)),
+                justify_content: JustifyContent::Center,
+                ..default()
+            },
+            BackgroundColor(BUTTON),
+            action,
+        ))
+        .with_children(|button| {
+            button.spawn(label(text, TEXT_SIZE));
+        });
+}
+
+/// Drawn over whichever menu opened it.
+fn spawn_options(mut commands: Commands) {
+    commands
+        .spawn((root(StateScoped(Options::Open), BACKGROUND), GlobalZIndex(1)))
+        .with_children(|menu| {
+            menu.spawn(label("Options", TITLE_SIZE));
+            for setting in Setting::ALL {
+                menu.spawn(Node { column_gap: Val::Px(10.0), align_items: AlignItems::Center, ..default() })
+                    .with_children(|row| {
+                        spawn_small_button(row, "<", MenuButton::Adjust(setting, -1));
+                        row.spawn((
+                            label("", TEXT_SIZE),
+                            Node { width: Val::Px(260.0), justify_content: JustifyContent::Center, ..default() },
+                            SettingText(setting),
+                        ));
+                        spawn_small_button(row, ">", MenuButton::Adjust(setting, 1));
+                    });
+            }
+            for action in Action::ALL {
+                menu.spawn((
+                    Button,
+                    Node {
+                        width: Val::Px(280.0),
+                        padding: UiRect::all(Val::Px(8.0)),
+                        justify_content: JustifyContent::Center,
+                        ..default()
+                    },
+                    BackgroundColor(BUTTON),
+                    MenuButton::Rebind(action),
+                ))
+                .with_children(|button| {
+                    button.spawn((label("", TEXT_SIZE), BindingText(action)));
+                });
+            }
+            spawn_button(menu, "Back", MenuButton::CloseOptions);
+        });
+}
+
+fn show_settings(
+    settings: Res<Settings>,
+    rebinding: Res<Rebinding>,
+    mut values: Query<(&SettingText, &mut Text), Without<BindingText>>,
+    mut bindings: Query<(&BindingText, &mut Text), Without<SettingText>>,
+    added: Query<(), Added<SettingText>>,
+) {
+    if !settings.is_changed() && !rebinding.is_changed() && added.is_empty() {
+        return;
+    }
+    for (SettingText(setting), mut text) in &mut values {
+        text.0 = setting.describe(&settings);
+    }
+    for (BindingText(action), mut text) in &mut bindings {
+        text.0 = if rebinding.0 == Some(*action) {
+            format!("{}: press a key", action.name())
+        } else {
+            format!("{}: {:?}", action.name(), settings.keys.get(*action))
+        };
+    }
+}
+
+fn cancel_rebinding(mut rebinding: ResMut<Rebinding>) {
+    rebinding.0 = None;
+}
+
+/// Binds the next key pressed to the action being rebound. A key already in
+/// use swaps places with the old one, so bindings never clash.
+fn rebind_key(keyboard_input: Res<ButtonInput<KeyCode>>, mut rebinding: ResMut<Rebinding>, mut settings: ResMut<Settings>) {
+    let Some(action) = rebinding.0 else {
+        return;
+    };
+    let Some(key) = keyboard_input.get_just_pressed().next().copied() else {
+        return;
+    };
+    rebinding.0 = None;
+    let old = settings.keys.get(action);
+    if let Some(other) = Action::ALL.into_iter().find(|a| *a != action && settings.keys.get(*a) == key) {
+        settings.keys.set(other, old);
+    }
+    settings.keys.set(action, key);
+}
+
+fn colour_buttons(
+    form: Res<WorldForm>,
+    mut buttons: Query<(&Interaction, &MenuButton, &mut BackgroundColor), Changed<Interaction>>,
+) {
+    for (interaction, action, mut colour) in &mut buttons {
+        colour.0 = match interaction {
+            Interaction::Pressed => BUTTON_PRESSED,
+            Interaction::Hovered => BUTTON_HOVERED,
+            Interaction::None => match action {
+                MenuButton::Focus(field) if *field == form.focus => FOCUSED_FIELD,
+                _ => BUTTON,
+            },
+        };
+    }
+}
+
+fn press_buttons(
+    mut commands: Commands,
+    buttons: Query<(&Interaction, &MenuButton), Changed<Interaction>>,
+    mut form: ResMut<WorldForm>,
+    mut settings: ResMut<Settings>,
+    mut rebinding: ResMut<Rebinding>,
+    mut next_state: ResMut<NextState<GameState>>,
+    mut next_pause: ResMut<NextState<Pause>>,
+    mut next_options: ResMut<NextState<Options>>,
+    mut exit: EventWriter<AppExit>,
+) {
+    for (interaction, action) in &buttons {
+        if *interaction != Interaction::Pressed {
+            continue;
+        }
+        match action {
+            MenuButton::Play => next_state.set(GameState::WorldSelect),
+            MenuButton::Options => next_options.set(Options::Open),
+            MenuButton::CloseOptions => next_options.set(Options::Closed),
+            MenuButton::Adjust(setting, delta) => setting.adjust(&mut settings, *delta),
+            MenuButton::Rebind(action) => rebinding.0 = Some(*action),
+            MenuButton::Quit => {
+                exit.write(AppExit::Success);
+            }
+            MenuButton::Load(world) => {
+                commands.insert_resource(world.clone());
+                next_state.set(GameState::InGame);
+            }
+            MenuButton::Focus(field) => form.focus = *field,
+            MenuButton::Create => match create_world(&form) {
+                Ok(world) => {
+                    commands.insert_resource(world);
+                    next_state.set(GameState::InGame);
+                }
+                Err(err) => form.error = Some(err),
+            },
+            MenuButton::Back => next_state.set(GameState::MainMenu),
+            MenuButton::Resume => next_pause.set(Pause::Running),
+            // The world is saved on leaving the game.
+            MenuButton::SaveAndQuit => {
+                next_options.set(Options::Closed);
+                next_state.set(GameState::MainMenu);
+            }
+        }
+    }
+}
+
+/// Checks the form and writes the save of the new world.
+fn create_world(form: &WorldForm) -> Result<SelectedWorld, String> {
+    let name = form.name.trim().to_string();
+    if !valid_world_name(&name) {
+        return Err("Names use letters, digits, spaces, - and _".to_string());
+    }
+    if world_dir(&name).exists() {
+        return Err(format!("A world called {name} already exists"));
+    }
+    let seed = match form.seed.trim() {
+        "" => rand::random(),
+        text => text.parse().map_err(|_| format!("Seeds are whole numbers up to {}", u32::MAX))?,
+    };
+    write_world_save(&name, &WorldSave { seed, ..default() });
+    Ok(SelectedWorld { name, seed })
+}
+
+fn type_in_form(mut keys: EventReader<KeyboardInput>, mut form: ResMut<WorldForm>) {
+    for key in keys.read() {
+        if key.state != ButtonState::Pressed {
+            continue;
+        }
+        let focus = form.focus;
+        let text = match focus {
+            Field::Name => &mut form.name,
+            Field::Seed => &mut form.seed,
+        };
+        match &key.logical_key {
+            Key::Backspace => {
+                text.pop();
+            }
+            Key::Tab => {
+                form.focus = if focus == Field::Name { Field::Seed } else { Field::Name };
+            }
+            _ => {
+                let allowed = |c: &char| match focus {
+                    Field::Name => !c.is_control(),
+                    Field::Seed => c.is_ascii_digit(),
+                };
+                if let Some(typed) = &key.text {
+                    text.extend(typed.chars().filter(allowed));
+                }
+            }
+        }
+    }
+}
+
+fn show_form(
+    form: Res<WorldForm>,
+    mut fields: Query<(&FieldText, &mut Text), Without<FormError>>,
+    mut error: Query<&mut Text, With<FormError>>,
+    mut buttons: Query<(&MenuButton, &Interaction, &mut BackgroundColor)>,
+) {
+    if !form.is_changed() {
+        return;
+    }
+    for (FieldText(field), mut text) in &mut fields {
+        text.0 = match field {
+            Field::Name => format!("Name: {}", form.name),
+            Field::Seed if form.seed.is_empty() => "Seed: random".to_string(),
+            Field::Seed => format!("Seed: {}", form.seed),
+        };
+    }
+    for mut text in &mut error {
+        text.0 = form.error.clone().unwrap_or_default();
+    }
+    for (action, interaction, mut colour) in &mut buttons {
+        if let (MenuButton::Focus(field), Interaction::None) = (action, interaction) {
+            colour.0 = if *field == form.focus { FOCUSED_FIELD } else { BUTTON };
+        }
+    }
+}
+
+/// The pause key closes the options, pauses and resumes the game and backs
+/// out of world select.
+fn handle_escape(
+    keyboard_input: Res<ButtonInput<KeyCode>>,
+    settings: Res<Settings>,
+    rebinding: Res<Rebinding>,
+    state: Res<State<GameState>>,
+    pause: Option<Res<State<Pause>>>,
+    options: Res<State<Options>>,
+    mut next_state: ResMut<NextState<GameState>>,
+    mut next_pause: ResMut<NextState<Pause>>,
+    mut next_options: ResMut<NextState<Options>>,
+) {
+    // While rebinding, the key press belongs to `rebind_key`.
+    if rebinding.0.is_some() || !keyboard_input.just_pressed(settings.keys.pause) {
+        return;
+    }
+    if *options.get() == Options::Open {
+        next_options.set(Options::Closed);
+        return;
+    }
+    match (state.get(), pause.as_deref().map(State::get)) {
+        (GameState::InGame, Some(Pause::Running)) => next_pause.set(Pause::Paused),
+        (GameState::InGame, Some(Pause::Paused | Pause::Map | Pause::Console)) => next_pause.set(Pause::Running),
+        (GameState::WorldSelect, _) => next_state.set(GameState::MainMenu),
+        _ => {}
+    }
+}
@@ -1,103 +1,207 @@
+use std::path::PathBuf;
+
 use bevy::{
-    color::palettes::css::BLACK, prelude::*, render::{
-        camera::{RenderTarget, Viewport},
+    asset::io::file::FileAssetReader, color::palettes::css::BLACK, image::ImageSampler, prelude::*, render::{
+        camera::RenderTarget,
         render_resource::{
             Extent3d, TextureDescriptor, TextureDimension, TextureFormat, TextureUsages,
         },
         view::RenderLayers,
-    }, sprite::Anchor, window::WindowResized
+    }, window::{PrimaryWindow, WindowResized}
 };
 
-use crate::game::{player::{MyRoundGizmos, PlayerPlugin}, tilemap::{setup_map, spawn_tiles, TileMapPlugin}};
-use bevy_inspector_egui::{bevy_egui::EguiPlugin, quick::WorldInspectorPlugin};
+use crate::game::{background::BackgroundPlugin, clock::ClockPlugin, console::ConsolePlugin, cursor::CursorPlugin, debug::DebugPlugin, drops::DropsPlugin, enemy::EnemyPlugin, explosion::ExplosionPlugin, falling::FallingPlugin, health::HealthPlugin, hud::HudPlugin, item::ItemPlugin, lighting::LightingPlugin, liquid::LiquidPlugin, map::MapPlugin, melee::MeleePlugin, menu::MenuPlugin, pathfinding::PathfindingPlugin, projectile::ProjectilePlugin, replay::ReplayPlugin, save::SavePlugin, settings::{load_settings, Scaling, Settings, SettingsPlugin}, speedrun::SpeedrunPlugin, state::StatePlugin, player::{move_world, PlayerPlugin}, tilemap::TileMapPlugin};
 
-mod tilemap;
-mod player;
+pub mod tilemap;
+pub mod player;
+pub mod lighting;
+pub mod clock;
+mod save;
+mod debug;
+pub mod worldgen;
+pub mod liquid;
+mod health;
+mod falling;
+mod background;
+mod enemy;
+pub mod pathfinding;
+pub mod raycast;
+pub mod item;
+mod projectile;
+pub mod melee;
+mod drops;
+mod explosion;
+mod hud;
+mod state;
+mod menu;
+pub mod settings;
+pub mod cursor;
+mod map;
+mod speedrun;
+pub mod export;
+pub mod headless;
+pub mod replay;
+pub mod console;
 
+/// Size of one screen of the world, in pixels. The tile grid follows from
+/// it; the canvas can be given a smaller size in the [`Settings`].
 const RES_WIDTH: u32 = 320;
 const RES_HEIGHT: u32 = 180;
 const PIXEL_PERFECT_LAYERS: RenderLayers = RenderLayers::layer(0);
 const HIGH_RES_LAYERS: RenderLayers = RenderLayers::layer(1);
+/// Layer of the canvas copy blown up for [`Scaling::SharpBilinear`].
+const PRESCALE_LAYERS: RenderLayers = RenderLayers::layer(2);
 
 pub struct GamePlugin;
 
 impl Plugin for GamePlugin {
     fn build(&self, app: &mut App) {
-        app.add_plugins(DefaultPlugins.set(ImagePlugin::default_nearest()));
-		app.init_gizmo_group::<MyRoundGizmos>();
+        let settings = load_settings();
+        app.add_plugins(
+            DefaultPlugins
+                .set(ImagePlugin::default_nearest())
+                .set(WindowPlugin { primary_window: Some(settings.window()), ..default() }),
+        );
+        app.insert_resource(AssetDir::of(app));
+        app.insert_resource(settings);
+        app.add_plugins(SettingsPlugin);
+        app.add_plugins(SimulationPlugin);
+        app.add_plugins(MenuPlugin);
         app.add_systems(Startup, setup_camera);
-        //app.add_plugins(EguiPlugin::default());
-        //app.add_plugins(WorldInspectorPlugin::new());
+        app.add_systems(Update, (fit_canvas, place_view.after(move_world)).chain());
+        app.add_plugins(CursorPlugin);
+        app.add_plugins(LightingPlugin);
+        app.add_plugins(ClockPlugin);
+        app.add_plugins(SavePlugin);
+        app.add_plugins(DebugPlugin);
+        app.add_plugins(FallingPlugin);
+        app.add_plugins(BackgroundPlugin);
+        app.add_plugins(EnemyPlugin);
+        app.add_plugins(PathfindingPlugin);
+        app.add_plugins(ItemPlugin);
+        app.add_plugins(ProjectilePlugin);
+        app.add_plugins(MeleePlugin);
+        app.add_plugins(DropsPlugin);
+        app.add_plugins(ExplosionPlugin);
+        app.add_plugins(HudPlugin);
+        app.add_plugins(MapPlugin);
+        app.add_plugins(SpeedrunPlugin);
+        app.add_plugins(ConsolePlugin);
+    }
+}
+
+/// The world and the player, without anything that reads a window or a
+/// camera, so they also run under `MinimalPlugins`; see [`headless`].
+pub struct SimulationPlugin;
+
+impl Plugin for SimulationPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_plugins(StatePlugin);
         app.add_plugins(TileMapPlugin);
-        app.add_systems(Update, scale_canvas_on_resize);
+        app.init_resource::<ViewCentre>();
         app.add_plugins(PlayerPlugin);
+        app.add_plugins(LiquidPlugin);
+        app.add_plugins(HealthPlugin);
+        app.add_plugins(ReplayPlugin);
+    }
+}
+
+/// Directory the [`AssetPlugin`] loads from, for the data files that are
+/// read straight from disk.
+#[derive(Resource, Debug, Clone)]
+pub struct AssetDir(pub PathBuf);
+
+impl AssetDir {
+    /// Where the `app`'s asset plugin looks, wherever the game is started from.
+    pub fn of(app: &App) -> Self {
+        let file_path = match app.get_added_plugins::<AssetPlugin>().first() {
+            Some(plugin) => plugin.file_path.clone(),
+            None => AssetPlugin::default().file_path,
+        };
+        Self(FileAssetReader::get_base_path().join(file_path))
     }
 }
 
+/// Sprite showing the canvas in the window.
 #[derive(Component)]
-struct PixelatedCanvas;
+pub(crate) struct PixelatedCanvas;
 
-/// Camera that renders the pixel-perfect world to the [`Canvas`].
+/// Sprite drawing the canvas into the prescaled image.
 #[derive(Component)]
-struct InGameCamera;
+struct PrescaledCanvas;
 
-/// Camera that renders the [`Canvas`] (and other graphics on [`HIGH_RES_LAYERS`]) to the screen.
+/// Camera that renders the [`PrescaledCanvas`] for [`Scaling::SharpBilinear`].
 #[derive(Component)]
-struct OuterCamera;
+struct PrescaleCamera;
 
-/// Spawns a capsule mesh on the pixel-perfect layer.
-fn setup_mesh(
-    mut commands: Commands,
-    asset_server: Res<AssetServer>,
-) {
-	let mut sprite = Sprite::from_image(asset_server.load("block.png"));
-	sprite.anchor = Anchor::TopLeft;
-
-	commands.spawn((
-		sprite,
-		Transform::from_xyz(0., 0., 0.),
-		HIGH_RES_LAYERS,
-	));
+/// World position the view is centred on. [`move_world`] keeps it on the
+/// player within the loaded screen.
+///
+/// It need not be on a whole pixel: the [`InGameCamera`] stays on the pixel
+/// grid so world sprites do not shimmer, and the rest is made up by shifting
+/// the canvas in the window.
+#[derive(Resource, Debug, Clone, Copy, PartialEq)]
+pub struct ViewCentre(pub Vec2);
+
+impl Default for ViewCentre {
+    /// The middle of the loaded screen.
+    fn default() -> Self {
+        Self(Vec2::new(RES_WIDTH as f32, -(RES_HEIGHT as f32)) * 0.5)
+    }
 }
 
-fn setup_mesh2(
-    mut commands: Commands,
-	mut texture_atlas_layouts: ResMut<Assets<TextureAtlasLayout>>,
-    asset_server: Res<AssetServer>,
-) {
-	let layout = TextureAtlasLayout::from_grid(UVec2::splat(8), 2, 1, None, None);
-    let h_layout = texture_atlas_layouts.add(layout);
-	let texture = asset_server.load("block.png");
-
-    let mut sprite = Sprite {
-        image: texture.clone(),
-        texture_atlas: Some(TextureAtlas {
-            layout: h_layout.clone(),
-            index: 0,
-        }),
-        ..Default::default()
-    };
+/// Images the world is rendered to, recreated when the settings ask for
+/// another size.
+///
+/// The images are one pixel larger than the visible `size` each way, so
+/// there is always a pixel to scroll in when the view is between pixels.
+#[derive(Resource)]
+pub struct Canvas {
+    /// Target of the [`InGameCamera`].
+    pub image: Handle<Image>,
+    /// Visible part of the canvas, in canvas pixels.
+    pub size: UVec2,
+    /// The canvas blown up `prescale` times, with linear filtering.
+    pub prescaled: Handle<Image>,
+    pub prescale: u32,
+    /// How far right and down of the rendered image the view starts, from
+    /// 0 to 1 canvas pixels.
+    pub subpixel: Vec2,
+}
 
-    sprite.anchor = Anchor::TopLeft;
+impl Canvas {
+    pub fn size_f32(&self) -> Vec2 {
+        self.size.as_vec2()
+    }
 
-    commands.spawn((
-        sprite,
-        Transform::from_xyz(0., 0., 0.),
-        PIXEL_PERFECT_LAYERS,
-    ));
-}
+    /// Size of the rendered images, with the spare pixel.
+    pub fn image_size(&self) -> UVec2 {
+        self.size + UVec2::ONE
+    }
 
-#[derive(Component)]
-struct EguiCamera;
+    /// World position of a visible canvas pixel (top-left origin, y down)
+    /// with the [`InGameCamera`] at `camera`.
+    pub fn to_world(&self, canvas_px: Vec2, camera: &Transform) -> Vec2 {
+        let half = self.image_size().as_vec2() * 0.5;
+        let image_px = canvas_px + self.subpixel;
+        camera.translation.truncate() + Vec2::new(image_px.x - half.x, half.y - image_px.y)
+    }
+
+    /// Visible canvas pixel at a world position, the inverse of [`Canvas::to_world`].
+    pub fn to_canvas(&self, world: Vec2, camera: &Transform) -> Vec2 {
+        let half = self.image_size().as_vec2() * 0.5;
+        let local = world - camera.translation.truncate();
+        Vec2::new(local.x + half.x, half.y - local.y) - self.subpixel
+    }
+}
 
-fn setup_camera(mut commands: Commands, mut images: ResMut<Assets<Image>>) {
+/// Render target image of `size` pixels, cleared to zero.
+fn canvas_image(size: UVec2, sampler: ImageSampler) -> Image {
     let canvas_size = Extent3d {
-        width: RES_WIDTH,
-        height: RES_HEIGHT,
+        width: size.x,
+        height: size.y,
         ..default()
     };
-
-    // This Image serves as a canvas representing the low-resolution game screen
     let mut canvas = Image {
         texture_descriptor: TextureDescriptor {
             label: None,
@@ -111,16 +215,41 @@ fn setup_camera(mut commands: Commands, mut images: ResMut<Assets<Image>>) {
                 | TextureUsages::RENDER_ATTACHMENT,
             view_formats: &[],
         },
+        sampler,
         ..default()
     };
 
     // Fill image.data with zeroes
     canvas.resize(canvas_size);
+    canvas
+}
 
-    let image_handle = images.add(canvas);
+/// Camera that renders the pixel-perfect world to the [`Canvas`].
+#[derive(Component)]
+pub(crate) struct InGameCamera;
 
-    let half_width = (RES_WIDTH as f32) / 2.0;
-    let half_height = (RES_HEIGHT as f32) / 2.0;
+/// Camera that renders the [`Canvas`] (and other graphics on [`HIGH_RES_LAYERS`]) to the screen.
+#[derive(Component)]
+struct OuterCamera;
+
+fn setup_camera(
+    mut commands: Commands,
+    mut images: ResMut<Assets<Image>>,
+    settings: Res<Settings>,
+) {
+    let size = settings.canvas_size();
+
+    // This Image serves as a canvas representing the low-resolution game screen
+    let image_handle = images.add(canvas_image(size + UVec2::ONE, ImageSampler::Default));
+    // Filled in by `fit_canvas` once sharp bilinear scaling is picked.
+    let prescaled = images.add(canvas_image(size + UVec2::ONE, ImageSampler::linear()));
+    commands.insert_resource(Canvas {
+        image: image_handle.clone(),
+        size,
+        prescaled: prescaled.clone(),
+        prescale: 1,
+        subpixel: Vec2::ZERO,
+    });
 
     // The "outer" camera renders whatever is on `HIGH_RES_LAYERS` to the screen.
     // here, the canvas and one of the sample sprites will be rendered by this camera
@@ -128,10 +257,14 @@ fn setup_camera(mut commands: Commands, mut images: ResMut<Assets<Image>>) {
         Camera2d, 
         Camera {
             order: 0,
+            // Letterbox bars around the canvas.
+            clear_color: ClearColorConfig::Custom(BLACK.into()),
             ..default()
         },
         Msaa::Off, 
         OuterCamera, 
+        // Menus are drawn over the canvas by this camera.
+        IsDefaultUiCamera,
         HIGH_RES_LAYERS
     ));
 
@@ -140,41 +273,117 @@ fn setup_camera(mut commands: Commands, mut images: ResMut<Assets<Image>>) {
         Camera2d,
         Camera {
             // Render before the "main pass" camera
-            order: -1,
+            order: -2,
             target: RenderTarget::Image(image_handle.clone().into()),
             clear_color: ClearColorConfig::Custom(BLACK.into()),
             ..default()
         },
-        Transform::from_xyz(half_width, -half_height, 0.0), // shift camera
+        // Placed by `place_view`.
+        Transform::default(),
         Msaa::Off,
         InGameCamera,
         PIXEL_PERFECT_LAYERS,
     ));
 
-    // Spawn the canvas
+    // Blows the canvas up by a whole multiple between the two cameras above.
     commands.spawn((
-        Sprite::from_image(image_handle), 
-        Transform {
-            translation: Vec3::ZERO,
-            scale: Vec3::splat(4.0), // <-- SCALE FACTOR (integer to keep pixel perfect)
+        Camera2d,
+        Camera {
+            order: -1,
+            target: RenderTarget::Image(prescaled.into()),
+            is_active: false,
             ..default()
         },
+        Msaa::Off,
+        PrescaleCamera,
+        PRESCALE_LAYERS,
+    ));
+    commands.spawn((Sprite::from_image(image_handle.clone()), PrescaledCanvas, PRESCALE_LAYERS));
+
+    // Spawn the canvas; `fit_canvas` scales it to the window and
+    // `place_view` picks the visible part.
+    commands.spawn((
+        Sprite::from_image(image_handle), 
+        Transform::default(),
         PixelatedCanvas, 
         HIGH_RES_LAYERS
     ));
 
 }
 
-fn scale_canvas_on_resize(
+/// Recreates the canvas when the internal resolution changes and scales it
+/// to the window with the chosen [`Scaling`].
+fn fit_canvas(
     mut resize_events: EventReader<WindowResized>,
-    mut query: Query<&mut Transform, With<PixelatedCanvas>>,
+    settings: Res<Settings>,
+    window: Single<&Window, With<PrimaryWindow>>,
+    mut canvas: ResMut<Canvas>,
+    mut images: ResMut<Assets<Image>>,
+    mut in_game_camera: Single<&mut Camera, (With<InGameCamera>, Without<PrescaleCamera>)>,
+    mut prescale_camera: Single<&mut Camera, (With<PrescaleCamera>, Without<InGameCamera>)>,
+    mut prescaled_sprite: Single<(&mut Sprite, &mut Transform), (With<PrescaledCanvas>, Without<PixelatedCanvas>)>,
+    mut canvas_sprite: Single<(&mut Sprite, &mut Transform), (With<PixelatedCanvas>, Without<PrescaledCanvas>)>,
 ) {
-    for event in resize_events.read() {
-        let h_scale = (event.width / RES_WIDTH as f32).floor();
-        let v_scale = (event.height / RES_HEIGHT as f32).floor();
-        let scale = h_scale.min(v_scale).max(1.0); // at least 1
-        for mut transform in &mut query {
-            transform.scale = Vec3::splat(scale);
-        }
+    if resize_events.read().last().is_none() && !settings.is_changed() {
+        return;
     }
-}
\ No newline at end of file
+    let size = settings.canvas_size();
+    let scale = settings.canvas_scale_for(window.size(), size.as_vec2());
+    let prescale = settings.prescale_for(scale);
+    let sharp = settings.scaling == Scaling::SharpBilinear;
+
+    if canvas.size != size {
+        canvas.size = size;
+        canvas.image = images.add(canvas_image(canvas.image_size(), ImageSampler::Default));
+        // The prescaled copy has to follow.
+        canvas.prescale = 0;
+        in_game_camera.target = RenderTarget::Image(canvas.image.clone().into());
+        prescaled_sprite.0.image = canvas.image.clone();
+    }
+    if sharp && canvas.prescale != prescale {
+        canvas.prescaled = images.add(canvas_image(canvas.image_size() * prescale, ImageSampler::linear()));
+        canvas.prescale = prescale;
+        prescale_camera.target = RenderTarget::Image(canvas.prescaled.clone().into());
+        prescaled_sprite.1.scale = Vec3::splat(prescale as f32);
+    }
+    prescale_camera.is_active = sharp;
+
+    // The sprite is always one unit per canvas pixel, whatever image it
+    // shows, so picking and the HUD only need its scale.
+    let (sprite, transform) = &mut *canvas_sprite;
+    sprite.image = if sharp { canvas.prescaled.clone() } else { canvas.image.clone() };
+    sprite.custom_size = Some(size.as_vec2());
+    transform.scale = scale.extend(1.0);
+}
+
+/// Puts the [`InGameCamera`] on the pixel grid just up and left of the view
+/// and shows the visible part of the canvas, shifted by the remainder. World
+/// sprites stay pixel-aligned on the canvas while the view scrolls smoothly.
+pub(crate) fn place_view(
+    view: Res<ViewCentre>,
+    mut canvas: ResMut<Canvas>,
+    mut camera: Single<&mut Transform, With<InGameCamera>>,
+    mut canvas_sprite: Single<&mut Sprite, With<PixelatedCanvas>>,
+) {
+    let size = canvas.size_f32();
+    // Top-left corner of the view, y growing down.
+    let top_left = Vec2::new(view.0.x, -view.0.y) - size * 0.5;
+    let snapped = top_left.floor();
+    let subpixel = top_left - snapped;
+    if canvas.subpixel != subpixel {
+        canvas.subpixel = subpixel;
+    }
+
+    let image = canvas.image_size().as_vec2();
+    let centre = Vec2::new(snapped.x + image.x * 0.5, -(snapped.y + image.y * 0.5));
+    if camera.translation.truncate() != centre {
+        camera.translation = centre.extend(camera.translation.z);
+    }
+
+    // The prescaled copy has `prescale` texels per canvas pixel.
+    let texel = if canvas_sprite.image == canvas.prescaled { canvas.prescale as f32 } else { 1.0 };
+    let rect = Rect::from_corners(subpixel * texel, (subpixel + size) * texel);
+    if canvas_sprite.rect != Some(rect) {
+        canvas_sprite.rect = Some(rect);
+    }
+}
@@ -0,0 +1,306 @@
+//! Route finding for agents that walk, jump and fall over the tile grid.
+//!
+//! Everything here works on global tile coordinates through a `solid` query,
+//! so it runs without an `App` and can be driven from benchmarks or tests with
+//! any closure. [`NavCache`] keeps one navigation graph per screen-sized chunk
+//! and drops chunks whose tiles change.
+
+use std::{
+    cmp::Reverse,
+    collections::{BinaryHeap, HashMap},
+};
+
+use bevy::{ecs::system::SystemParam, prelude::*};
+
+use crate::game::{
+    state::{GameState, GameplaySet},
+    tilemap::{TileChanged, TileLayer, TileMap, CHUNK_COLS, CHUNK_ROWS, TILE_SIZE},
+    worldgen::WorldGen,
+};
+
+/// Most nodes a single query expands before giving up.
+const MAX_EXPANDED: usize = 20_000;
+
+pub struct PathfindingPlugin;
+
+impl Plugin for PathfindingPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<NavCache>();
+        app.add_systems(Update, invalidate_nav_cache.in_set(GameplaySet));
+        app.add_systems(OnExit(GameState::InGame), clear_nav_cache);
+    }
+}
+
+/// Movement abilities of an agent, in tiles.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct AgentParams {
+    /// Height of the agent; its width is always one tile.
+    pub height: i32,
+    /// Highest ledge it can jump onto.
+    pub jump_height: i32,
+    /// Widest horizontal distance covered by a jump.
+    pub jump_distance: i32,
+    /// Longest drop it is willing to take.
+    pub max_fall: i32,
+}
+
+impl AgentParams {
+    /// Derives jump reach from physics values in pixels and seconds.
+    pub fn from_physics(height: i32, run_speed: f32, jump_speed: f32, gravity: f32, max_fall: i32) -> Self {
+        let tile = TILE_SIZE as f32;
+        let gravity = gravity.abs().max(1.0);
+        let rise = jump_speed * jump_speed / (2.0 * gravity);
+        let airtime = 2.0 * jump_speed / gravity;
+        Self {
+            height,
+            jump_height: (rise / tile).floor() as i32,
+            jump_distance: (run_speed * airtime / tile).floor() as i32,
+            max_fall,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NavAction {
+    /// Walk to the neighbouring cell on the same row.
+    Walk,
+    /// Jump from the current cell and land on the target.
+    Jump,
+    /// Step off a ledge and drop onto the target.
+    Fall,
+}
+
+/// One move of a route: perform `action` to end up standing in `to`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct NavStep {
+    pub action: NavAction,
+    pub to: IVec2,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Edge {
+    to: IVec2,
+    action: NavAction,
+    cost: u32,
+}
+
+/// Walk/jump/fall edges leaving every standable cell of one chunk.
+#[derive(Debug, Default)]
+pub struct ChunkGraph {
+    edges: HashMap<IVec2, Vec<Edge>>,
+}
+
+/// Chunk whose screen loads global cell `g`; the overlapping edge columns
+/// belong to the chunk they come first in.
+pub fn chunk_of(g: IVec2) -> IVec2 {
+    IVec2::new((g.x + 1).div_euclid(CHUNK_COLS), (g.y + 1).div_euclid(CHUNK_ROWS))
+}
+
+/// Whether the agent can stand with its feet in `g`.
+pub fn standable(solid: &impl Fn(IVec2) -> bool, params: &AgentParams, g: IVec2) -> bool {
+    solid(g + IVec2::Y) && clear(solid, params, g)
+}
+
+/// Whether the agent's body fits with its feet in `g`.
+fn clear(solid: &impl Fn(IVec2) -> bool, params: &AgentParams, g: IVec2) -> bool {
+    (0..params.height).all(|k| !solid(g - IVec2::Y * k))
+}
+
+/// Whether a jump from `from` to `to` has room: up the starting column, across
+/// at the apex, then down into the landing cell.
+///
+/// The apex is one row above the higher end, so the agent clears the ledge it
+/// lands on, unless that is beyond its jump; it then crosses at the highest row
+/// it reaches, which still has to be level with the landing.
+fn jump_clear(solid: &impl Fn(IVec2) -> bool, params: &AgentParams, from: IVec2, to: IVec2) -> bool {
+    let apex = (from.y.min(to.y) - 1).max(from.y - params.jump_height.max(1));
+    if to.y < apex {
+        return false;
+    }
+    let step = (to.x - from.x).signum();
+    (apex..from.y).all(|y| clear(solid, params, IVec2::new(from.x, y)))
+        && (1..=(to.x - from.x).abs()).all(|i| clear(solid, params, IVec2::new(from.x + i * step, apex)))
+        && (apex..to.y).all(|y| clear(solid, params, IVec2::new(to.x, y)))
+}
+
+/// Edges leaving the standable cell `from`.
+fn edges_from(solid: &impl Fn(IVec2) -> bool, params: &AgentParams, from: IVec2) -> Vec<Edge> {
+    let mut edges = Vec::new();
+
+    for side in [-1, 1] {
+        let next = from + IVec2::new(side, 0);
+        if standable(solid, params, next) {
+            edges.push(Edge { to: next, action: NavAction::Walk, cost: 1 });
+        } else if clear(solid, params, next) {
+            let mut landing = next;
+            for _ in 0..params.max_fall {
+                landing += IVec2::Y;
+                if !clear(solid, params, landing) {
+                    break;
+                }
+                if standable(solid, params, landing) {
+                    let drop = (landing.y - from.y) as u32;
+                    edges.push(Edge { to: landing, action: NavAction::Fall, cost: 1 + drop });
+                    break;
+                }
+            }
+        }
+    }
+
+    for dx in -params.jump_distance..=params.jump_distance {
+        for dy in -params.jump_height..=params.max_fall {
+            // Adjacent cells on the same row are walks.
+            if (dx.abs() <= 1 && dy == 0) || (dx == 0 && dy >= 0) {
+                continue;
+            }
+            let to = from + IVec2::new(dx, dy);
+            if standable(solid, params, to) && jump_clear(solid, params, from, to) {
+                edges.push(Edge { to, action: NavAction::Jump, cost: (dx.abs() + dy.abs()) as u32 + 2 });
+            }
+        }
+    }
+
+    edges
+}
+
+/// Builds the navigation graph of one chunk.
+pub fn build_chunk(solid: &impl Fn(IVec2) -> bool, params: &AgentParams, chunk: IVec2) -> ChunkGraph {
+    let origin = IVec2::new(chunk.x * CHUNK_COLS - 1, chunk.y * CHUNK_ROWS - 1);
+    let mut graph = ChunkGraph::default();
+    for y in 0..CHUNK_ROWS {
+        for x in 0..CHUNK_COLS {
+            let g = origin + IVec2::new(x, y);
+            if standable(solid, params, g) {
+                graph.edges.insert(g, edges_from(solid, params, g));
+            }
+        }
+    }
+    graph
+}
+
+/// Whether building the graph of `chunk` for `params` looks at cell `g`.
+///
+/// Edges leave the chunk by up to a jump sideways, a jump and the agent's
+/// height upward and a fall and the floor under it downward.
+fn reads(params: &AgentParams, chunk: IVec2, g: IVec2) -> bool {
+    let origin = IVec2::new(chunk.x * CHUNK_COLS - 1, chunk.y * CHUNK_ROWS - 1);
+    let side = params.jump_distance.max(1);
+    let left = origin.x - side;
+    let right = origin.x + CHUNK_COLS - 1 + side;
+    let top = origin.y - params.jump_height.max(1) - params.height + 1;
+    let bottom = origin.y + CHUNK_ROWS - 1 + params.max_fall.max(0) + 1;
+    (left..=right).contains(&g.x) && (top..=bottom).contains(&g.y)
+}
+
+/// Navigation graphs built so far, per agent type and chunk.
+#[derive(Resource, Default)]
+pub struct NavCache {
+    chunks: HashMap<(AgentParams, IVec2), ChunkGraph>,
+}
+
+impl NavCache {
+    /// Forgets the graphs that may contain edges through `g`.
+    pub fn invalidate(&mut self, g: IVec2) {
+        self.chunks.retain(|(params, chunk), _| !reads(params, *chunk, g));
+    }
+
+    pub fn clear(&mut self) {
+        self.chunks.clear();
+    }
+
+    fn edges(&mut self, solid: &impl Fn(IVec2) -> bool, params: &AgentParams, g: IVec2) -> &[Edge] {
+        let chunk = chunk_of(g);
+        let graph = self
+            .chunks
+            .entry((*params, chunk))
+            .or_insert_with(|| build_chunk(solid, params, chunk));
+        graph.edges.get(&g).map_or(&[], |edges| edges.as_slice())
+    }
+
+    /// Cheapest route from `start` to `goal`, both feet cells in global tile
+    /// coordinates. Returns an empty route when already there and `None` when
+    /// the goal cannot be reached.
+    pub fn find_path(
+        &mut self,
+        solid: &impl Fn(IVec2) -> bool,
+        params: &AgentParams,
+        start: IVec2,
+        goal: IVec2,
+    ) -> Option<Vec<NavStep>> {
+        if !standable(solid, params, start) || !standable(solid, params, goal) {
+            return None;
+        }
+        let heuristic = |g: IVec2| ((g.x - goal.x).abs() + (g.y - goal.y).abs()) as u32;
+
+        let mut open = BinaryHeap::new();
+        let mut cost: HashMap<IVec2, u32> = HashMap::new();
+        let mut came_from: HashMap<IVec2, (IVec2, NavAction)> = HashMap::new();
+        cost.insert(start, 0);
+        open.push(Reverse((heuristic(start), 0, (start.x, start.y))));
+
+        let mut expanded = 0;
+        while let Some(Reverse((_, queued_cost, (x, y)))) = open.pop() {
+            let current = IVec2::new(x, y);
+            // A cheaper way here was found after this entry was queued.
+            if queued_cost > cost[&current] {
+                continue;
+            }
+            if current == goal {
+                let mut steps = Vec::new();
+                let mut at = goal;
+                while let Some(&(prev, action)) = came_from.get(&at) {
+                    steps.push(NavStep { action, to: at });
+                    at = prev;
+                }
+                steps.reverse();
+                return Some(steps);
+            }
+            expanded += 1;
+            if expanded > MAX_EXPANDED {
+                return None;
+            }
+
+            let current_cost = queued_cost;
+            let edges = self.edges(solid, params, current).to_vec();
+            for edge in edges {
+                let next_cost = current_cost + edge.cost;
+                if cost.get(&edge.to).is_none_or(|&c| next_cost < c) {
+                    cost.insert(edge.to, next_cost);
+                    came_from.insert(edge.to, (current, edge.action));
+                    open.push(Reverse((next_cost + heuristic(edge.to), next_cost, (edge.to.x, edge.to.y))));
+                }
+            }
+        }
+        None
+    }
+}
+
+/// Pathfinding over the live world for use inside systems.
+#[derive(SystemParam)]
+pub struct Pathfinder<'w> {
+    cache: ResMut<'w, NavCache>,
+    tilemap: Res<'w, TileMap>,
+    worldgen: Res<'w, WorldGen>,
+}
+
+impl Pathfinder<'_> {
+    pub fn find_path(&mut self, params: &AgentParams, start: IVec2, goal: IVec2) -> Option<Vec<NavStep>> {
+        let tilemap = &self.tilemap;
+        let worldgen = &self.worldgen;
+        let solid = |g: IVec2| tilemap.solid_global(worldgen, g);
+        self.cache.find_path(&solid, params, start, goal)
+    }
+}
+
+fn invalidate_nav_cache(mut events: EventReader<TileChanged>, mut cache: ResMut<NavCache>) {
+    for change in events.read() {
+        if change.layer == TileLayer::Foreground {
+            cache.invalidate(change.pos);
+        }
+    }
+}
+
+/// Graphs belong to the world being left.
+fn clear_nav_cache(mut cache: ResMut<NavCache>) {
+    cache.clear();
+}
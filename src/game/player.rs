@@ -1,15 +1,33 @@
-use bevy::{color::palettes::css::{GREEN, RED, WHITE}, prelude::*, sprite::Anchor};
-use noise::{NoiseFn, Perlin, Seedable};
-use rand::Rng;
+use bevy::{color::palettes::css::{GREEN, RED}, prelude::*, sprite::Anchor};
 
-use crate::game::{tilemap::{Tile, TileMap, COLS, ROWS, TCOLS, TILE_SIZE, TROWS}, PixelatedCanvas, PIXEL_PERFECT_LAYERS, RES_HEIGHT, RES_WIDTH};
+use crate::game::{console::{AddConsoleCommand, Args, CommandResult, ConsoleCommand, Param, ParamKind}, pathfinding::chunk_of, health::{Died, Health, Hitbox, Knockback}, settings::Settings, speedrun::disqualify_run, state::{GameState, GameplaySet}, item::Inventory, liquid::{LiquidMap, MAX_LEVEL}, tilemap::{change_screen, respawn_tile_sprites, screen_origin, ScreenChanged, TileMap, CHUNK_COLS, CHUNK_ROWS, TCOLS, TILE_SIZE, TROWS}, worldgen::WorldGen, ViewCentre, PIXEL_PERFECT_LAYERS, RES_HEIGHT, RES_WIDTH};
 
 pub struct PlayerPlugin;
 
 impl Plugin for PlayerPlugin {
     fn build(&self, app: &mut App) {
-        app.add_systems(Startup, setup);
-		app.add_systems(Update, (update_player,move_world ).chain());
+		app.init_gizmo_group::<MyRoundGizmos>();
+        app.add_systems(OnEnter(GameState::InGame), setup);
+		app.add_systems(Update, (update_player,move_world ).chain().in_set(GameplaySet));
+		app.add_systems(Update, respawn_on_death.in_set(GameplaySet));
+        app.add_console_command(ConsoleCommand {
+            name: "tp",
+            help: "moves the player to a screen",
+            params: vec![Param::new("x", ParamKind::Int), Param::new("y", ParamKind::Int)],
+            run: run_tp,
+        });
+        app.add_console_command(ConsoleCommand {
+            name: "tp_tile",
+            help: "moves the player to a global tile",
+            params: vec![Param::new("x", ParamKind::Int), Param::new("y", ParamKind::Int)],
+            run: run_tp_tile,
+        });
+        app.add_console_command(ConsoleCommand {
+            name: "noclip",
+            help: "lets the player fly through tiles",
+            params: vec![],
+            run: run_noclip,
+        });
     }
 }
 
@@ -17,7 +35,7 @@ impl Plugin for PlayerPlugin {
 #[derive(Default, Reflect, GizmoConfigGroup)]
 pub struct MyRoundGizmos {}
 
-#[derive(Component)]
+#[derive(Component, Debug, Clone)]
 pub struct Player {
 	pub speed: f32,
 	pub remainder: Vec2,
@@ -26,8 +44,31 @@ pub struct Player {
 	pub on_ground: bool,
 	pub inside: bool,
 	pub was_inside: bool,
+	pub submerged: bool,
+	/// -1 when facing left, 1 when facing right.
+	pub facing: f32,
+	/// Flies through tiles, ignoring gravity; toggled from the console.
+	pub noclip: bool,
 }
 
+/// Health the player starts with.
+pub const PLAYER_HEALTH: u32 = 100;
+/// Where the player appears on the loaded screen after dying.
+const SPAWN_POINT: Vec3 = Vec3::new(200., -100.0, 0.0);
+
+/// Horizontal speed multiplier while submerged.
+const SWIM_SLOWDOWN: f32 = 0.5;
+/// Gravity multiplier while submerged.
+const SWIM_GRAVITY: f32 = 0.25;
+/// Upward speed while holding jump underwater.
+const SWIM_SPEED: f32 = 80.0;
+/// Fastest sinking speed underwater.
+const SINK_SPEED: f32 = 60.0;
+/// Flying speed with noclip on.
+const NOCLIP_SPEED: f32 = 200.0;
+/// How quickly the view catches up with the player, per second.
+const VIEW_FOLLOW: f32 = 8.0;
+
 fn setup(
 	mut commands: Commands,
     asset_server: Res<AssetServer>,
@@ -35,52 +76,136 @@ fn setup(
 	let mut sprite = Sprite::from_image(asset_server.load("player.png"));
 	sprite.anchor = Anchor::TopLeft;
 	commands.spawn((
-		Player{speed:100.0, remainder: Vec2::ZERO, gravity: 90., velocity: Vec2::ZERO, on_ground: false,  inside: true, was_inside: true},
+		Player{speed:100.0, remainder: Vec2::ZERO, gravity: 90., velocity: Vec2::ZERO, on_ground: false,  inside: true, was_inside: true, submerged: false, facing: 1., noclip: false},
+		Health::new(PLAYER_HEALTH),
+		Hitbox { size: Vec2::splat(8.) },
+		Knockback::default(),
+		Inventory::starter(),
 		sprite,
-		Transform::from_xyz(200., -100.0, 0.0),
+		Transform::from_translation(SPAWN_POINT),
         PIXEL_PERFECT_LAYERS,
+        StateScoped(GameState::InGame),
 	));
 }
 
+fn respawn_on_death(
+    mut died: EventReader<Died>,
+    tilemap: Res<TileMap>,
+    mut player_query: Query<(&mut Player, &mut Health, &mut Transform)>,
+) {
+    for Died(entity) in died.read() {
+        let Ok((mut player, mut health, mut transform)) = player_query.get_mut(*entity) else {
+            continue;
+        };
+        health.current = health.max;
+        transform.translation = free_spot_above(&tilemap, SPAWN_POINT.truncate()).extend(SPAWN_POINT.z);
+        player.velocity = Vec2::ZERO;
+        player.remainder = Vec2::ZERO;
+    }
+}
+
+/// Whether the player, with its top-left corner at `pos`, overlaps a solid tile.
+fn blocked_at(tilemap: &TileMap, pos: Vec2) -> bool {
+    let far = TILE_SIZE as f32 - 1.;
+    [Vec2::ZERO, Vec2::new(far, 0.), Vec2::new(0., -far), Vec2::new(far, -far)]
+        .iter()
+        .any(|corner| tilemap.collide_at(pos + *corner))
+}
+
+/// First position at or above `pos`, a tile at a time, where the player is
+/// clear of tiles; `pos` itself if the screen is solid all the way up.
+fn free_spot_above(tilemap: &TileMap, pos: Vec2) -> Vec2 {
+    (0..TROWS)
+        .map(|rows| pos + Vec2::Y * (rows as u32 * TILE_SIZE) as f32)
+        .find(|p| !blocked_at(tilemap, *p))
+        .unwrap_or(pos)
+}
+
 fn update_player(
     tilemap: ResMut<TileMap>,
-    mut player_query: Query<(&mut Player, &mut Transform)>,
+    liquids: Res<LiquidMap>,
+    mut player_query: Query<(&mut Player, &mut Transform, &mut Knockback)>,
 	keyboard_input: Res<ButtonInput<KeyCode>>,
+	settings: Res<Settings>,
 	time: Res<Time>,
-	mut gizmos: Gizmos,
-    mut my_gizmos: Gizmos<MyRoundGizmos>,
 ) {
-    let (mut player, mut transform) = player_query.single_mut().unwrap();
+    let (mut player, mut transform, mut knockback) = player_query.single_mut().unwrap();
 	let mut player_pos = transform.translation;
 	let mut offset = Vec2::ZERO;
 	let mut dir = Vec2::ZERO;
 	
 
-	if keyboard_input.pressed(KeyCode::KeyD) {
+	if keyboard_input.pressed(settings.keys.right) {
 		dir.x = 1.;
 		offset.x = 8.;
     }
-	if keyboard_input.pressed(KeyCode::KeyA) {
+	if keyboard_input.pressed(settings.keys.left) {
 		dir.x = -1.;
     }
-	if keyboard_input.pressed(KeyCode::KeyW) {
+	if keyboard_input.pressed(settings.keys.up) {
 		//dir.y = 1.;
     }
-	if keyboard_input.pressed(KeyCode::KeyS) {
+	if keyboard_input.pressed(settings.keys.down) {
 		//dir.y = -1.;
 		//offset.y = 8.;
     }
 
-	player.velocity.x = dir.x * player.speed;
+	// --- Liquid at the player's centre ---
+	let centre = tilemap.cell_at(Vec2::new(player_pos.x + 4., player_pos.y - 4.));
+	let liquid_level = liquids.level_at(tilemap.origin() + centre);
+	player.submerged = liquid_level >= MAX_LEVEL / 2;
+
+	if dir.x != 0. {
+		player.facing = dir.x;
+	}
+
+	// --- Noclip: fly anywhere, up or jump to rise and down to sink ---
+	if player.noclip {
+		if keyboard_input.pressed(settings.keys.jump) || keyboard_input.pressed(settings.keys.up) {
+			dir.y = 1.;
+		} else if keyboard_input.pressed(settings.keys.down) {
+			dir.y = -1.;
+		}
+		knockback.take();
+		player.velocity = dir * NOCLIP_SPEED;
+		player.on_ground = false;
+		let p_vel = player.velocity * time.delta_secs();
+		player.remainder += p_vel;
+		let mov = player.remainder.round();
+		player.remainder -= mov;
+		transform.translation += mov.extend(0.);
+		return;
+	}
+
+	// A hit takes away horizontal control for a moment.
+	if !knockback.active() {
+		player.velocity.x = dir.x * player.speed;
+		if player.submerged {
+			player.velocity.x *= SWIM_SLOWDOWN;
+		}
+	}
+	if let Some(push) = knockback.take() {
+		player.velocity = push;
+		player.on_ground = false;
+	}
 	//player.velocity.y = dir.y * player.speed;
 	let dt = time.delta_secs();
 	// --- Gravity ---
     let gravity = -1000.0; // downward acceleration
 	
-    player.velocity.y += gravity * dt;
+    if player.submerged {
+        player.velocity.y += gravity * SWIM_GRAVITY * dt;
+        player.velocity.y = player.velocity.y.max(-SINK_SPEED);
+        // --- Swim ---
+        if keyboard_input.pressed(settings.keys.jump) {
+            player.velocity.y = SWIM_SPEED;
+        }
+    } else {
+        player.velocity.y += gravity * dt;
+    }
 
 	 // --- Jump ---
-    if player.on_ground && keyboard_input.just_pressed(KeyCode::Space) {
+    if player.on_ground && keyboard_input.just_pressed(settings.keys.jump) {
         player.velocity.y = 300.0; // jump strength
 		player.on_ground = false;
 		println!("_______________________________________________________")
@@ -97,6 +222,8 @@ fn update_player(
 	if signy < 0. { 
 		offset.y = -8.;
 	}
+	// Knockback can move the player against the pressed key.
+	offset.x = if signx > 0. { 8. } else { 0. };
 
 	// right
 	// |-----x
@@ -148,6 +275,44 @@ fn update_player(
 	transform.translation = player_pos;
 }
 
+/// Loads `screen` and puts the player at `position` on it, at rest.
+fn teleport(world: &mut World, screen: IVec2, position: Vec2) -> Result<(), String> {
+    disqualify_run(world);
+    change_screen(world, screen);
+    let mut query = world.query::<(&mut Player, &mut Transform)>();
+    let (mut player, mut transform) = query.single_mut(world).map_err(|_| "there is no player".to_string())?;
+    transform.translation = position.extend(transform.translation.z);
+    player.velocity = Vec2::ZERO;
+    player.remainder = Vec2::ZERO;
+    Ok(())
+}
+
+fn run_tp(world: &mut World, args: &Args) -> CommandResult {
+    let screen = IVec2::new(args.int(0), args.int(1));
+    teleport(world, screen, SPAWN_POINT.truncate())?;
+    Ok(format!("moved to screen {}, {}", screen.x, screen.y))
+}
+
+/// Puts the top-left of the player in the tile, on the screen that owns it.
+fn run_tp_tile(world: &mut World, args: &Args) -> CommandResult {
+    let g = IVec2::new(args.int(0), args.int(1));
+    let screen = chunk_of(g);
+    let local = g - screen_origin(screen);
+    let tile = TILE_SIZE as f32;
+    teleport(world, screen, Vec2::new(local.x as f32 * tile, -local.y as f32 * tile))?;
+    Ok(format!("moved to tile {}, {} on screen {}, {}", g.x, g.y, screen.x, screen.y))
+}
+
+fn run_noclip(world: &mut World, _: &Args) -> CommandResult {
+    let mut query = world.query::<&mut Player>();
+    let mut player = query.single_mut(world).map_err(|_| "there is no player".to_string())?;
+    player.noclip = !player.noclip;
+    player.velocity = Vec2::ZERO;
+    let message = format!("noclip {}", if player.noclip { "on" } else { "off" });
+    disqualify_run(world);
+    Ok(message)
+}
+
 pub fn draw_point(gizmos: &mut Gizmos, pos: Vec3) {
 	gizmos.rect_2d(    
 		Isometry2d::new(Vec2::new(pos.x, pos.y), Rot2::radians(0.0)), 
@@ -164,11 +329,24 @@ pub fn draw_point_red(gizmos: &mut Gizmos, pos: Vec3) {
 	);
 }
 
+/// Where the view centres to show the player centred at `centre` on a
+/// `canvas` sized canvas: on the player, but never past the edges of the
+/// loaded screen.
+fn view_target(centre: Vec2, canvas: Vec2) -> Vec2 {
+    let screen = Vec2::new(RES_WIDTH as f32, RES_HEIGHT as f32);
+    let half = (canvas * 0.5).min(screen * 0.5);
+    Vec2::new(centre.x.clamp(half.x, screen.x - half.x), -(-centre.y).clamp(half.y, screen.y - half.y))
+}
+
 pub fn move_world(
     mut commands: Commands,
     mut tilemap: ResMut<TileMap>,
     mut player_query: Query<(&mut Transform, &mut Player)>,
-    asset_server: Res<AssetServer>,
+    worldgen: Res<WorldGen>,
+    mut screen_changed: EventWriter<ScreenChanged>,
+    settings: Res<Settings>,
+    mut view: ResMut<ViewCentre>,
+    time: Res<Time>,
 	mut gizmos: Gizmos,
 ) {
 	let mut dir = Vec2::ZERO;
@@ -177,8 +355,8 @@ pub fn move_world(
 
     let mut player_pos = transform.translation;
 
-    let chunk_width = 39.0 * TILE_SIZE as f32;
-    let chunk_height = 22.0 * TILE_SIZE as f32;
+    let chunk_width = CHUNK_COLS as f32 * TILE_SIZE as f32;
+    let chunk_height = CHUNK_ROWS as f32 * TILE_SIZE as f32;
     let player_width = 8.0; // adjust to your sprite width / 2
 
     if player.inside {
@@ -214,14 +392,25 @@ pub fn move_world(
         }
     }
     player_pos = transform.translation;
-    player.inside = player_pos.x >= 8. && player_pos.x < 39.0 * TILE_SIZE as f32 &&
-					player_pos.y <= -8. && player_pos.y > -22.0 * TILE_SIZE as f32;
+    player.inside = player_pos.x >= 8. && player_pos.x < CHUNK_COLS as f32 * TILE_SIZE as f32 &&
+					player_pos.y <= -8. && player_pos.y > -(CHUNK_ROWS as f32) * TILE_SIZE as f32;
 
     if player.inside {
 		draw_point(&mut gizmos,Vec3::new(player_pos.x, player_pos.y, 0.));
     } else {
 		draw_point_red(&mut gizmos,Vec3::new(player_pos.x, player_pos.y, 0.));
     }
+
+    // Glide after the player, but cut straight to a new screen.
+    let target = view_target(player_pos.truncate() + Vec2::new(4., -4.), settings.canvas_size().as_vec2());
+    let follow = 1.0 - (-VIEW_FOLLOW * time.delta_secs()).exp();
+    let mut centre = if dir == Vec2::ZERO { view.0.lerp(target, follow) } else { target };
+    if centre.distance(target) < 0.01 {
+        centre = target;
+    }
+    if view.0 != centre {
+        view.0 = centre;
+    }
     
     if dir.x == 0. && dir.y == 0. {
         return;
@@ -229,57 +418,7 @@ pub fn move_world(
 
     tilemap.position += dir;
 
-    let mut rng = rand::thread_rng();
-    let perlin = Perlin::new(1);
-	let texture = asset_server.load("block.png");
-
-    let gx1 = (tilemap.position.x * 40. -1.) as f64 * 0.1;
-    let gx2 = (tilemap.position.x * 40. + COLS as f32) as f64 * 0.1;
-	println!("{}..{}", gx1, gx2);
-
-    for y in -1..=(ROWS as i32) {
-        for x in -1..=(COLS as i32) {
-            let maybe_ent = tilemap.entities[(y+1)as usize][(x+1) as usize];
-            if let Some(ent) = maybe_ent {
-                commands.entity(ent).despawn();
-                tilemap.entities[(y+1) as usize][(x+1) as usize] = None;
-            }
-
-            let gx = (tilemap.position.x * 39. + x as f32) as f64 * 0.1;
-            let gy = (tilemap.position.y * 22. + y as f32) as f64 * 0.1;
-            let val = perlin.get([gx, gy, 0.1]);
-
-            if val > 0.2 {
-                let tile = Tile { tile_index: rng.gen_range(0..12) };
-                tilemap.set((x+1) as usize, (y+1)as usize, Some(tile));
-                let world_pos = Vec3::new(
-                    (x+1) as f32 * TILE_SIZE as f32,
-                    (((y+1) as f32) * TILE_SIZE as f32) * -1.0,
-                    0.0,
-                );
-
-				let mut sprite = Sprite {
-					image: texture.clone(),
-					texture_atlas: Some(TextureAtlas {
-						layout: tilemap.layout.clone(),
-						index: tile.tile_index,
-					}),
-					..Default::default()
-				};
-
-				sprite.anchor = Anchor::TopLeft;
-
-                // print the worldpos
-				let e = commands.spawn((
-					sprite,
-					Transform::from_xyz(world_pos.x, world_pos.y, world_pos.z),
-					PIXEL_PERFECT_LAYERS,
-				)).id();
-
-                tilemap.entities[(y + 1) as usize][(x + 1) as usize] = Some(e);
-            } else {
-                tilemap.set((x+1)as usize,(y+1) as usize, None);
-            }
-        }
-    }
+    tilemap.load_screen(&worldgen);
+    respawn_tile_sprites(&mut commands, &mut tilemap);
+    screen_changed.write(ScreenChanged { position: tilemap.position.as_ivec2() });
 }
@@ -0,0 +1,221 @@
+use bevy::{prelude::*, sprite::Anchor};
+
+use crate::game::{
+    cursor::CursorWorld,
+    explosion::{Explosion, ExplosionDef},
+    health::{DamageEvent, Health, Hitbox},
+    item::Inventory,
+    player::Player,
+    state::{GameState, GameplaySet},
+    tilemap::{ScreenChanged, TileLayer, TileMap},
+    PIXEL_PERFECT_LAYERS,
+};
+
+/// Seconds a stuck projectile stays before disappearing.
+const STUCK_TIME: f32 = 4.0;
+/// Longest distance checked against hitboxes in one go, in pixels.
+const HIT_STEP: f32 = 2.0;
+const PROJECTILE_Z: f32 = 0.3;
+/// Push given to whatever a projectile hits, in pixels per second.
+const PROJECTILE_KNOCKBACK: f32 = 60.0;
+
+pub struct ProjectilePlugin;
+
+impl Plugin for ProjectilePlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(Update, (fire_ranged, move_projectiles, clear_projectiles).chain().in_set(GameplaySet));
+    }
+}
+
+/// What a projectile does when it hits a solid tile.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TileImpact {
+    /// Stays stuck in the tile for a while.
+    Stick,
+    /// Removes the tile and disappears.
+    Destroy,
+    /// Explodes on hitting anything, or when its lifetime runs out.
+    Explode(ExplosionDef),
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct ProjectileDef {
+    pub speed: f32,
+    /// Vertical acceleration in pixels per second squared.
+    pub gravity: f32,
+    /// Seconds before it disappears in flight.
+    pub lifetime: f32,
+    pub damage: u32,
+    pub size: Vec2,
+    pub colour: Color,
+    pub impact: TileImpact,
+}
+
+#[derive(Component)]
+pub struct Projectile {
+    pub velocity: Vec2,
+    pub gravity: f32,
+    pub lifetime: f32,
+    pub damage: u32,
+    pub impact: TileImpact,
+    /// Entity that fired it; it never hits its owner.
+    pub owner: Entity,
+    pub stuck: bool,
+}
+
+/// Spawns a projectile whose centre starts at `from`, flying along `dir`.
+pub fn spawn_projectile(commands: &mut Commands, def: &ProjectileDef, owner: Entity, from: Vec2, dir: Vec2) {
+    let velocity = dir.normalize_or_zero() * def.speed;
+    let mut sprite = Sprite::from_color(def.colour, def.size);
+    sprite.anchor = Anchor::Center;
+    commands.spawn((
+        Projectile {
+            velocity,
+            gravity: def.gravity,
+            lifetime: def.lifetime,
+            damage: def.damage,
+            impact: def.impact,
+            owner,
+            stuck: false,
+        },
+        sprite,
+        Transform::from_xyz(from.x, from.y, PROJECTILE_Z).with_rotation(Quat::from_rotation_z(velocity.to_angle())),
+        PIXEL_PERFECT_LAYERS,
+        StateScoped(GameState::InGame),
+    ));
+}
+
+/// Left click with a ranged item shoots toward the cursor.
+fn fire_ranged(
+    mut commands: Commands,
+    mouse: Res<ButtonInput<MouseButton>>,
+    cursor: Res<CursorWorld>,
+    mut player: Query<(Entity, &Transform, &mut Inventory), With<Player>>,
+    time: Res<Time>,
+) {
+    let Ok((entity, transform, mut inventory)) = player.single_mut() else {
+        return;
+    };
+    inventory.cooldown = (inventory.cooldown - time.delta_secs()).max(0.0);
+    if !mouse.pressed(MouseButton::Left) || inventory.cooldown > 0.0 {
+        return;
+    }
+    let Some(item) = inventory.selected_item() else {
+        return;
+    };
+    let Some(ranged) = &item.kind.def().ranged else {
+        return;
+    };
+    let Some(target) = cursor.world() else {
+        return;
+    };
+
+    if let Some(ammo) = ranged.ammo
+        && !inventory.remove_one(ammo)
+    {
+        return;
+    }
+    if ranged.thrown {
+        inventory.remove_one(item.kind);
+    }
+    inventory.cooldown = ranged.cooldown;
+
+    let from = transform.translation.truncate() + Vec2::new(4., -4.);
+    spawn_projectile(&mut commands, &ranged.projectile, entity, from, target - from);
+}
+
+fn move_projectiles(
+    mut commands: Commands,
+    mut tilemap: ResMut<TileMap>,
+    mut projectiles: Query<(Entity, &mut Projectile, &mut Transform), Without<Hitbox>>,
+    targets: Query<(Entity, &Transform, &Hitbox), With<Health>>,
+    mut damage: EventWriter<DamageEvent>,
+    mut explosions: EventWriter<Explosion>,
+    time: Res<Time>,
+) {
+    let dt = time.delta_secs();
+    for (entity, mut projectile, mut transform) in &mut projectiles {
+        let explosion = match projectile.impact {
+            TileImpact::Explode(def) => Some(def),
+            _ => None,
+        };
+        let explode_at = |explosions: &mut EventWriter<Explosion>, centre: Vec2| {
+            if let Some(def) = explosion {
+                explosions.write(Explosion { centre, def });
+            }
+        };
+
+        projectile.lifetime -= dt;
+        if projectile.lifetime <= 0.0 {
+            explode_at(&mut explosions, transform.translation.truncate());
+            commands.entity(entity).despawn();
+            continue;
+        }
+        if projectile.stuck {
+            continue;
+        }
+
+        projectile.velocity.y += projectile.gravity * dt;
+        let from = transform.translation.truncate();
+        let travel = projectile.velocity * dt;
+        let length = travel.length();
+
+        // Stop at the first tile along the way.
+        let tile_hit = tilemap.raycast(from, travel, length);
+        let reach = tile_hit.map_or(length, |hit| hit.distance);
+
+        // Then look for a hitbox before that point.
+        let steps = (reach / HIT_STEP).ceil().max(1.0) as usize;
+        let hit_entity = (0..=steps).find_map(|i| {
+            let point = from + travel.normalize_or_zero() * reach * i as f32 / steps as f32;
+            targets.iter().find(|(target, target_tf, hitbox)| {
+                *target != projectile.owner && hitbox.contains(target_tf.translation.truncate(), point)
+            })
+        });
+        if let Some((target, _, _)) = hit_entity {
+            explode_at(&mut explosions, from);
+            damage.write(DamageEvent { target, amount: projectile.damage, knockback: projectile.velocity.normalize_or_zero() * PROJECTILE_KNOCKBACK });
+            commands.entity(entity).despawn();
+            continue;
+        }
+
+        let Some(hit) = tile_hit else {
+            transform.translation.x += travel.x;
+            transform.translation.y += travel.y;
+            transform.rotation = Quat::from_rotation_z(projectile.velocity.to_angle());
+            continue;
+        };
+        match projectile.impact {
+            TileImpact::Stick => {
+                projectile.stuck = true;
+                projectile.lifetime = STUCK_TIME;
+                transform.translation.x = hit.point.x;
+                transform.translation.y = hit.point.y;
+            }
+            TileImpact::Destroy => {
+                let g = tilemap.origin() + hit.cell;
+                tilemap.set_global(TileLayer::Foreground, g, None);
+                commands.entity(entity).despawn();
+            }
+            TileImpact::Explode(_) => {
+                // Go off just in front of the tile, not inside it.
+                explode_at(&mut explosions, hit.point - travel.normalize_or_zero());
+                commands.entity(entity).despawn();
+            }
+        }
+    }
+}
+
+/// Projectiles live in screen coordinates, so they go when the screen changes.
+fn clear_projectiles(
+    mut commands: Commands,
+    mut screen_changed: EventReader<ScreenChanged>,
+    projectiles: Query<Entity, With<Projectile>>,
+) {
+    if screen_changed.read().last().is_none() {
+        return;
+    }
+    for entity in &projectiles {
+        commands.entity(entity).despawn();
+    }
+}
@@ -0,0 +1,160 @@
+//! Grid traversal queries over tiles.
+//!
+//! Points are in the canvas space tiles are drawn in: tile `(x, y)` covers
+//! `x * 8 .. x * 8 + 8` horizontally and `-y * 8 - 8 .. -y * 8` vertically,
+//! each including its lower bound; see [`cell_of`].
+//! The functions take a `solid` query per cell so they work on any grid;
+//! [`TileMap`] has shortcuts for the loaded screen.
+
+use bevy::prelude::*;
+
+use crate::game::tilemap::{TileLayer, TileMap, TILE_SIZE};
+
+/// Most cells a ray walks through, so one with no end in sight still stops.
+const MAX_STEPS: usize = 4096;
+
+/// Side of a tile a ray entered through.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Face {
+    Left,
+    Right,
+    Top,
+    Bottom,
+}
+
+impl Face {
+    /// Outward normal of the face in canvas space.
+    pub fn normal(&self) -> Vec2 {
+        match self {
+            Face::Left => Vec2::NEG_X,
+            Face::Right => Vec2::X,
+            Face::Top => Vec2::Y,
+            Face::Bottom => Vec2::NEG_Y,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RayHit {
+    /// Cell that was hit.
+    pub cell: IVec2,
+    /// Face the ray entered through; `None` when it started inside the cell.
+    pub face: Option<Face>,
+    /// Distance travelled in pixels.
+    pub distance: f32,
+    pub point: Vec2,
+}
+
+/// Cell containing a canvas point. A point on the line between two rows is
+/// in the lower one, the same as between two columns it is in the right one.
+pub fn cell_of(point: Vec2) -> IVec2 {
+    let tile = TILE_SIZE as f32;
+    IVec2::new((point.x / tile).floor() as i32, -((point.y / tile).floor() as i32) - 1)
+}
+
+/// Walks the cells along a ray (DDA) and returns the first solid one within
+/// `max_distance` pixels, giving up after [`MAX_STEPS`] cells.
+pub fn raycast(solid: impl Fn(IVec2) -> bool, origin: Vec2, dir: Vec2, max_distance: f32) -> Option<RayHit> {
+    let tile = TILE_SIZE as f32;
+    let dir = dir.normalize_or_zero();
+    let mut cell = cell_of(origin);
+    if solid(cell) {
+        return Some(RayHit { cell, face: None, distance: 0.0, point: origin });
+    }
+    if dir == Vec2::ZERO {
+        return None;
+    }
+
+    // Grid space: one unit per tile, y growing downwards like rows.
+    let start = Vec2::new(origin.x, -origin.y) / tile;
+    let grid_dir = Vec2::new(dir.x, -dir.y);
+    let step = IVec2::new(grid_dir.x.signum() as i32, grid_dir.y.signum() as i32);
+    let delta = Vec2::new(
+        if grid_dir.x != 0.0 { (1.0 / grid_dir.x).abs() } else { f32::INFINITY },
+        if grid_dir.y != 0.0 { (1.0 / grid_dir.y).abs() } else { f32::INFINITY },
+    );
+    let boundary = |pos: f32, cell: i32, step: i32| if step > 0 { cell as f32 + 1.0 - pos } else { pos - cell as f32 };
+    let mut next = Vec2::new(
+        if step.x != 0 { boundary(start.x, cell.x, step.x) * delta.x } else { f32::INFINITY },
+        if step.y != 0 { boundary(start.y, cell.y, step.y) * delta.y } else { f32::INFINITY },
+    );
+    let max_t = max_distance / tile;
+
+    for _ in 0..MAX_STEPS {
+        let (t, face) = if next.x < next.y {
+            cell.x += step.x;
+            let t = next.x;
+            next.x += delta.x;
+            (t, if step.x > 0 { Face::Left } else { Face::Right })
+        } else {
+            cell.y += step.y;
+            let t = next.y;
+            next.y += delta.y;
+            (t, if step.y > 0 { Face::Top } else { Face::Bottom })
+        };
+        if t > max_t {
+            return None;
+        }
+        if solid(cell) {
+            let distance = t * tile;
+            return Some(RayHit { cell, face: Some(face), distance, point: origin + dir * distance });
+        }
+    }
+    None
+}
+
+/// Whether nothing solid lies on the segment from `a` to `b`.
+pub fn line_of_sight(solid: impl Fn(IVec2) -> bool, a: Vec2, b: Vec2) -> bool {
+    raycast(solid, a, b - a, a.distance(b)).is_none()
+}
+
+/// Sweeps a `size` box with its top-left corner at `pos` along `dir` and returns
+/// the nearest hit of its leading edges.
+pub fn box_cast(solid: impl Fn(IVec2) -> bool, pos: Vec2, size: Vec2, dir: Vec2, max_distance: f32) -> Option<RayHit> {
+    let tile = TILE_SIZE as f32;
+    let dir = dir.normalize_or_zero();
+    // Nudge the corners inwards so touching a wall is not a hit.
+    let (left, right) = (pos.x + 0.01, pos.x + size.x - 0.01);
+    let (top, bottom) = (pos.y - 0.01, pos.y - size.y + 0.01);
+
+    // Sample the edges facing the direction at least once per tile.
+    let mut origins = Vec::new();
+    let samples = |from: f32, to: f32| {
+        let count = ((to - from).abs() / tile).ceil().max(1.0) as usize;
+        (0..=count).map(move |i| from + (to - from) * i as f32 / count as f32)
+    };
+    if dir.x != 0.0 {
+        let x = if dir.x > 0.0 { right } else { left };
+        origins.extend(samples(bottom, top).map(|y| Vec2::new(x, y)));
+    }
+    if dir.y != 0.0 {
+        let y = if dir.y > 0.0 { top } else { bottom };
+        origins.extend(samples(left, right).map(|x| Vec2::new(x, y)));
+    }
+
+    origins
+        .into_iter()
+        .filter_map(|origin| raycast(&solid, origin, dir, max_distance))
+        .min_by(|a, b| a.distance.total_cmp(&b.distance))
+}
+
+impl TileMap {
+    fn solid_cell(&self, cell: IVec2) -> bool {
+        cell.x >= 0 && cell.y >= 0 && self.tile_at(TileLayer::Foreground, cell.x as usize, cell.y as usize).is_some_and(|t| t.solid())
+    }
+
+    /// [`raycast`] against the solid tiles of the loaded screen.
+    pub fn raycast(&self, origin: Vec2, dir: Vec2, max_distance: f32) -> Option<RayHit> {
+        raycast(|c| self.solid_cell(c), origin, dir, max_distance)
+    }
+
+    /// [`line_of_sight`] through the loaded screen.
+    pub fn line_of_sight(&self, a: Vec2, b: Vec2) -> bool {
+        line_of_sight(|c| self.solid_cell(c), a, b)
+    }
+
+    /// [`box_cast`] against the solid tiles of the loaded screen.
+    pub fn box_cast(&self, pos: Vec2, size: Vec2, dir: Vec2, max_distance: f32) -> Option<RayHit> {
+        box_cast(|c| self.solid_cell(c), pos, size, dir, max_distance)
+    }
+}
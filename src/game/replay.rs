@@ -0,0 +1,352 @@
+//! Records the input of every simulated tick and plays it back.
+//!
+//! A [`Replay`] holds the seed, the frame times and the actions the
+//! [`SimulationPlugin`](super::SimulationPlugin) reads, so playing it on a
+//! fresh world repeats the session: the same player moves, the same screen
+//! changes and the same tile edits. Every `checksum_every` ticks it also holds
+//! a [`state_checksum`], and playback reports the first tick where the world
+//! no longer matches, which makes replays usable as regression tests.
+//!
+//! Only ticks where the world runs are recorded; menus and pauses are left
+//! out, and playback waits while the game is paused.
+
+use std::{fs, path::{Path, PathBuf}, time::Duration};
+
+use bevy::{prelude::*, time::{TimeSystem, TimeUpdateStrategy}};
+use serde::{Deserialize, Serialize};
+
+use crate::game::{
+    cursor::{CursorPoint, CursorWorld},
+    liquid::LiquidMap,
+    player::Player,
+    settings::{Action, Settings},
+    state::{GameState, GameplaySet, Pause, SelectedWorld},
+    tilemap::{TileLayer, TileMap},
+};
+
+/// Actions recorded each tick, by bit in [`InputFrame::actions`].
+/// New actions go at the end so older recordings keep their bits.
+const RECORDED_ACTIONS: [Action; 6] = [Action::Left, Action::Right, Action::Jump, Action::Build, Action::Up, Action::Down];
+/// Mouse buttons recorded each tick, by bit in [`InputFrame::buttons`].
+const RECORDED_BUTTONS: [MouseButton; 2] = [MouseButton::Left, MouseButton::Right];
+/// Ticks between two checksums in new recordings: one a second at 60 Hz.
+pub const CHECKSUM_EVERY: u32 = 60;
+/// World name replays are played in; it is never saved.
+pub(crate) const REPLAY_WORLD: &str = "replay";
+
+pub struct ReplayPlugin;
+
+impl Plugin for ReplayPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(First, feed_time.before(TimeSystem).run_if(resource_exists::<Playback>));
+        app.add_systems(OnEnter(GameState::MainMenu), start_playback.run_if(resource_exists::<Playback>));
+        app.add_systems(OnEnter(GameState::InGame), start_recording.run_if(resource_exists::<Recorder>));
+        app.add_systems(
+            Update,
+            (record_input.run_if(resource_exists::<Recorder>), feed_input.run_if(resource_exists::<Playback>))
+                .before(GameplaySet)
+                .run_if(in_state(Pause::Running)),
+        );
+        app.add_systems(
+            Last,
+            (record_checksum.run_if(resource_exists::<Recorder>), check_playback.run_if(resource_exists::<Playback>))
+                .run_if(in_state(Pause::Running)),
+        );
+        app.add_systems(OnExit(GameState::InGame), write_recording.run_if(resource_exists::<Recorder>));
+        app.add_systems(
+            Last,
+            write_recording_on_exit.run_if(resource_exists::<Recorder>.and(in_state(GameState::InGame))),
+        );
+    }
+}
+
+/// Input of one simulated tick.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct InputFrame {
+    /// Length of the tick.
+    pub delta: Duration,
+    /// Bit `i` is set while `RECORDED_ACTIONS[i]` is held.
+    pub actions: u8,
+    /// Bit `i` is set while `RECORDED_BUTTONS[i]` is held.
+    pub buttons: u8,
+    pub cursor: Option<CursorPoint>,
+}
+
+/// A recorded session.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Replay {
+    pub seed: u32,
+    /// Ticks between two entries of `checksums`.
+    pub checksum_every: u32,
+    pub frames: Vec<InputFrame>,
+    /// [`state_checksum`] after every `checksum_every` ticks.
+    pub checksums: Vec<u64>,
+}
+
+impl Replay {
+    pub fn new(seed: u32) -> Self {
+        Self { seed, checksum_every: CHECKSUM_EVERY, frames: Vec::new(), checksums: Vec::new() }
+    }
+}
+
+pub fn read_replay(path: &Path) -> Result<Replay, String> {
+    let text = fs::read_to_string(path).map_err(|err| format!("could not read {}: {err}", path.display()))?;
+    ron::from_str(&text).map_err(|err| format!("bad replay {}: {err}", path.display()))
+}
+
+pub fn write_replay(path: &Path, replay: &Replay) -> Result<(), String> {
+    // One frame a line would be most of the file; keep it compact.
+    let text = ron::ser::to_string(replay).map_err(|err| format!("could not serialize replay: {err}"))?;
+    fs::write(path, text).map_err(|err| format!("could not write {}: {err}", path.display()))
+}
+
+/// Records the ticks of the world being played, written to `path`, if any,
+/// on leaving it.
+#[derive(Resource, Default)]
+pub struct Recorder {
+    pub path: Option<PathBuf>,
+    pub replay: Option<Replay>,
+}
+
+impl Recorder {
+    pub fn to_file(path: impl Into<PathBuf>) -> Self {
+        Self { path: Some(path.into()), replay: None }
+    }
+}
+
+/// First tick where the played-back world differed from the recorded one.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Divergence {
+    pub tick: u32,
+    pub expected: u64,
+    pub actual: u64,
+}
+
+/// Plays a [`Replay`] back from the main menu, or from the first tick in game
+/// when it is inserted there.
+#[derive(Resource)]
+pub struct Playback {
+    pub replay: Replay,
+    /// Ticks played so far.
+    pub tick: u32,
+    pub diverged: Option<Divergence>,
+    /// Bits of the frame before, to tell presses from holds.
+    held: (u8, u8),
+    /// Time strategy to go back to once the replay ends.
+    time: Option<TimeUpdateStrategy>,
+}
+
+impl Playback {
+    pub fn new(replay: Replay) -> Self {
+        Self { replay, tick: 0, diverged: None, held: (0, 0), time: None }
+    }
+
+    pub fn finished(&self) -> bool {
+        self.tick as usize >= self.replay.frames.len()
+    }
+
+    fn next_frame(&self) -> Option<&InputFrame> {
+        self.replay.frames.get(self.tick as usize)
+    }
+}
+
+/// FNV-1a, so checksums stay the same across builds and platforms.
+struct Checksum(u64);
+
+impl Checksum {
+    fn new() -> Self {
+        Self(0xcbf2_9ce4_8422_2325)
+    }
+
+    fn write(&mut self, bytes: &[u8]) {
+        for byte in bytes {
+            self.0 ^= *byte as u64;
+            self.0 = self.0.wrapping_mul(0x0100_0000_01b3);
+        }
+    }
+
+    fn write_f32(&mut self, value: f32) {
+        self.write(&value.to_bits().to_le_bytes());
+    }
+
+    fn write_i32(&mut self, value: i32) {
+        self.write(&value.to_le_bytes());
+    }
+}
+
+/// Hash of what a replay has to reproduce exactly: the player, the loaded
+/// screen, the tile edits and the liquids.
+pub fn state_checksum(player: &Player, transform: &Transform, tilemap: &TileMap, liquids: &LiquidMap) -> u64 {
+    let mut sum = Checksum::new();
+    for value in [transform.translation.truncate(), player.velocity, player.remainder] {
+        sum.write_f32(value.x);
+        sum.write_f32(value.y);
+    }
+    sum.write(&[player.on_ground as u8, player.inside as u8, player.submerged as u8]);
+    sum.write_i32(tilemap.position.x as i32);
+    sum.write_i32(tilemap.position.y as i32);
+
+    // Both maps are unordered.
+    let mut edits: Vec<_> = tilemap.edits.iter().collect();
+    edits.sort_by_key(|((layer, g), _)| (*layer == TileLayer::Background, g.y, g.x));
+    for ((layer, g), tile) in edits {
+        sum.write(&[*layer as u8]);
+        sum.write_i32(g.x);
+        sum.write_i32(g.y);
+        match tile {
+            Some(tile) => sum.write(&[1, tile.kind as u8, tile.tile_index as u8]),
+            None => sum.write(&[0]),
+        }
+    }
+    let mut cells: Vec<_> = liquids.cells.iter().collect();
+    cells.sort_by_key(|(g, _)| (g.y, g.x));
+    for (g, liquid) in cells {
+        sum.write_i32(g.x);
+        sum.write_i32(g.y);
+        sum.write(&[liquid.kind as u8, liquid.level]);
+    }
+    sum.0
+}
+
+fn bits<T>(items: &[T], held: impl Fn(&T) -> bool) -> u8 {
+    items.iter().enumerate().filter(|(_, item)| held(item)).fold(0, |bits, (i, _)| bits | (1 << i))
+}
+
+fn start_recording(mut recorder: ResMut<Recorder>, world: Res<SelectedWorld>) {
+    recorder.replay = Some(Replay::new(world.seed));
+}
+
+fn record_input(
+    mut recorder: ResMut<Recorder>,
+    time: Res<Time>,
+    keyboard_input: Res<ButtonInput<KeyCode>>,
+    mouse: Res<ButtonInput<MouseButton>>,
+    cursor: Res<CursorWorld>,
+    settings: Res<Settings>,
+) {
+    let Some(replay) = &mut recorder.replay else {
+        return;
+    };
+    replay.frames.push(InputFrame {
+        delta: time.delta(),
+        actions: bits(&RECORDED_ACTIONS, |action| keyboard_input.pressed(settings.keys.get(*action))),
+        buttons: bits(&RECORDED_BUTTONS, |button| mouse.pressed(*button)),
+        cursor: cursor.0,
+    });
+}
+
+fn record_checksum(
+    mut recorder: ResMut<Recorder>,
+    player: Single<(&Player, &Transform)>,
+    tilemap: Res<TileMap>,
+    liquids: Res<LiquidMap>,
+) {
+    let Some(replay) = &mut recorder.replay else {
+        return;
+    };
+    if (replay.frames.len() as u32).is_multiple_of(replay.checksum_every) {
+        let (player, transform) = *player;
+        replay.checksums.push(state_checksum(player, transform, &tilemap, &liquids));
+    }
+}
+
+fn write_recording(recorder: Res<Recorder>) {
+    let (Some(path), Some(replay)) = (&recorder.path, &recorder.replay) else {
+        return;
+    };
+    match write_replay(path, replay) {
+        Ok(()) => info!("recorded {} ticks to {}", replay.frames.len(), path.display()),
+        Err(err) => error!("{err}"),
+    }
+}
+
+fn write_recording_on_exit(mut exit: EventReader<AppExit>, recorder: Res<Recorder>) {
+    if exit.read().next().is_none() {
+        return;
+    }
+    write_recording(recorder);
+}
+
+/// Goes straight from the main menu into a fresh world with the replay's seed.
+fn start_playback(mut commands: Commands, playback: Res<Playback>, mut next: ResMut<NextState<GameState>>) {
+    if playback.tick > 0 {
+        return;
+    }
+    commands.insert_resource(SelectedWorld { name: REPLAY_WORLD.to_string(), seed: playback.replay.seed });
+    next.set(GameState::InGame);
+}
+
+/// Makes the coming frame as long as the next recorded tick, and gives the
+/// clock back once the replay is over.
+fn feed_time(mut playback: ResMut<Playback>, mut strategy: ResMut<TimeUpdateStrategy>) {
+    match playback.next_frame().map(|frame| frame.delta) {
+        Some(delta) => {
+            let before = std::mem::replace(&mut *strategy, TimeUpdateStrategy::ManualDuration(delta));
+            playback.time.get_or_insert(before);
+        }
+        None => {
+            if let Some(before) = playback.time.take() {
+                *strategy = before;
+            }
+        }
+    }
+}
+
+/// Replaces the live input with the recorded one for this tick.
+fn feed_input(
+    mut playback: ResMut<Playback>,
+    mut keyboard_input: ResMut<ButtonInput<KeyCode>>,
+    mut mouse: ResMut<ButtonInput<MouseButton>>,
+    mut cursor: ResMut<CursorWorld>,
+    settings: Res<Settings>,
+) {
+    let Some(frame) = playback.next_frame().cloned() else {
+        return;
+    };
+    let (actions_before, buttons_before) = playback.held;
+    for (i, action) in RECORDED_ACTIONS.iter().enumerate() {
+        set_held(&mut keyboard_input, settings.keys.get(*action), frame.actions & (1 << i) != 0, actions_before & (1 << i) != 0);
+    }
+    for (i, button) in RECORDED_BUTTONS.iter().enumerate() {
+        set_held(&mut mouse, *button, frame.buttons & (1 << i) != 0, buttons_before & (1 << i) != 0);
+    }
+    cursor.0 = frame.cursor;
+    playback.held = (frame.actions, frame.buttons);
+    playback.tick += 1;
+}
+
+/// Puts `input` in the state it had when recorded: pressed this tick only
+/// if it was not held the tick before.
+fn set_held<T: Copy + Eq + std::hash::Hash + Send + Sync + 'static>(input: &mut ButtonInput<T>, button: T, held: bool, held_before: bool) {
+    input.reset(button);
+    if held {
+        input.press(button);
+        if held_before {
+            input.clear_just_pressed(button);
+        }
+    }
+}
+
+fn check_playback(
+    mut playback: ResMut<Playback>,
+    player: Single<(&Player, &Transform)>,
+    tilemap: Res<TileMap>,
+    liquids: Res<LiquidMap>,
+) {
+    let tick = playback.tick;
+    let every = playback.replay.checksum_every;
+    if tick == 0 || !tick.is_multiple_of(every) || playback.diverged.is_some() {
+        return;
+    }
+    let Some(expected) = playback.replay.checksums.get((tick / every - 1) as usize).copied() else {
+        return;
+    };
+    let (player, transform) = *player;
+    let actual = state_checksum(player, transform, &tilemap, &liquids);
+    if actual != expected {
+        error!("replay diverged at tick {tick}: checksum {actual:016x}, recorded {expected:016x}");
+        playback.diverged = Some(Divergence { tick, expected, actual });
+    } else if playback.finished() {
+        info!("replay matched the recording for all {tick} ticks");
+    }
+}
@@ -0,0 +1,175 @@
+use std::{fs, path::PathBuf};
+
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::game::{
+    clock::WorldClock,
+    liquid::{Liquid, LiquidMap},
+    map::Discovered,
+    replay::Playback,
+    state::{GameState, SelectedWorld},
+    tilemap::{setup_map, spawn_tiles, Tile, TileLayer, TileMap},
+    worldgen::WorldGen,
+};
+
+const SAVE_DIR: &str = "saves";
+const WORLD_FILE: &str = "world.ron";
+
+pub struct SavePlugin;
+
+impl Plugin for SavePlugin {
+    fn build(&self, app: &mut App) {
+        // Edits must be in the map before its sprites are spawned.
+        app.add_systems(OnEnter(GameState::InGame), load_world.after(setup_map).before(spawn_tiles));
+        // A replay plays in a throwaway world.
+        app.add_systems(OnExit(GameState::InGame), save_world.run_if(not(resource_exists::<Playback>)));
+        app.add_systems(Last, save_world_on_exit.run_if(in_state(GameState::InGame).and(not(resource_exists::<Playback>))));
+    }
+}
+
+/// The parts of a world that are not regenerated from the seed: changed tiles
+/// and liquid, the clock and the map. The player always starts over at the
+/// spawn point, and falling tiles, drops and enemies are not kept.
+#[derive(Serialize, Deserialize, Default)]
+pub struct WorldSave {
+    /// Saves written before worlds had seeds all used seed 1.
+    #[serde(default = "default_seed")]
+    pub seed: u32,
+    pub clock: WorldClock,
+    /// Screens shown on the world map.
+    #[serde(default)]
+    pub discovered: Vec<IVec2>,
+    /// Tiles of either layer changed since generation, by global tile.
+    #[serde(default)]
+    pub edits: Vec<(TileLayer, IVec2, Option<Tile>)>,
+    /// Every liquid cell, by global tile.
+    #[serde(default)]
+    pub liquids: Vec<(IVec2, Liquid)>,
+    /// Screens whose generated liquid is already in `liquids`.
+    #[serde(default)]
+    pub seeded: Vec<IVec2>,
+}
+
+fn default_seed() -> u32 {
+    1
+}
+
+/// Directory holding the save of the world called `name`.
+pub fn world_dir(name: &str) -> PathBuf {
+    PathBuf::from(SAVE_DIR).join(name)
+}
+
+pub fn world_path(name: &str) -> PathBuf {
+    world_dir(name).join(WORLD_FILE)
+}
+
+/// Whether `name` can be used as a world name, and so as a directory name.
+pub fn valid_world_name(name: &str) -> bool {
+    !name.is_empty()
+        && name.len() <= 32
+        && name.chars().all(|c| c.is_ascii_alphanumeric() || c == ' ' || c == '-' || c == '_')
+        && name.trim() == name
+}
+
+/// Names and seeds of the saved worlds, sorted by name.
+pub fn list_worlds() -> Vec<SelectedWorld> {
+    let Ok(entries) = fs::read_dir(SAVE_DIR) else {
+        return Vec::new();
+    };
+    let mut worlds: Vec<SelectedWorld> = entries
+        .flatten()
+        .filter_map(|entry| entry.file_name().into_string().ok())
+        .filter_map(|name| read_world_save(&name).map(|save| SelectedWorld { name, seed: save.seed }))
+        .collect();
+    worlds.sort_by(|a, b| a.name.cmp(&b.name));
+    worlds
+}
+
+pub fn read_world_save(name: &str) -> Option<WorldSave> {
+    let path = world_path(name);
+    let text = fs::read_to_string(&path).ok()?;
+    match ron::from_str(&text) {
+        Ok(save) => Some(save),
+        Err(err) => {
+            warn!("ignoring unreadable save {}: {err}", path.display());
+            None
+        }
+    }
+}
+
+pub fn write_world_save(name: &str, save: &WorldSave) {
+    let path = world_path(name);
+    let text = match ron::ser::to_string_pretty(save, ron::ser::PrettyConfig::default()) {
+        Ok(text) => text,
+        Err(err) => {
+            error!("could not serialize world: {err}");
+            return;
+        }
+    };
+    if let Err(err) = fs::create_dir_all(world_dir(name)).and_then(|_| fs::write(&path, text)) {
+        error!("could not write {}: {err}", path.display());
+    }
+}
+
+fn load_world(
+    mut commands: Commands,
+    world: Res<SelectedWorld>,
+    mut tilemap: ResMut<TileMap>,
+    worldgen: Res<WorldGen>,
+    mut liquids: ResMut<LiquidMap>,
+) {
+    let save = read_world_save(&world.name).unwrap_or_default();
+    commands.insert_resource(save.clock);
+    commands.insert_resource(Discovered(save.discovered.into_iter().collect()));
+    tilemap.edits = save.edits.into_iter().map(|(layer, g, tile)| ((layer, g), tile)).collect();
+    tilemap.load_screen(&worldgen);
+    for (g, liquid) in save.liquids {
+        liquids.set(g, Some(liquid));
+    }
+    liquids.seeded.extend(save.seeded);
+}
+
+/// Sorted by row then column, so the file does not churn between saves.
+fn sorted(mut cells: Vec<IVec2>) -> Vec<IVec2> {
+    cells.sort_by_key(|g| (g.y, g.x));
+    cells
+}
+
+fn save_world(
+    world: Res<SelectedWorld>,
+    clock: Res<WorldClock>,
+    discovered: Res<Discovered>,
+    tilemap: Res<TileMap>,
+    liquids: Res<LiquidMap>,
+) {
+    let mut edits: Vec<_> = tilemap.edits.iter().map(|(&(layer, g), &tile)| (layer, g, tile)).collect();
+    edits.sort_by_key(|&(layer, g, _)| (layer as u8, g.y, g.x));
+    let mut cells: Vec<_> = liquids.cells.iter().map(|(&g, &liquid)| (g, liquid)).collect();
+    cells.sort_by_key(|&(g, _)| (g.y, g.x));
+    write_world_save(
+        &world.name,
+        &WorldSave {
+            seed: world.seed,
+            clock: clock.clone(),
+            discovered: sorted(discovered.0.iter().copied().collect()),
+            edits,
+            liquids: cells,
+            seeded: sorted(liquids.seeded.iter().copied().collect()),
+        },
+    );
+}
+
+fn save_world_on_exit(
+    mut exit: EventReader<AppExit>,
+    world: Res<SelectedWorld>,
+    clock: Res<WorldClock>,
+    discovered: Res<Discovered>,
+    tilemap: Res<TileMap>,
+    liquids: Res<LiquidMap>,
+) {
+    if exit.read().next().is_none() {
+        return;
+    }
+    save_world(world, clock, discovered, tilemap, liquids);
+}
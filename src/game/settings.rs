@@ -0,0 +1,391 @@
+//! User settings, kept in `settings.ron` in the platform config directory.
+//!
+//! Missing entries fall back to their defaults and out of range ones are
+//! clamped, so old or hand edited files keep working. A file that does not
+//! parse is reported and moved aside rather than overwritten. The file is
+//! rewritten whenever [`Settings`] changes.
+
+use std::{fs, path::PathBuf};
+
+use bevy::{
+    audio::{GlobalVolume, Volume},
+    prelude::*,
+    window::{MonitorSelection, PrimaryWindow, WindowMode, WindowResolution},
+};
+use serde::{Deserialize, Serialize};
+
+use crate::game::{RES_HEIGHT, RES_WIDTH};
+
+const APP_DIR: &str = "terra";
+const SETTINGS_FILE: &str = "settings.ron";
+/// Where an unreadable settings file is kept, next to the real one.
+const UNREADABLE_FILE: &str = "settings.ron.bad";
+/// Largest fixed canvas scale that can be picked.
+pub const MAX_CANVAS_SCALE: u32 = 8;
+const MIN_WINDOW: UVec2 = UVec2::new(320, 180);
+const MAX_WINDOW: UVec2 = UVec2::new(7680, 4320);
+const MIN_CANVAS: UVec2 = UVec2::new(160, 90);
+/// The tile grid covers one screen, so the canvas can show all of it but no
+/// more; a smaller canvas scrolls over it with the player.
+const MAX_CANVAS: UVec2 = UVec2::new(RES_WIDTH, RES_HEIGHT);
+/// Internal resolutions offered by the options menu.
+pub const CANVAS_SIZES: [UVec2; 4] = [
+    UVec2::new(192, 108),
+    UVec2::new(256, 144),
+    UVec2::new(288, 162),
+    UVec2::new(RES_WIDTH, RES_HEIGHT),
+];
+/// Window sizes offered by the options menu.
+pub const WINDOW_SIZES: [UVec2; 5] = [
+    UVec2::new(1280, 720),
+    UVec2::new(1600, 900),
+    UVec2::new(1920, 1080),
+    UVec2::new(2560, 1440),
+    UVec2::new(3840, 2160),
+];
+
+pub struct SettingsPlugin;
+
+impl Plugin for SettingsPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(Update, (apply_settings, save_settings).chain());
+    }
+}
+
+/// Something the player does with a key.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Action {
+    Left,
+    Right,
+    Jump,
+    /// Rises in noclip.
+    Up,
+    /// Sinks in noclip.
+    Down,
+    /// Held while right-clicking to place walls instead of removing them.
+    Build,
+    Pause,
+    /// Opens and closes the world map.
+    Map,
+    /// Opens and closes the developer console.
+    Console,
+}
+
+impl Action {
+    pub const ALL: [Action; 9] = [
+        Action::Left,
+        Action::Right,
+        Action::Jump,
+        Action::Up,
+        Action::Down,
+        Action::Build,
+        Action::Pause,
+        Action::Map,
+        Action::Console,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Action::Left => "left",
+            Action::Right => "right",
+            Action::Jump => "jump",
+            Action::Up => "up",
+            Action::Down => "down",
+            Action::Build => "build",
+            Action::Pause => "pause",
+            Action::Map => "map",
+            Action::Console => "console",
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(default)]
+pub struct KeyBindings {
+    pub left: KeyCode,
+    pub right: KeyCode,
+    pub jump: KeyCode,
+    pub up: KeyCode,
+    pub down: KeyCode,
+    pub build: KeyCode,
+    pub pause: KeyCode,
+    pub map: KeyCode,
+    pub console: KeyCode,
+}
+
+impl Default for KeyBindings {
+    fn default() -> Self {
+        Self {
+            left: KeyCode::KeyA,
+            right: KeyCode::KeyD,
+            jump: KeyCode::Space,
+            up: KeyCode::KeyW,
+            down: KeyCode::KeyS,
+            build: KeyCode::ShiftLeft,
+            pause: KeyCode::Escape,
+            map: KeyCode::KeyM,
+            console: KeyCode::Backquote,
+        }
+    }
+}
+
+impl KeyBindings {
+    pub fn get(&self, action: Action) -> KeyCode {
+        match action {
+            Action::Left => self.left,
+            Action::Right => self.right,
+            Action::Jump => self.jump,
+            Action::Up => self.up,
+            Action::Down => self.down,
+            Action::Build => self.build,
+            Action::Pause => self.pause,
+            Action::Map => self.map,
+            Action::Console => self.console,
+        }
+    }
+
+    /// Whether two actions share a key.
+    pub fn has_clash(&self) -> bool {
+        Action::ALL
+            .iter()
+            .enumerate()
+            .any(|(i, a)| Action::ALL[..i].iter().any(|b| self.get(*a) == self.get(*b)))
+    }
+
+    pub fn set(&mut self, action: Action, key: KeyCode) {
+        match action {
+            Action::Left => self.left = key,
+            Action::Right => self.right = key,
+            Action::Jump => self.jump = key,
+            Action::Up => self.up = key,
+            Action::Down => self.down = key,
+            Action::Build => self.build = key,
+            Action::Pause => self.pause = key,
+            Action::Map => self.map = key,
+            Action::Console => self.console = key,
+        }
+    }
+}
+
+/// How the canvas is scaled up to the window.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Scaling {
+    /// Whole multiples only, with black bars around the canvas.
+    #[default]
+    Integer,
+    /// As large as fits, sampling the nearest canvas pixel. Pixels may
+    /// come out one screen pixel wider than their neighbours.
+    Fit,
+    /// Fills the window, ignoring the aspect ratio.
+    Stretch,
+    /// Like [`Scaling::Fit`], but the canvas is first blown up by a whole
+    /// multiple and then smoothed down, so only pixel edges are blended.
+    SharpBilinear,
+}
+
+impl Scaling {
+    pub const ALL: [Scaling; 4] = [Scaling::Integer, Scaling::Fit, Scaling::Stretch, Scaling::SharpBilinear];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Scaling::Integer => "integer",
+            Scaling::Fit => "fit",
+            Scaling::Stretch => "stretch",
+            Scaling::SharpBilinear => "sharp bilinear",
+        }
+    }
+}
+
+#[derive(Resource, Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(default)]
+pub struct Settings {
+    pub window_width: u32,
+    pub window_height: u32,
+    pub fullscreen: bool,
+    /// Internal resolution the world is drawn at, in canvas pixels.
+    pub canvas_width: u32,
+    pub canvas_height: u32,
+    pub scaling: Scaling,
+    /// Scale of the canvas with [`Scaling::Integer`]; 0 picks the largest
+    /// that fits the window.
+    pub canvas_scale: u32,
+    /// Master volume from 0 to 1.
+    pub volume: f32,
+    pub keys: KeyBindings,
+    /// Shows the speedrun timer and the ghost of the best run.
+    pub speedrun: bool,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Self {
+            window_width: 1280,
+            window_height: 720,
+            fullscreen: false,
+            canvas_width: RES_WIDTH,
+            canvas_height: RES_HEIGHT,
+            scaling: Scaling::Integer,
+            canvas_scale: 0,
+            volume: 0.8,
+            keys: KeyBindings::default(),
+            speedrun: false,
+        }
+    }
+}
+
+impl Settings {
+    /// Clamps values into range, resets a canvas the screen cannot fill and
+    /// drops key bindings that clash.
+    pub fn validated(mut self) -> Self {
+        self.window_width = self.window_width.clamp(MIN_WINDOW.x, MAX_WINDOW.x);
+        self.window_height = self.window_height.clamp(MIN_WINDOW.y, MAX_WINDOW.y);
+        let canvas = self.canvas_size();
+        if canvas.cmplt(MIN_CANVAS).any() || canvas.cmpgt(MAX_CANVAS).any() {
+            warn!(
+                "canvas {}x{} is not between {}x{} and the {}x{} screen, resetting it",
+                canvas.x, canvas.y, MIN_CANVAS.x, MIN_CANVAS.y, MAX_CANVAS.x, MAX_CANVAS.y
+            );
+            self.canvas_width = RES_WIDTH;
+            self.canvas_height = RES_HEIGHT;
+        }
+        self.canvas_scale = self.canvas_scale.min(MAX_CANVAS_SCALE);
+        self.volume = if self.volume.is_finite() { self.volume.clamp(0.0, 1.0) } else { Settings::default().volume };
+
+        let defaults = KeyBindings::default();
+        for (i, action) in Action::ALL.iter().enumerate() {
+            let key = self.keys.get(*action);
+            if Action::ALL[..i].iter().any(|earlier| self.keys.get(*earlier) == key) {
+                warn!("{key:?} is bound twice, resetting {}", action.name());
+                self.keys.set(*action, defaults.get(*action));
+            }
+        }
+        // A reset binding can clash with another one; start over then.
+        if self.keys.has_clash() {
+            self.keys = defaults;
+        }
+        self
+    }
+
+    pub fn canvas_size(&self) -> UVec2 {
+        UVec2::new(self.canvas_width, self.canvas_height)
+    }
+
+    /// Scale of the canvas on each axis in a window of `size`.
+    pub fn canvas_scale_for(&self, size: Vec2, canvas: Vec2) -> Vec2 {
+        let stretch = size / canvas;
+        let fit = stretch.min_element();
+        match self.scaling {
+            Scaling::Integer => {
+                let whole = fit.floor().max(1.0);
+                Vec2::splat(if self.canvas_scale == 0 { whole } else { (self.canvas_scale as f32).min(whole) })
+            }
+            Scaling::Fit | Scaling::SharpBilinear => Vec2::splat(fit),
+            Scaling::Stretch => stretch,
+        }
+    }
+
+    /// Whole multiple the canvas is blown up by before the final scale, so
+    /// that [`Scaling::SharpBilinear`] only ever shrinks it. 1 for the
+    /// other modes.
+    pub fn prescale_for(&self, scale: Vec2) -> u32 {
+        if self.scaling == Scaling::SharpBilinear {
+            (scale.max_element().ceil() as u32).clamp(1, MAX_CANVAS_SCALE)
+        } else {
+            1
+        }
+    }
+
+    pub fn window(&self) -> Window {
+        Window {
+            title: "terra".to_string(),
+            resolution: WindowResolution::new(self.window_width as f32, self.window_height as f32),
+            mode: self.window_mode(),
+            ..default()
+        }
+    }
+
+    fn window_mode(&self) -> WindowMode {
+        if self.fullscreen {
+            WindowMode::BorderlessFullscreen(MonitorSelection::Current)
+        } else {
+            WindowMode::Windowed
+        }
+    }
+}
+
+pub fn settings_path() -> PathBuf {
+    dirs::config_dir().unwrap_or_default().join(APP_DIR).join(SETTINGS_FILE)
+}
+
+/// Reads the settings file, using defaults for anything missing.
+///
+/// A file that does not parse gives the defaults too, but is reported with
+/// where it went wrong and renamed to [`UNREADABLE_FILE`], so that saving the
+/// defaults does not silently lose everything else in it.
+pub fn load_settings() -> Settings {
+    let path = settings_path();
+    let Ok(text) = fs::read_to_string(&path) else {
+        return Settings::default();
+    };
+    match ron::from_str::<Settings>(&text) {
+        Ok(settings) => settings.validated(),
+        Err(err) => {
+            let kept = path.with_file_name(UNREADABLE_FILE);
+            match fs::rename(&path, &kept) {
+                Ok(()) => error!("unreadable settings {}: {err}; using the defaults, the file is kept as {}", path.display(), kept.display()),
+                Err(rename_err) => error!("unreadable settings {}: {err}; using the defaults, and could not set it aside: {rename_err}", path.display()),
+            }
+            Settings::default()
+        }
+    }
+}
+
+pub fn write_settings(settings: &Settings) {
+    let path = settings_path();
+    let text = match ron::ser::to_string_pretty(settings, ron::ser::PrettyConfig::default()) {
+        Ok(text) => text,
+        Err(err) => {
+            error!("could not serialize settings: {err}");
+            return;
+        }
+    };
+    let dir = path.parent().map(PathBuf::from).unwrap_or_default();
+    if let Err(err) = fs::create_dir_all(dir).and_then(|_| fs::write(&path, text)) {
+        error!("could not write {}: {err}", path.display());
+    }
+}
+
+/// Pushes changed settings to the window and the audio volume. The canvas
+/// is picked up by [`fit_canvas`](super::fit_canvas).
+///
+/// The window size and mode are only pushed when those settings change, so
+/// changing anything else keeps a window the player resized by hand.
+fn apply_settings(
+    settings: Res<Settings>,
+    mut window: Single<&mut Window, With<PrimaryWindow>>,
+    mut volume: ResMut<GlobalVolume>,
+    mut applied: Local<Option<(WindowMode, UVec2)>>,
+) {
+    if !settings.is_changed() {
+        return;
+    }
+    let mode = settings.window_mode();
+    let size = UVec2::new(settings.window_width, settings.window_height);
+    if *applied != Some((mode, size)) {
+        *applied = Some((mode, size));
+        if window.mode != mode {
+            window.mode = mode;
+        }
+        let size = size.as_vec2();
+        if !settings.fullscreen && window.resolution.size() != size {
+            window.resolution.set(size.x, size.y);
+        }
+    }
+    volume.volume = Volume::Linear(settings.volume);
+}
+
+fn save_settings(settings: Res<Settings>) {
+    if settings.is_changed() && !settings.is_added() {
+        write_settings(&settings);
+    }
+}
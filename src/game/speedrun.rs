@@ -0,0 +1,261 @@
+//! Optional speedrun mode: a timer from the first step to the finish screen,
+//! a split at every screen change and a ghost of the world's best run.
+//!
+//! The best run is kept next to the world save, so every world has its own.
+//! Time only runs with the world, not in menus or while paused.
+
+use std::fs;
+
+use bevy::{prelude::*, sprite::Anchor};
+use serde::{Deserialize, Serialize};
+
+use crate::game::{
+    player::{move_world, Player},
+    replay::REPLAY_WORLD,
+    save::world_dir,
+    settings::Settings,
+    state::{GameState, GameplaySet, SelectedWorld},
+    tilemap::{ScreenChanged, TileMap, CHUNK_COLS, CHUNK_ROWS, TILE_SIZE},
+    PIXEL_PERFECT_LAYERS,
+};
+
+const BEST_RUN_FILE: &str = "best_run.ron";
+/// Reaching this screen ends a run: five screens east of the spawn.
+pub const FINISH_SCREEN: IVec2 = IVec2::new(5, 0);
+/// Seconds between two recorded ghost positions.
+const GHOST_INTERVAL: f32 = 0.05;
+const GHOST_COLOUR: Color = Color::srgba(0.6, 0.8, 1.0, 0.4);
+/// Behind the player and the tiles, in front of the walls.
+const GHOST_Z: f32 = -0.5;
+
+pub struct SpeedrunPlugin;
+
+impl Plugin for SpeedrunPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(OnEnter(GameState::InGame), (load_best_run, spawn_ghost));
+        app.add_systems(OnExit(GameState::InGame), unload_speedrun);
+        app.add_systems(
+            Update,
+            (start_run, time_run.after(move_world)).chain().in_set(GameplaySet).run_if(speedrun_enabled),
+        );
+        app.add_systems(Update, move_ghost.in_set(GameplaySet));
+    }
+}
+
+/// Time since the start when the run entered a screen.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub struct Split {
+    pub screen: IVec2,
+    pub time: f32,
+}
+
+/// Fastest finished run of a world.
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct BestRun {
+    pub time: f32,
+    pub splits: Vec<Split>,
+    /// Player positions every `GHOST_INTERVAL` seconds, in world pixels
+    /// counted from screen `(0, 0)`.
+    pub ghost: Vec<Vec2>,
+}
+
+impl BestRun {
+    /// Where the ghost is `time` seconds into the run.
+    pub fn ghost_at(&self, time: f32) -> Option<Vec2> {
+        let last = self.ghost.len().checked_sub(1)?;
+        let at = (time / GHOST_INTERVAL).max(0.0);
+        let i = (at.floor() as usize).min(last);
+        let next = (i + 1).min(last);
+        Some(self.ghost[i].lerp(self.ghost[next], at.fract()))
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum RunState {
+    /// Waiting for the player to move.
+    #[default]
+    Ready,
+    Running,
+    Finished,
+}
+
+/// The run in the loaded world.
+#[derive(Resource, Default)]
+pub struct Speedrun {
+    pub state: RunState,
+    /// Seconds since the start.
+    pub time: f32,
+    pub splits: Vec<Split>,
+    ghost: Vec<Vec2>,
+    pub best: Option<BestRun>,
+    /// Whether the finished run beat `best`, which it then replaced.
+    pub new_best: bool,
+    /// Set when the console moved the player or changed the world; such a
+    /// run still finishes but never becomes the best run.
+    pub invalid: bool,
+}
+
+impl Speedrun {
+    /// How far ahead (negative) or behind the best run the last split was.
+    pub fn last_split_delta(&self) -> Option<f32> {
+        let i = self.splits.len().checked_sub(1)?;
+        let best = self.best.as_ref()?.splits.get(i)?;
+        (best.screen == self.splits[i].screen).then(|| self.splits[i].time - best.time)
+    }
+}
+
+/// Ghost of the best run.
+#[derive(Component)]
+struct Ghost;
+
+fn speedrun_enabled(settings: Res<Settings>) -> bool {
+    settings.speedrun
+}
+
+/// World pixels of the top-left corner of `screen`, counted from screen `(0, 0)`.
+fn screen_offset(screen: IVec2) -> Vec2 {
+    let size = Vec2::new(CHUNK_COLS as f32, CHUNK_ROWS as f32) * TILE_SIZE as f32;
+    Vec2::new(screen.x as f32 * size.x, -screen.y as f32 * size.y)
+}
+
+/// Keeps the current run from becoming the best run, for console commands
+/// that move the player or change the world.
+pub fn disqualify_run(world: &mut World) {
+    if let Some(mut run) = world.get_resource_mut::<Speedrun>() {
+        run.invalid = true;
+    }
+}
+
+/// `m:ss.cc`, with a sign when `signed`.
+pub fn format_time(seconds: f32, signed: bool) -> String {
+    let sign = if seconds < 0.0 { "-" } else if signed { "+" } else { "" };
+    let centis = (seconds.abs() * 100.0).round() as u32;
+    format!("{sign}{}:{:02}.{:02}", centis / 6000, centis / 100 % 60, centis % 100)
+}
+
+fn read_best_run(world: &str) -> Option<BestRun> {
+    let path = world_dir(world).join(BEST_RUN_FILE);
+    let text = fs::read_to_string(&path).ok()?;
+    match ron::from_str(&text) {
+        Ok(best) => Some(best),
+        Err(err) => {
+            warn!("ignoring unreadable best run {}: {err}", path.display());
+            None
+        }
+    }
+}
+
+fn write_best_run(world: &str, best: &BestRun) {
+    let path = world_dir(world).join(BEST_RUN_FILE);
+    let text = match ron::ser::to_string(best) {
+        Ok(text) => text,
+        Err(err) => {
+            error!("could not serialize best run: {err}");
+            return;
+        }
+    };
+    if let Err(err) = fs::create_dir_all(world_dir(world)).and_then(|_| fs::write(&path, text)) {
+        error!("could not write {}: {err}", path.display());
+    }
+}
+
+fn load_best_run(mut commands: Commands, world: Res<SelectedWorld>) {
+    commands.insert_resource(Speedrun { best: read_best_run(&world.name), ..default() });
+}
+
+fn unload_speedrun(mut commands: Commands) {
+    commands.remove_resource::<Speedrun>();
+}
+
+fn spawn_ghost(mut commands: Commands, asset_server: Res<AssetServer>) {
+    let mut sprite = Sprite::from_image(asset_server.load("player.png"));
+    sprite.anchor = Anchor::TopLeft;
+    sprite.color = GHOST_COLOUR;
+    commands.spawn((
+        Ghost,
+        sprite,
+        Transform::from_xyz(0.0, 0.0, GHOST_Z),
+        Visibility::Hidden,
+        PIXEL_PERFECT_LAYERS,
+        StateScoped(GameState::InGame),
+    ));
+}
+
+/// Starts the clock on the first step or jump, with the ghost where the
+/// player stands.
+fn start_run(
+    mut run: ResMut<Speedrun>,
+    keyboard_input: Res<ButtonInput<KeyCode>>,
+    settings: Res<Settings>,
+    tilemap: Res<TileMap>,
+    player: Single<&Transform, With<Player>>,
+) {
+    if run.state != RunState::Ready {
+        return;
+    }
+    let keys = [settings.keys.left, settings.keys.right, settings.keys.jump];
+    if keyboard_input.any_pressed(keys) {
+        run.state = RunState::Running;
+        run.ghost.push(screen_offset(tilemap.position.as_ivec2()) + player.translation.truncate());
+    }
+}
+
+fn time_run(
+    mut run: ResMut<Speedrun>,
+    mut screen_changed: EventReader<ScreenChanged>,
+    time: Res<Time>,
+    tilemap: Res<TileMap>,
+    player: Single<&Transform, With<Player>>,
+    world: Res<SelectedWorld>,
+) {
+    if run.state != RunState::Running {
+        screen_changed.clear();
+        return;
+    }
+    let before = run.time;
+    run.time += time.delta_secs();
+    // A ghost position for every interval the tick crossed.
+    let position = screen_offset(tilemap.position.as_ivec2()) + player.translation.truncate();
+    for _ in (before / GHOST_INTERVAL).floor() as u32..(run.time / GHOST_INTERVAL).floor() as u32 {
+        run.ghost.push(position);
+    }
+
+    for ScreenChanged { position: screen } in screen_changed.read() {
+        let time = run.time;
+        run.splits.push(Split { screen: *screen, time });
+        if *screen == FINISH_SCREEN {
+            run.state = RunState::Finished;
+        }
+    }
+    if run.state != RunState::Finished {
+        return;
+    }
+    info!("run finished in {}", format_time(run.time, false));
+    // Replays play back in a throwaway world whose best run is never shown.
+    if run.invalid || world.name == REPLAY_WORLD || run.best.as_ref().is_some_and(|best| best.time <= run.time) {
+        return;
+    }
+    let best = BestRun { time: run.time, splits: run.splits.clone(), ghost: std::mem::take(&mut run.ghost) };
+    write_best_run(&world.name, &best);
+    run.best = Some(best);
+    run.new_best = true;
+}
+
+fn move_ghost(
+    run: Option<Res<Speedrun>>,
+    settings: Res<Settings>,
+    tilemap: Res<TileMap>,
+    mut ghost: Single<(&mut Transform, &mut Visibility), With<Ghost>>,
+) {
+    let (transform, visibility) = &mut *ghost;
+    let position = run
+        .filter(|run| settings.speedrun && run.state == RunState::Running)
+        .and_then(|run| run.best.as_ref()?.ghost_at(run.time));
+    let Some(position) = position else {
+        **visibility = Visibility::Hidden;
+        return;
+    };
+    let local = position - screen_offset(tilemap.position.as_ivec2());
+    transform.translation = local.extend(GHOST_Z);
+    **visibility = Visibility::Inherited;
+}
@@ -0,0 +1,58 @@
+use bevy::prelude::*;
+
+pub struct StatePlugin;
+
+impl Plugin for StatePlugin {
+    fn build(&self, app: &mut App) {
+        app.init_state::<GameState>();
+        app.add_sub_state::<Pause>();
+        app.enable_state_scoped_entities::<GameState>();
+        app.enable_state_scoped_entities::<Pause>();
+        app.configure_sets(Update, GameplaySet.run_if(in_state(Pause::Running)));
+        app.configure_sets(FixedUpdate, GameplaySet.run_if(in_state(Pause::Running)));
+        app.configure_sets(PostUpdate, GameplaySet.run_if(in_state(Pause::Running)));
+        app.add_systems(Update, finish_boot.run_if(in_state(GameState::Boot)));
+    }
+}
+
+/// Top-level screens of the game.
+#[derive(States, Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum GameState {
+    /// Static data is loaded during `Startup`; the game leaves this state on the first update.
+    #[default]
+    Boot,
+    MainMenu,
+    /// Picking a saved world or creating a new one.
+    WorldSelect,
+    /// A world is loaded. Entities spawned for it carry `StateScoped(GameState::InGame)`.
+    InGame,
+}
+
+/// Whether the world is simulated while [`GameState::InGame`].
+#[derive(SubStates, Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+#[source(GameState = GameState::InGame)]
+pub enum Pause {
+    #[default]
+    Running,
+    Paused,
+    /// The full-screen world map is open.
+    Map,
+    /// The developer console is open and takes the keyboard.
+    Console,
+}
+
+/// Systems that read or change the world. They only run in game while not paused,
+/// so they can rely on the world resources existing.
+#[derive(SystemSet, Debug, Clone, PartialEq, Eq, Hash)]
+pub struct GameplaySet;
+
+/// World chosen in world select, read when entering [`GameState::InGame`].
+#[derive(Resource, Debug, Clone)]
+pub struct SelectedWorld {
+    pub name: String,
+    pub seed: u32,
+}
+
+fn finish_boot(mut next: ResMut<NextState<GameState>>) {
+    next.set(GameState::MainMenu);
+}
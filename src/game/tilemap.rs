@@ -1,48 +1,276 @@
-use bevy::{color::palettes::css::{BLACK, RED, WHITE}, prelude::*, sprite::Anchor};
-use noise::{NoiseFn, Perlin};
-use rand::Rng;
-use crate::game::{player::{draw_point, draw_point_red}, OuterCamera, PixelatedCanvas, PIXEL_PERFECT_LAYERS, RES_HEIGHT, RES_WIDTH};
-use crate::game::player::{Player};
+use std::collections::HashMap;
+
+use bevy::{color::palettes::css::WHITE, prelude::*, sprite::Anchor};
+use serde::{Deserialize, Serialize};
+use crate::game::{console::{command_word, AddConsoleCommand, Args, CommandResult, ConsoleCommand, Param, ParamKind}, drops::{drop_item, DroppedItem}, enemy::Enemy, falling::FallingTile, item::{Inventory, ItemKind}, liquid::LiquidMap, map::Discovered, pathfinding::NavCache, player::{draw_point, draw_point_red, Player}, projectile::Projectile, raycast::cell_of, settings::Settings, speedrun::disqualify_run, state::{GameState, GameplaySet, SelectedWorld}, worldgen::WorldGen, cursor::CursorWorld, Canvas, PixelatedCanvas, ViewCentre, PIXEL_PERFECT_LAYERS, RES_HEIGHT, RES_WIDTH};
 
 
-pub const TCOLS: usize = 40;
-pub const TROWS: usize = 23;
-pub const COLS: usize = 38;//40;
-pub const ROWS: usize = 21;//23;
 pub const TILE_SIZE: u32 = 8;
+/// Tiles loaded for one screen, enough to cover it.
+pub const TCOLS: usize = RES_WIDTH.div_ceil(TILE_SIZE) as usize;
+pub const TROWS: usize = RES_HEIGHT.div_ceil(TILE_SIZE) as usize;
+/// Tiles inside the border of a screen.
+pub const COLS: usize = TCOLS - 2;
+pub const ROWS: usize = TROWS - 2;
+/// Tiles between the origins of two horizontally adjacent screens.
+pub const CHUNK_COLS: i32 = TCOLS as i32 - 1;
+/// Tiles between the origins of two vertically adjacent screens.
+pub const CHUNK_ROWS: i32 = TROWS as i32 - 1;
+/// Tile sprites in `block.png`, `TILE_SIZE` pixels each.
+pub const ATLAS_PATH: &str = "block.png";
+pub const ATLAS_COLUMNS: u32 = 4;
+pub const ATLAS_ROWS: u32 = 3;
+/// Most tiles one `fill` or `clear` command may change.
+const MAX_FILL: i64 = 10_000;
 
 pub struct TileMapPlugin;
 
 impl Plugin for TileMapPlugin {
     fn build(&self, app: &mut App) {
-        app.add_systems(Startup, (setup_map, spawn_tiles).chain());
-		app.add_systems(Update, update_tiles);
+        app.add_systems(OnEnter(GameState::InGame), (setup_map, spawn_tiles).chain());
+        app.add_systems(OnExit(GameState::InGame), unload_map);
+		app.add_systems(Update, update_tiles.in_set(GameplaySet));
+		app.add_event::<TileChanged>();
+		app.add_event::<ScreenChanged>();
+		app.add_systems(PostUpdate, (sync_tile_sprites, publish_tile_changes).in_set(GameplaySet));
+        let region = [
+            Param::new("x1", ParamKind::Int),
+            Param::new("y1", ParamKind::Int),
+            Param::new("x2", ParamKind::Int),
+            Param::new("y2", ParamKind::Int),
+        ];
+        app.add_console_command(ConsoleCommand {
+            name: "fill",
+            help: "fills global tiles from x1, y1 to x2, y2 with a tile",
+            params: region.into_iter().chain([Param::new("tile", ParamKind::Choice(tile_names))]).collect(),
+            run: run_fill,
+        });
+        app.add_console_command(ConsoleCommand {
+            name: "clear",
+            help: "empties global tiles and liquid from x1, y1 to x2, y2",
+            params: region.to_vec(),
+            run: run_clear,
+        });
+        app.add_console_command(ConsoleCommand {
+            name: "seed",
+            help: "shows the seed, or regenerates the world from a new one",
+            params: vec![Param::optional("seed", ParamKind::Word)],
+            run: run_seed,
+        });
     }
 }
 
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum TileKind {
+    Dirt,
+    Stone,
+    Torch,
+    Sand,
+    Gravel,
+}
+
+/// Static properties shared by every tile of a [`TileKind`].
+pub struct TileDef {
+    pub name: &'static str,
+    /// Whether the player collides with it.
+    pub solid: bool,
+    /// Light lost when light passes through this tile.
+    pub opacity: u8,
+    /// Light emitted by the tile itself.
+    pub emission: u8,
+    /// Tint applied to the atlas sprite.
+    pub tint: Color,
+    /// Whether the tile drops when the cell below it is empty.
+    pub falls: bool,
+    /// Explosion strength needed to break it.
+    pub blast_resistance: f32,
+    /// Colour of its pixel on the world map.
+    pub map_colour: Color,
+}
+
+const DIRT: TileDef = TileDef { name: "dirt", solid: true, opacity: 3, emission: 0, tint: Color::WHITE, falls: false, blast_resistance: 1.0, map_colour: Color::srgb(0.55, 0.38, 0.22) };
+const STONE: TileDef = TileDef { name: "stone", solid: true, opacity: 4, emission: 0, tint: Color::srgb(0.7, 0.7, 0.75), falls: false, blast_resistance: 2.5, map_colour: Color::srgb(0.5, 0.5, 0.55) };
+const TORCH: TileDef = TileDef { name: "torch", solid: false, opacity: 1, emission: 14, tint: Color::srgb(1.0, 0.8, 0.3), falls: false, blast_resistance: 0.0, map_colour: Color::srgb(1.0, 0.8, 0.3) };
+const SAND: TileDef = TileDef { name: "sand", solid: true, opacity: 3, emission: 0, tint: Color::srgb(0.95, 0.85, 0.55), falls: true, blast_resistance: 0.5, map_colour: Color::srgb(0.9, 0.8, 0.5) };
+const GRAVEL: TileDef = TileDef { name: "gravel", solid: true, opacity: 3, emission: 0, tint: Color::srgb(0.55, 0.5, 0.5), falls: true, blast_resistance: 0.8, map_colour: Color::srgb(0.5, 0.45, 0.45) };
+
+impl TileKind {
+    pub const ALL: [TileKind; 5] = [TileKind::Dirt, TileKind::Stone, TileKind::Torch, TileKind::Sand, TileKind::Gravel];
+
+    pub fn def(&self) -> &'static TileDef {
+        match self {
+            TileKind::Dirt => &DIRT,
+            TileKind::Stone => &STONE,
+            TileKind::Torch => &TORCH,
+            TileKind::Sand => &SAND,
+            TileKind::Gravel => &GRAVEL,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
 pub struct Tile {
     pub tile_index: usize, // Which sprite in the atlas
+    pub kind: TileKind,
+}
+
+impl Tile {
+    pub fn solid(&self) -> bool {
+        self.kind.def().solid
+    }
+}
+
+pub type TileGrid = [[Option<Tile>; TCOLS]; TROWS];
+
+/// Global tile coordinates of cell `(0, 0)` of the screen at `screen`.
+pub fn screen_origin(screen: IVec2) -> IVec2 {
+    IVec2::new(screen.x * CHUNK_COLS - 1, screen.y * CHUNK_ROWS - 1)
 }
 
+/// The two tile layers of the world: colliding foreground tiles and the
+/// non-colliding walls drawn behind them.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum TileLayer {
+    Foreground,
+    Background,
+}
+
+/// Sent for every tile changed through [`TileMap::set_global`], loaded or not.
+#[derive(Event, Debug, Clone, Copy)]
+pub struct TileChanged {
+    pub layer: TileLayer,
+    pub pos: IVec2,
+}
+
+/// Sent when a screen has been generated into the [`TileMap`], at startup and
+/// every time [`move_world`](crate::game::player::move_world) moves to another one.
+#[derive(Event, Debug, Clone, Copy)]
+pub struct ScreenChanged {
+    pub position: IVec2,
+}
+
+/// Background walls are drawn this much darker than the same tile in front.
+pub const WALL_SHADE: f32 = 0.45;
+const WALL_Z: f32 = -1.0;
+
 #[derive(Resource)]
 pub struct TileMap {
-    pub tiles: [[Option<Tile>; TCOLS]; TROWS],
+    tiles: TileGrid,
     pub entities: [[Option<Entity>; TCOLS]; TROWS],
+    /// Background layer, see [`TileLayer::Background`].
+    walls: TileGrid,
+    pub wall_entities: [[Option<Entity>; TCOLS]; TROWS],
 	pub layout: Handle<TextureAtlasLayout>,
+	pub texture: Handle<Image>,
     pub position: Vec2,
+    /// Tiles changed since generation, by layer and global tile coordinates.
+    pub edits: HashMap<(TileLayer, IVec2), Option<Tile>>,
+    /// Loaded cells whose sprite no longer matches their tile.
+    pub dirty: Vec<(TileLayer, usize, usize)>,
+    /// Changes made since the last [`TileChanged`] events went out.
+    pub changed: Vec<TileChanged>,
 }
 
 impl TileMap {
-    pub fn new(layout: Handle<TextureAtlasLayout>) -> Self {
+    pub fn new(layout: Handle<TextureAtlasLayout>, texture: Handle<Image>) -> Self {
         Self {
             tiles: [[None; TCOLS]; TROWS],
             entities: [[None; TCOLS]; TROWS],
+            walls: [[None; TCOLS]; TROWS],
+            wall_entities: [[None; TCOLS]; TROWS],
 			layout,
+			texture,
             position: Vec2::ZERO,
+            edits: HashMap::new(),
+            dirty: Vec::new(),
+            changed: Vec::new(),
+        }
+    }
+
+    pub fn grid(&self, layer: TileLayer) -> &TileGrid {
+        match layer {
+            TileLayer::Foreground => &self.tiles,
+            TileLayer::Background => &self.walls,
+        }
+    }
+
+    fn grid_mut(&mut self, layer: TileLayer) -> &mut TileGrid {
+        match layer {
+            TileLayer::Foreground => &mut self.tiles,
+            TileLayer::Background => &mut self.walls,
+        }
+    }
+
+    fn entities_mut(&mut self, layer: TileLayer) -> &mut [[Option<Entity>; TCOLS]; TROWS] {
+        match layer {
+            TileLayer::Foreground => &mut self.entities,
+            TileLayer::Background => &mut self.wall_entities,
+        }
+    }
+
+    /// Global tile coordinates of cell `(0, 0)` of the loaded screen.
+    pub fn origin(&self) -> IVec2 {
+        screen_origin(self.position.as_ivec2())
+    }
+
+    pub fn to_global(&self, x: usize, y: usize) -> IVec2 {
+        self.origin() + IVec2::new(x as i32, y as i32)
+    }
+
+    /// Loaded cell holding global tile `g`, if it is on this screen.
+    pub fn to_local(&self, g: IVec2) -> Option<(usize, usize)> {
+        let local = g - self.origin();
+        if local.x < 0 || local.y < 0 || local.x >= TCOLS as i32 || local.y >= TROWS as i32 {
+            None
+        } else {
+            Some((local.x as usize, local.y as usize))
+        }
+    }
+
+    /// Tile of `layer` at global coordinates, whether or not its screen is loaded.
+    pub fn tile_global(&self, worldgen: &WorldGen, layer: TileLayer, g: IVec2) -> Option<Tile> {
+        if let Some((x, y)) = self.to_local(g) {
+            return self.grid(layer)[y][x];
+        }
+        match self.edits.get(&(layer, g)) {
+            Some(tile) => *tile,
+            None => worldgen.generate(layer, g),
         }
     }
 
+    /// Whether the foreground tile at global coordinates collides; walls never do.
+    pub fn solid_global(&self, worldgen: &WorldGen, g: IVec2) -> bool {
+        self.tile_global(worldgen, TileLayer::Foreground, g).is_some_and(|t| t.solid())
+    }
+
+    /// Changes a tile anywhere in the world, remembering the edit for when its
+    /// screen is generated again.
+    pub fn set_global(&mut self, layer: TileLayer, g: IVec2, tile: Option<Tile>) {
+        self.edits.insert((layer, g), tile);
+        self.changed.push(TileChanged { layer, pos: g });
+        if let Some((x, y)) = self.to_local(g) {
+            self.grid_mut(layer)[y][x] = tile;
+            self.dirty.push((layer, x, y));
+        }
+    }
+
+    /// Fills both layers for the screen at `position` from the generator and the edits.
+    pub fn load_screen(&mut self, worldgen: &WorldGen) {
+        for layer in [TileLayer::Foreground, TileLayer::Background] {
+            for y in 0..TROWS {
+                for x in 0..TCOLS {
+                    let g = self.to_global(x, y);
+                    let tile = match self.edits.get(&(layer, g)) {
+                        Some(tile) => *tile,
+                        None => worldgen.generate(layer, g),
+                    };
+                    self.grid_mut(layer)[y][x] = tile;
+                }
+            }
+        }
+        self.dirty.clear();
+    }
+
     pub fn get_entity_at(&self, x: usize, y: usize) -> Option<Entity> {
         if (x > COLS) || ( y > ROWS) {
             None
@@ -51,26 +279,15 @@ impl TileMap {
         }
     }
     
-    pub fn get_tile_at(&self, x: usize, y: usize) -> Option<Tile> {
-        if (x >= TCOLS) || ( y >= TROWS) {
-            None
-        } else {
-            self.tiles[y][x]
-        }
+    /// Loaded tile of `layer` in cell `(x, y)`, if the cell is on the screen.
+    pub fn tile_at(&self, layer: TileLayer, x: usize, y: usize) -> Option<Tile> {
+        self.grid(layer).get(y)?.get(x).copied().flatten()
     }
 
-    pub fn getTile(&self, x: usize, y: usize) -> Option<Tile> {
-        self.tiles[y][x]
-    }
-    
     pub fn get(&self, x: usize, y: usize) -> Option<Entity> {
         self.entities[y][x]
     }
 
-    pub fn set(&mut self, x: usize, y: usize, tile: Option<Tile>) {
-        self.tiles[y][x] = tile;
-    }
-
     pub fn to_map_coords(&self, pos: Vec2) -> Vec2 {
         let x = pos.x / TILE_SIZE as f32;
         let y = pos.y / TILE_SIZE as f32;
@@ -78,102 +295,192 @@ impl TileMap {
         Vec2::new(x,y)
     }
 
-    pub fn collide_at(&self, mut pos: Vec2) -> bool {
-        let tile_x = (pos.x / TILE_SIZE as f32).floor() as i32;
-        pos.y += 8.;
-        //(y as f32-(ROWS as f32)+1.) * TILE_SIZE as f32,
-        //let tile_y = ((pos.y + 0.5 * TILE_SIZE as f32) / TILE_SIZE as f32).floor() as i32 * -1.0;
-        let tile_y = ((((pos.y / TILE_SIZE as f32)).floor()) as i32) * - 1;
+    /// Cell under a canvas position, as [`cell_of`] finds it for raycasts.
+    pub fn cell_at(&self, pos: Vec2) -> IVec2 {
+        cell_of(pos)
+    }
 
-        let e = self.get_tile_at(tile_x as usize, tile_y as usize);
-        e.is_some()
+    pub fn collide_at(&self, pos: Vec2) -> bool {
+        let cell = self.cell_at(pos);
+        let e = self.tile_at(TileLayer::Foreground, cell.x as usize, cell.y as usize);
+        e.is_some_and(|tile| tile.solid())
     }
 }
 
 
 
 pub fn setup_map(
-	mut commands: Commands,
-	mut texture_atlas_layouts: ResMut<Assets<TextureAtlasLayout>>,
+    mut commands: Commands,
+    mut texture_atlas_layouts: ResMut<Assets<TextureAtlasLayout>>,
+    asset_server: Res<AssetServer>,
+    world: Res<SelectedWorld>,
+    mut screen_changed: EventWriter<ScreenChanged>,
 ) {
-	let layout = TextureAtlasLayout::from_grid(UVec2::splat(8), 4, 3, None, None);
+    let layout = TextureAtlasLayout::from_grid(UVec2::splat(TILE_SIZE), ATLAS_COLUMNS, ATLAS_ROWS, None, None);
     let h_layout = texture_atlas_layouts.add(layout);
-	let mut tilemap = TileMap::new(h_layout);
+    let mut tilemap = TileMap::new(h_layout, asset_server.load(ATLAS_PATH));
 
-    let mut rng = rand::thread_rng();
-    let perlin = Perlin::new(1);
+    let worldgen = WorldGen::new(world.seed);
+    tilemap.load_screen(&worldgen);
+    screen_changed.write(ScreenChanged { position: tilemap.position.as_ivec2() });
 
-    for y in -1..=(ROWS as i32) {
-        for x in -1..=(COLS as i32) {
-            let gx = (tilemap.position.x * 39. + x as f32) as f64 * 0.1;
-            let gy = (tilemap.position.y * 22. + y as f32) as f64 * 0.1;
-            let val = perlin.get([gx, gy, 0.1]);
+    commands.insert_resource(worldgen);
+    commands.insert_resource(tilemap);
+}
 
-            if val > 0.3 {
-                tilemap.set((x+1) as usize, (y+1) as usize, Some(Tile { tile_index: rng.gen_range(0..12) }));
-            }
-        }
+/// Loads the screen at `screen` the way [`move_world`](crate::game::player::move_world)
+/// does, for code with the whole world at hand.
+pub fn change_screen(world: &mut World, screen: IVec2) {
+    world.resource_scope(|world, mut tilemap: Mut<TileMap>| {
+        tilemap.position = screen.as_vec2();
+        tilemap.load_screen(world.resource::<WorldGen>());
+        respawn_tile_sprites(&mut world.commands(), &mut tilemap);
+    });
+    world.flush();
+    world.send_event(ScreenChanged { position: screen });
+}
+
+fn tile_names(_: &World) -> Vec<String> {
+    TileKind::ALL.iter().map(|kind| command_word(kind.def().name)).collect()
+}
+
+fn run_fill(world: &mut World, args: &Args) -> CommandResult {
+    let kind = TileKind::ALL
+        .into_iter()
+        .find(|kind| command_word(kind.def().name) == args.word(4))
+        .expect("checked against the tile names");
+    fill_region(world, args, Some(kind))
+}
+
+fn run_clear(world: &mut World, args: &Args) -> CommandResult {
+    fill_region(world, args, None)
+}
+
+/// Sets the foreground of the region in the first four arguments, draining
+/// any liquid there.
+fn fill_region(world: &mut World, args: &Args, kind: Option<TileKind>) -> CommandResult {
+    let (from, to) = (IVec2::new(args.int(0), args.int(1)), IVec2::new(args.int(2), args.int(3)));
+    let (low, high) = (from.min(to), from.max(to));
+    let count = (high.x as i64 - low.x as i64 + 1) * (high.y as i64 - low.y as i64 + 1);
+    if count > MAX_FILL {
+        return Err(format!("{count} tiles is too many, at most {MAX_FILL}"));
+    }
+    fill_tiles(world, low, high, kind);
+    Ok(format!("{} {count} tiles", if kind.is_some() { "filled" } else { "cleared" }))
+}
+
+/// Sets the foreground of every global tile from `from` to `to`, both
+/// included, and drains any liquid there.
+pub fn fill_tiles(world: &mut World, from: IVec2, to: IVec2, kind: Option<TileKind>) {
+    let (low, high) = (from.min(to), from.max(to));
+    let tile = kind.map(|kind| Tile { tile_index: 0, kind });
+    let cells = || (low.y..=high.y).flat_map(move |y| (low.x..=high.x).map(move |x| IVec2::new(x, y)));
+    let mut tilemap = world.resource_mut::<TileMap>();
+    for g in cells() {
+        tilemap.set_global(TileLayer::Foreground, g, tile);
+    }
+    let mut liquids = world.resource_mut::<LiquidMap>();
+    for g in cells() {
+        liquids.set(g, None);
+    }
+}
+
+/// Swaps the generator for one with another seed, dropping every edit and
+/// liquid, and reloads the screen. The world is saved with the new seed.
+fn run_seed(world: &mut World, args: &Args) -> CommandResult {
+    if !args.given(0) {
+        return Ok(format!("seed {}", world.resource::<WorldGen>().seed));
+    }
+    let seed: u32 = args.word(0).parse().map_err(|_| format!("seed: expected a number up to {}, got {}", u32::MAX, args.word(0)))?;
+    let config = world.resource::<WorldGen>().config;
+    world.insert_resource(WorldGen::with_config(seed, config));
+    world.resource_mut::<SelectedWorld>().seed = seed;
+    world.resource_mut::<LiquidMap>().clear();
+    if let Some(mut cache) = world.get_resource_mut::<NavCache>() {
+        cache.clear();
+    }
+    if let Some(mut discovered) = world.get_resource_mut::<Discovered>() {
+        discovered.0.clear();
     }
+    // Nothing of the old world is left standing in the new one.
+    despawn_all::<FallingTile>(world);
+    despawn_all::<DroppedItem>(world);
+    despawn_all::<Enemy>(world);
+    despawn_all::<Projectile>(world);
+    disqualify_run(world);
+    let mut tilemap = world.resource_mut::<TileMap>();
+    tilemap.edits.clear();
+    let screen = tilemap.position.as_ivec2();
+    change_screen(world, screen);
+    Ok(format!("regenerated with seed {seed}"))
+}
 
-	commands.insert_resource(tilemap);
+fn despawn_all<T: Component>(world: &mut World) {
+    let entities: Vec<Entity> = world.query_filtered::<Entity, With<T>>().iter(world).collect();
+    for entity in entities {
+        world.despawn(entity);
+    }
+}
+
+/// Drops the world resources; the tile sprites go with the other in-game entities.
+fn unload_map(mut commands: Commands) {
+    commands.remove_resource::<TileMap>();
+    commands.remove_resource::<WorldGen>();
 }
 
 fn update_tiles(
-    //camera_query: Single<(&Camera, &GlobalTransform)>,
-    tilemap: ResMut<TileMap>,
+    mut tilemap: ResMut<TileMap>,
     mut sprites: Query<&mut Sprite, Without<PixelatedCanvas>>,
-    window: Single<&Window>,
-    canvas_query: Query<(&Transform, &Sprite), With<PixelatedCanvas>>,
-	mut gizmos: Gizmos,
+    cursor: Res<CursorWorld>,
+    canvas: Option<Res<Canvas>>,
+    view: Res<ViewCentre>,
+    mouse: Res<ButtonInput<MouseButton>>,
+    keyboard_input: Res<ButtonInput<KeyCode>>,
+    settings: Res<Settings>,
+    worldgen: Res<WorldGen>,
+    mut inventories: Query<&mut Inventory, With<Player>>,
+    mut commands: Commands,
+    mut gizmos: Gizmos,
 ) {
-    if let Some(cursor_pos) = window.cursor_position()
-    {
-        let (canvas_tf, _canvas_sprite) = canvas_query.single().unwrap();
-
-        // cursor_pos is top-left origin
-        let win_w = window.width() as f32;
-        let win_h = window.height() as f32;
-
-        // 1) window (top-left origin) -> world (center origin)
-        let world_x = cursor_pos.x - win_w * 0.5;
-        let world_y = cursor_pos.y - win_h * 0.5;
-
-
-        // 2) world -> canvas-local (canvas_transform.translation is the canvas center)
-        let local_x = world_x - canvas_tf.translation.x;
-        let local_y = world_y - canvas_tf.translation.y;
-
-
-        // 3) undo canvas scale (assumes uniform scale)
-        let scale = canvas_tf.scale.x.max(1e-6);
-        let sprite_local_x = local_x / scale;
-        let sprite_local_y = local_y / scale;
-
-        // 4) sprite-local -> canvas pixel coords (top-left origin)
-        let canvas_x = sprite_local_x + (RES_WIDTH as f32) * 0.5;
-        let canvas_y = sprite_local_y + (RES_HEIGHT as f32) * 0.5;
-
-        draw_point(&mut gizmos, Vec3::new(canvas_x, canvas_y*-1., 0.));
-        gizmos.rect_2d(    
-            Isometry2d::new(Vec2::new((RES_WIDTH as f32) * 0.5, - (RES_HEIGHT as f32) * 0.5), Rot2::radians(0.)), 
-            Vec2::new(38.*8., 21.*8.), 
-            WHITE 
-	    );
-        // 5) tile indices
-        let tile_x = (canvas_x / TILE_SIZE as f32).floor() as i32;
-        let tile_y = ((canvas_y * TILE_SIZE as f32) / TILE_SIZE as f32).floor() as i32;
-
-
-        // --- Step 5: Clamp and update ---
-        //if tile_x >= 0 && tile_x < COLS as i32 && tile_y >= 0 && tile_y < ROWS as i32 {
-        if tilemap.collide_at(Vec2::new(canvas_x, canvas_y*-1.0)) {
-            draw_point_red(&mut gizmos, Vec3::new(canvas_x, canvas_y*-1., 0.));
-            if let Some(ent) = tilemap.get_entity_at(tile_x as usize, tile_y as usize) {
-                if let Ok(mut sprite) = sprites.get_mut(ent) {
-                    if let Some(at) = &mut sprite.texture_atlas {
-                        // Toggle tile index
-                        at.index = 1;
-                    }
+    if let Some(point) = cursor.0 {
+        draw_point(&mut gizmos, point.world.extend(0.));
+        // Outline the view inside its border tiles; headless runs have no canvas.
+        if let Some(canvas) = canvas {
+            gizmos.rect_2d(
+                Isometry2d::from_translation(view.0),
+                canvas.size_f32() - Vec2::splat(2. * TILE_SIZE as f32),
+                WHITE,
+            );
+        }
+        let (tile_x, tile_y) = (point.tile.x, point.tile.y);
+
+        if tilemap.collide_at(point.world) {
+            draw_point_red(&mut gizmos, point.world.extend(0.));
+            if let Some(ent) = tilemap.get_entity_at(tile_x as usize, tile_y as usize)
+                && let Ok(mut sprite) = sprites.get_mut(ent)
+                && let Some(at) = &mut sprite.texture_atlas
+            {
+                // Toggle tile index
+                at.index = 1;
+            }
+        }
+
+        // --- Hammer: right click knocks down the wall under the cursor, shift + right click builds one ---
+        if mouse.just_pressed(MouseButton::Right) {
+            let g = tilemap.origin() + tilemap.cell_at(point.world);
+            if tilemap.to_local(g).is_some() {
+                let wall = tilemap.tile_global(&worldgen, TileLayer::Background, g);
+                let building = keyboard_input.pressed(settings.keys.build);
+                if building && wall.is_none() {
+                    // Walls are built out of the selected block, one item each.
+                    let Ok(mut inventory) = inventories.single_mut() else { return };
+                    let Some(item) = inventory.selected_item() else { return };
+                    let Some(kind) = item.kind.to_tile() else { return };
+                    inventory.remove_one(item.kind);
+                    tilemap.set_global(TileLayer::Background, g, Some(Tile { tile_index: worldgen.atlas_index(g), kind }));
+                } else if let (false, Some(wall)) = (building, wall) {
+                    tilemap.set_global(TileLayer::Background, g, None);
+                    drop_item(&mut commands, g, ItemKind::from_tile(wall.kind), 1);
                 }
             }
         }
@@ -182,55 +489,79 @@ fn update_tiles(
 pub fn spawn_tiles(
     mut commands: Commands,
     mut tilemap: ResMut<TileMap>,
-    asset_server: Res<AssetServer>,
 ) {
-	let texture = asset_server.load("block.png");
-
-    let text_font = TextFont {
-        font: asset_server.load("MedodicaRegular.otf"),
-        font_size: 10.0,
-        ..default()
-    };
+    respawn_tile_sprites(&mut commands, &mut tilemap);
+}
 
-    for y in 0..TROWS {
-        for x in 0..TCOLS {
-            //println!("tilemap[{}][{}] = {:?}", x, y, tilemap.tiles[y][x]);
-            if let Some(tile) = tilemap.getTile(x, y) {
-                let world_pos = Vec3::new(
-                    x as f32 * TILE_SIZE as f32,
-                    //(y as f32-(ROWS as f32)+1.) * TILE_SIZE as f32,
-                    (y as f32 * TILE_SIZE as f32) * -1.0,
-                    0.0,
-                );
-
-				let mut sprite = Sprite {
-					image: texture.clone(),
-					texture_atlas: Some(TextureAtlas {
-						layout: tilemap.layout.clone(),
-						index: tile.tile_index,
-					}),
-					..Default::default()
-				};
-
-				sprite.anchor = Anchor::TopLeft;
-
-                // print the worldpos
-				let e = commands.spawn((
-					sprite,
-					Transform::from_xyz(world_pos.x, world_pos.y, world_pos.z),
-					PIXEL_PERFECT_LAYERS,
-				)).id();
-
-				tilemap.entities[y][x] = Some(e);
-
-                /*commands.spawn((
-                    Text2d::new(format!("({}, {})", x, y)),
-                    text_font.clone(),
-					Transform::from_xyz(world_pos.x, world_pos.y, world_pos.z),
-                    PIXEL_PERFECT_LAYERS,
-                ));*/
+/// Despawns every tile sprite and spawns new ones for the loaded grid.
+pub fn respawn_tile_sprites(commands: &mut Commands, tilemap: &mut TileMap) {
+    for layer in [TileLayer::Foreground, TileLayer::Background] {
+        for y in 0..TROWS {
+            for x in 0..TCOLS {
+                respawn_tile_sprite(commands, tilemap, layer, x, y);
             }
         }
     }
 }
 
+fn respawn_tile_sprite(commands: &mut Commands, tilemap: &mut TileMap, layer: TileLayer, x: usize, y: usize) {
+    if let Some(ent) = tilemap.entities_mut(layer)[y][x].take() {
+        commands.entity(ent).despawn();
+    }
+    //println!("tilemap[{}][{}] = {:?}", x, y, tilemap.tiles[y][x]);
+    if let Some(tile) = tilemap.grid(layer)[y][x] {
+        let e = spawn_tile_sprite(commands, tilemap, layer, x, y, tile);
+        tilemap.entities_mut(layer)[y][x] = Some(e);
+    }
+}
+
+pub fn spawn_tile_sprite(commands: &mut Commands, tilemap: &TileMap, layer: TileLayer, x: usize, y: usize, tile: Tile) -> Entity {
+    let (z, color) = match layer {
+        TileLayer::Foreground => (0.0, tile.kind.def().tint),
+        TileLayer::Background => (WALL_Z, (tile.kind.def().tint.to_linear() * WALL_SHADE).with_alpha(1.0).into()),
+    };
+    let world_pos = Vec3::new(
+        x as f32 * TILE_SIZE as f32,
+        -(y as f32 * TILE_SIZE as f32),
+        z,
+    );
+
+    let mut sprite = Sprite {
+        image: tilemap.texture.clone(),
+        texture_atlas: Some(TextureAtlas {
+            layout: tilemap.layout.clone(),
+            index: tile.tile_index,
+        }),
+        color,
+        ..Default::default()
+    };
+
+    sprite.anchor = Anchor::TopLeft;
+
+    commands.spawn((
+        sprite,
+        Transform::from_xyz(world_pos.x, world_pos.y, world_pos.z),
+        PIXEL_PERFECT_LAYERS,
+        StateScoped(GameState::InGame),
+    )).id()
+}
+
+/// Respawns the sprites of cells changed through [`TileMap::set_global`].
+fn sync_tile_sprites(mut commands: Commands, mut tilemap: ResMut<TileMap>) {
+    if tilemap.dirty.is_empty() {
+        return;
+    }
+    let dirty = std::mem::take(&mut tilemap.dirty);
+    for (layer, x, y) in dirty {
+        respawn_tile_sprite(&mut commands, &mut tilemap, layer, x, y);
+    }
+}
+
+fn publish_tile_changes(mut tilemap: ResMut<TileMap>, mut events: EventWriter<TileChanged>) {
+    if tilemap.changed.is_empty() {
+        return;
+    }
+    for change in std::mem::take(&mut tilemap.changed) {
+        events.write(change);
+    }
+}
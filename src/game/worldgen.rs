@@ -0,0 +1,163 @@
+use bevy::prelude::*;
+use noise::{NoiseFn, Perlin};
+use serde::{Deserialize, Serialize};
+
+use crate::game::{liquid::LiquidKind, tilemap::{Tile, TileKind, TileLayer, CHUNK_ROWS}};
+
+/// First global row with background walls: everything below the surface screens.
+pub const UNDERGROUND_ROW: i32 = CHUNK_ROWS - 1;
+/// Global row below which generated liquid is lava instead of water.
+pub const LAVA_DEPTH: i32 = 66;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum Biome {
+    Forest,
+    Desert,
+}
+
+impl Biome {
+    pub const ALL: [Biome; 2] = [Biome::Forest, Biome::Desert];
+
+    /// Name used for the biome's data files.
+    pub fn name(&self) -> &'static str {
+        match self {
+            Biome::Forest => "forest",
+            Biome::Desert => "desert",
+        }
+    }
+}
+
+/// Knobs of the generator. The defaults are the game's; `terra-worldgen`
+/// reads others from a RON file to preview changes before making them.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct WorldGenConfig {
+    /// Noise value above which a cell is solid.
+    pub solid_threshold: f64,
+    /// Scale of the terrain noise: smaller values give bigger caves.
+    pub terrain_scale: f64,
+    /// Chance that an empty cell resting on a solid one gets a torch.
+    pub torch_chance: f64,
+    /// Noise value above which an empty cell starts filled with liquid.
+    pub liquid_threshold: f64,
+    /// Noise value above which solid ground becomes sand or gravel.
+    pub falling_patch_threshold: f64,
+}
+
+impl Default for WorldGenConfig {
+    fn default() -> Self {
+        Self {
+            solid_threshold: 0.2,
+            terrain_scale: 0.1,
+            torch_chance: 0.01,
+            liquid_threshold: 0.45,
+            falling_patch_threshold: 0.35,
+        }
+    }
+}
+
+/// Deterministic terrain generator: the same seed always gives the same world.
+#[derive(Resource, Clone)]
+pub struct WorldGen {
+    pub seed: u32,
+    pub config: WorldGenConfig,
+    perlin: Perlin,
+}
+
+impl WorldGen {
+    pub fn new(seed: u32) -> Self {
+        Self::with_config(seed, WorldGenConfig::default())
+    }
+
+    pub fn with_config(seed: u32, config: WorldGenConfig) -> Self {
+        Self { seed, config, perlin: Perlin::new(seed) }
+    }
+
+    /// Biome of the screen column `screen_x`; a biome spans the whole column.
+    pub fn biome_at(&self, screen_x: i32) -> Biome {
+        let val = self.perlin.get([screen_x as f64 * 0.3, 0.5, 20.0]);
+        if val > 0.2 { Biome::Desert } else { Biome::Forest }
+    }
+
+    /// Generated tile of `layer` at global tile coordinates.
+    pub fn generate(&self, layer: TileLayer, g: IVec2) -> Option<Tile> {
+        match layer {
+            TileLayer::Foreground => self.tile_at(g),
+            TileLayer::Background => self.wall_at(g),
+        }
+    }
+
+    /// Background wall behind underground cells, so caves have a back wall.
+    pub fn wall_at(&self, g: IVec2) -> Option<Tile> {
+        if g.y < UNDERGROUND_ROW {
+            return None;
+        }
+        let kind = if self.chance(g, 3) < 0.5 + g.y as f64 / 220.0 { TileKind::Stone } else { TileKind::Dirt };
+        Some(Tile { tile_index: self.atlas_index(g), kind })
+    }
+
+    /// Generated foreground tile at global tile coordinates, before any player edits.
+    pub fn tile_at(&self, g: IVec2) -> Option<Tile> {
+        if self.solid_at(g) {
+            let kind = self.solid_kind(g);
+            return Some(Tile { tile_index: self.atlas_index(g), kind });
+        }
+        if self.solid_at(g + IVec2::Y) && self.chance(g, 1) < self.config.torch_chance {
+            return Some(Tile { tile_index: self.atlas_index(g), kind: TileKind::Torch });
+        }
+        None
+    }
+
+    /// Liquid that an empty generated cell starts with.
+    pub fn liquid_at(&self, g: IVec2) -> Option<LiquidKind> {
+        if self.tile_at(g).is_some() {
+            return None;
+        }
+        let val = self.perlin.get([g.x as f64 * 0.05, g.y as f64 * 0.05, 5.0]);
+        if val <= self.config.liquid_threshold {
+            return None;
+        }
+        Some(if g.y > LAVA_DEPTH { LiquidKind::Lava } else { LiquidKind::Water })
+    }
+
+    fn solid_at(&self, g: IVec2) -> bool {
+        let scale = self.config.terrain_scale;
+        let val = self.perlin.get([g.x as f64 * scale, g.y as f64 * scale, 0.1]);
+        val > self.config.solid_threshold
+    }
+
+    /// Deeper tiles are mostly stone, with patches of sand near the surface and
+    /// gravel further down. Falling tiles only generate on top of solid ground.
+    fn solid_kind(&self, g: IVec2) -> TileKind {
+        let stone_chance = (0.3 + g.y as f64 / 22.0 * 0.2).clamp(0.0, 0.9);
+        let base = if self.chance(g, 0) < stone_chance { TileKind::Stone } else { TileKind::Dirt };
+
+        let patch = self.perlin.get([g.x as f64 * 0.08, g.y as f64 * 0.08, 9.0]);
+        if patch <= self.config.falling_patch_threshold || !self.solid_at(g + IVec2::Y) {
+            return base;
+        }
+        if g.y > LAVA_DEPTH / 2 { TileKind::Gravel } else { TileKind::Sand }
+    }
+
+    /// Atlas variant of the tile in global cell `g`.
+    pub fn atlas_index(&self, g: IVec2) -> usize {
+        (self.hash(g, 2) % 12) as usize
+    }
+
+    /// Uniform value in `0..1` for a cell; `salt` separates independent rolls.
+    fn chance(&self, g: IVec2, salt: u32) -> f64 {
+        self.hash(g, salt) as f64 / u32::MAX as f64
+    }
+
+    fn hash(&self, g: IVec2, salt: u32) -> u32 {
+        let mut h = self.seed ^ salt.wrapping_mul(0x9E37_79B9);
+        h ^= (g.x as u32).wrapping_mul(0x85EB_CA6B);
+        h = h.rotate_left(13).wrapping_mul(5).wrapping_add(0xE654_6B64);
+        h ^= (g.y as u32).wrapping_mul(0xC2B2_AE35);
+        h ^= h >> 16;
+        h = h.wrapping_mul(0x7FEB_352D);
+        h ^= h >> 15;
+        h = h.wrapping_mul(0x846C_A68B);
+        h ^ (h >> 16)
+    }
+}
@@ -0,0 +1,4 @@
+//! The game as a library, so the tools in `src/bin` and the integration tests
+//! can share its code.
+
+pub mod game;
@@ -1,11 +1,46 @@
-use bevy::{
-    prelude::*,
-};
+use std::path::Path;
 
-use crate::game::GamePlugin;
+use bevy::prelude::*;
 
-mod game;
+use terra::game::{
+    self, GamePlugin,
+    console::ConsoleScript,
+    replay::{Playback, Recorder, read_replay},
+};
 
 fn main() {
-    App::new().add_plugins(GamePlugin).run();
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    // Tools that run without opening a window.
+    if args.first().is_some_and(|command| command == "export") {
+        if let Err(err) = game::export::run_export_command(&args[1..]) {
+            eprintln!("{err}");
+            std::process::exit(1);
+        }
+        return;
+    }
+    let mut app = App::new();
+    app.add_plugins(GamePlugin);
+    match args.iter().map(String::as_str).collect::<Vec<_>>()[..] {
+        [] => {}
+        ["--record", path] => {
+            app.insert_resource(Recorder::to_file(path));
+        }
+        ["--script", path] => {
+            app.insert_resource(ConsoleScript(path.into()));
+        }
+        ["--replay", path] => match read_replay(Path::new(path)) {
+            Ok(replay) => {
+                app.insert_resource(Playback::new(replay));
+            }
+            Err(err) => {
+                eprintln!("{err}");
+                std::process::exit(1);
+            }
+        },
+        _ => {
+            eprintln!("usage: terra [--record <file> | --replay <file> | --script <file>]");
+            std::process::exit(1);
+        }
+    }
+    app.run();
 }
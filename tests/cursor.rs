@@ -0,0 +1,102 @@
+//! Cursor picking through the canvas scaling, for window sizes that do not
+//! fit the canvas exactly.
+
+use bevy::prelude::*;
+use terra::game::{
+    cursor::{pick, window_to_canvas},
+    settings::{Scaling, Settings},
+    Canvas,
+};
+
+const CANVAS: Vec2 = Vec2::new(320.0, 180.0);
+
+/// Transform `fit_canvas` gives the canvas sprite in a `window` sized window.
+fn canvas_transform(scaling: Scaling, window: Vec2) -> Transform {
+    let settings = Settings { scaling, ..default() };
+    Transform::from_scale(settings.canvas_scale_for(window, CANVAS).extend(1.0))
+}
+
+fn canvas(subpixel: Vec2) -> Canvas {
+    Canvas {
+        image: Handle::default(),
+        size: CANVAS.as_uvec2(),
+        prescaled: Handle::default(),
+        prescale: 1,
+        subpixel,
+    }
+}
+
+fn assert_near(actual: Vec2, expected: Vec2) {
+    assert!(actual.abs_diff_eq(expected, 1e-3), "expected {expected}, got {actual}");
+}
+
+#[test]
+fn window_centre_is_canvas_centre() {
+    for window in [Vec2::new(1280.0, 720.0), Vec2::new(1000.0, 600.0), Vec2::new(1001.0, 563.0)] {
+        for scaling in Scaling::ALL {
+            let tf = canvas_transform(scaling, window);
+            let point = window_to_canvas(window * 0.5, window, &tf, CANVAS);
+            assert_near(point.expect("the centre is on the canvas"), CANVAS * 0.5);
+        }
+    }
+}
+
+#[test]
+fn letterbox_bars_are_off_the_canvas() {
+    // Integer scaling puts a 3x canvas, 960 by 540, in the middle.
+    let window = Vec2::new(1000.0, 600.0);
+    let tf = canvas_transform(Scaling::Integer, window);
+    assert_eq!(tf.scale.truncate(), Vec2::splat(3.0));
+
+    assert_eq!(window_to_canvas(Vec2::new(10.0, 300.0), window, &tf, CANVAS), None);
+    assert_eq!(window_to_canvas(Vec2::new(990.0, 300.0), window, &tf, CANVAS), None);
+    assert_eq!(window_to_canvas(Vec2::new(500.0, 20.0), window, &tf, CANVAS), None);
+    assert_eq!(window_to_canvas(Vec2::new(500.0, 580.0), window, &tf, CANVAS), None);
+
+    assert_near(window_to_canvas(Vec2::new(20.0, 30.0), window, &tf, CANVAS).unwrap(), Vec2::ZERO);
+    assert_near(window_to_canvas(Vec2::new(23.0, 33.0), window, &tf, CANVAS).unwrap(), Vec2::ONE);
+    assert_near(window_to_canvas(Vec2::new(979.0, 569.0), window, &tf, CANVAS).unwrap(), CANVAS - Vec2::splat(1.0 / 3.0));
+}
+
+#[test]
+fn odd_window_corners_map_to_canvas_corners() {
+    let window = Vec2::new(1001.0, 563.0);
+    for scaling in [Scaling::Fit, Scaling::Stretch, Scaling::SharpBilinear] {
+        let tf = canvas_transform(scaling, window);
+        let scale = tf.scale.truncate();
+        let top_left = (window - CANVAS * scale) * 0.5;
+        // Half a canvas pixel in from each corner.
+        let first = window_to_canvas(top_left + scale * 0.5, window, &tf, CANVAS);
+        assert_near(first.expect("the first pixel is on the canvas"), Vec2::splat(0.5));
+        let last = window_to_canvas(top_left + (CANVAS - 0.5) * scale, window, &tf, CANVAS);
+        assert_near(last.expect("the last pixel is on the canvas"), CANVAS - 0.5);
+    }
+}
+
+#[test]
+fn stretch_scales_each_axis() {
+    let window = Vec2::new(1001.0, 563.0);
+    let tf = canvas_transform(Scaling::Stretch, window);
+    assert_near(tf.scale.truncate(), window / CANVAS);
+    // No bars: the window corners are the canvas corners.
+    assert_near(window_to_canvas(Vec2::ZERO, window, &tf, CANVAS).unwrap(), Vec2::ZERO);
+    assert_eq!(window_to_canvas(window, window, &tf, CANVAS), None);
+}
+
+#[test]
+fn picked_world_point_maps_back_to_the_canvas() {
+    let window = Vec2::new(1001.0, 563.0);
+    let tf = canvas_transform(Scaling::Fit, window);
+    let moved = canvas(Vec2::new(0.25, 0.5));
+    let camera = Transform::from_xyz(160.5, -90.5, 0.0);
+    for cursor in [Vec2::new(100.0, 100.0), window * 0.5, Vec2::new(900.0, 500.0)] {
+        let point = pick(cursor, window, &tf, &moved, &camera).expect("on the canvas");
+        assert_near(moved.to_canvas(point.world, &camera), point.canvas);
+    }
+    // The subpixel shift moves the world point, not the canvas pixel.
+    let still = canvas(Vec2::ZERO);
+    let shifted = pick(window * 0.5, window, &tf, &moved, &camera).unwrap();
+    let unshifted = pick(window * 0.5, window, &tf, &still, &camera).unwrap();
+    assert_near(shifted.canvas, unshifted.canvas);
+    assert_near(shifted.world - unshifted.world, Vec2::new(0.25, -0.5));
+}
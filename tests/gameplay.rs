@@ -0,0 +1,152 @@
+//! The player on hand-built layouts, stepped without a window.
+
+use bevy::prelude::*;
+use terra::game::{
+    headless::Simulation,
+    settings::Action,
+    tilemap::{TileKind, CHUNK_COLS, TILE_SIZE},
+};
+
+const SEED: u32 = 1234;
+/// Global row of the floor; the player spawns above it on screen `(0, 0)`.
+const FLOOR_ROW: i32 = 19;
+/// Where the player stands on the floor: its top is one tile above the
+/// floor's, which is at `-(FLOOR_ROW + 1) * TILE_SIZE` on the first screen.
+const STANDING_Y: f32 = -(FLOOR_ROW * TILE_SIZE as i32) as f32;
+
+/// A canvas smaller than the screen, so the view has room to follow the player.
+const SMALL_CANVAS: UVec2 = UVec2::new(192, 108);
+
+/// A world whose first `screens` screens to the right are empty down to a
+/// flat floor.
+fn flat_world(screens: i32) -> Simulation {
+    flatten(Simulation::new(SEED), screens)
+}
+
+/// Empties the first `screens` screens of `sim` to the right down to a flat
+/// floor.
+fn flatten(mut sim: Simulation, screens: i32) -> Simulation {
+    let right = screens * CHUNK_COLS;
+    sim.fill(IVec2::new(-1, -1), IVec2::new(right, FLOOR_ROW - 1), None);
+    sim.fill(IVec2::new(-1, FLOOR_ROW), IVec2::new(right, FLOOR_ROW), Some(TileKind::Stone));
+    sim
+}
+
+fn settled(sim: &mut Simulation) -> bool {
+    let (player, _) = sim.player();
+    player.on_ground && player.velocity.y == 0.0
+}
+
+#[test]
+fn player_lands_on_the_ground() {
+    let mut sim = flat_world(1);
+    assert!(sim.run_until(180, settled), "the player never landed");
+    let (_, position) = sim.player();
+    assert_eq!(position.y, STANDING_Y);
+    // And stays there.
+    sim.run(60);
+    assert_eq!(sim.player().1.y, STANDING_Y);
+    assert_eq!(sim.screen(), IVec2::ZERO);
+}
+
+#[test]
+fn player_cannot_walk_through_a_wall() {
+    let mut sim = flat_world(1);
+    let wall_col = 29;
+    sim.fill(IVec2::new(wall_col, -1), IVec2::new(wall_col, FLOOR_ROW - 1), Some(TileKind::Stone));
+    assert!(sim.run_until(180, settled));
+
+    sim.press(Action::Right);
+    sim.run(180);
+    let (_, position) = sim.player();
+    // Column 29 is drawn from x = 30 tiles on the first screen.
+    let wall_x = ((wall_col + 1) * TILE_SIZE as i32) as f32;
+    assert!(position.x + 8.0 <= wall_x, "player at {position} is inside the wall at x {wall_x}");
+    assert!(position.x + 8.0 >= wall_x - 2.0, "player at {position} stopped short of the wall");
+    assert_eq!(sim.screen(), IVec2::ZERO);
+}
+
+#[test]
+fn player_cannot_jump_through_a_ceiling() {
+    let mut sim = flat_world(1);
+    assert!(sim.run_until(180, settled));
+    // Low enough to bump into, above where the player spawned.
+    let ceiling_row = 13;
+    sim.fill(IVec2::new(-1, ceiling_row), IVec2::new(CHUNK_COLS, ceiling_row), Some(TileKind::Stone));
+
+    sim.press(Action::Jump);
+    let ceiling_bottom = -((ceiling_row + 2) * TILE_SIZE as i32) as f32;
+    let mut highest = f32::MIN;
+    for _ in 0..60 {
+        sim.step();
+        highest = highest.max(sim.player().1.y);
+    }
+    assert!(highest > STANDING_Y, "the player did not jump");
+    assert!(highest < ceiling_bottom, "the player reached {highest}, into the ceiling at {ceiling_bottom}");
+    sim.release(Action::Jump);
+    assert!(sim.run_until(120, settled), "the player did not land again");
+    assert_eq!(sim.player().1.y, STANDING_Y);
+}
+
+#[test]
+fn walking_right_moves_to_the_next_screen() {
+    let mut sim = flat_world(2);
+    assert!(sim.run_until(180, settled));
+
+    let chunk_width = (CHUNK_COLS * TILE_SIZE as i32) as f32;
+    let global_x = |sim: &mut Simulation| sim.screen().x as f32 * chunk_width + sim.player().1.x;
+    sim.press(Action::Right);
+    let mut last = global_x(&mut sim);
+    let moved = sim.run_until(600, |sim| {
+        let x = global_x(sim);
+        // One frame at walking speed is at most two pixels, across screens too.
+        assert!((0.0..=2.0).contains(&(x - last)), "the player jumped from {last} to {x}");
+        last = x;
+        sim.screen() == IVec2::new(1, 0)
+    });
+    assert!(moved, "the player never reached the next screen");
+
+    // Still standing on the same floor, which the new screen loaded.
+    sim.run(30);
+    let (player, position) = sim.player();
+    assert!(player.on_ground);
+    assert_eq!(position.y, STANDING_Y);
+    assert_eq!(sim.screen(), IVec2::new(1, 0));
+}
+
+#[test]
+fn view_follows_the_player_and_the_canvas_follows_the_view() {
+    let mut sim = flatten(Simulation::viewing(SEED, SMALL_CANVAS), 2);
+    assert!(sim.run_until(180, settled));
+    sim.run(120);
+    let half = SMALL_CANVAS.as_vec2() * 0.5;
+    let check_canvas = |sim: &mut Simulation| {
+        let expected = sim.view() + Vec2::new(-half.x, half.y);
+        let shown = sim.shown_corner();
+        assert!(shown.abs_diff_eq(expected, 1e-3), "the canvas shows from {shown}, the view from {expected}");
+    };
+
+    // Centred on the player, except that it stops at the bottom of the screen.
+    let centre = sim.player().1 + Vec2::new(4.0, -4.0);
+    // The screen is 180 pixels tall.
+    let bottom = -180.0 + half.y;
+    assert_eq!(sim.view(), Vec2::new(centre.x, bottom.max(centre.y)));
+    check_canvas(&mut sim);
+
+    // It lags behind a moving player, between pixels.
+    sim.press(Action::Left);
+    for _ in 0..30 {
+        sim.step();
+        check_canvas(&mut sim);
+    }
+    let centre = sim.player().1 + Vec2::new(4.0, -4.0);
+    assert!(sim.view().x > centre.x, "the view at {} is not behind the player at {}", sim.view(), centre);
+    assert!(sim.view().x.fract() != 0.0);
+    sim.release(Action::Left);
+
+    // And cuts to the next screen with the player.
+    sim.press(Action::Right);
+    assert!(sim.run_until(600, |sim| sim.screen() == IVec2::new(1, 0)));
+    assert_eq!(sim.view().x, half.x);
+    check_canvas(&mut sim);
+}
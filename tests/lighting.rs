@@ -0,0 +1,110 @@
+//! Light levels over hand-built screens, and relighting after edits.
+
+use rand::{rngs::StdRng, Rng, SeedableRng};
+use terra::game::{
+    lighting::{compute_light, relight, LightGrid, MAX_LIGHT},
+    tilemap::{Tile, TileGrid, TileKind, TCOLS, TROWS},
+};
+
+const NO_LIGHT: LightGrid = [[0; TCOLS]; TROWS];
+
+fn empty() -> TileGrid {
+    [[None; TCOLS]; TROWS]
+}
+
+fn tile(kind: TileKind) -> Option<Tile> {
+    Some(Tile { tile_index: 0, kind })
+}
+
+#[test]
+fn torch_light_falls_off_with_distance() {
+    let mut tiles = empty();
+    tiles[10][10] = tile(TileKind::Torch);
+    let levels = compute_light(&tiles, &empty(), &NO_LIGHT, 0);
+    let emission = TileKind::Torch.def().emission;
+    assert_eq!(levels[10][10], emission);
+    assert_eq!(levels[10][11], emission - 1);
+    assert_eq!(levels[10][13], emission - 3);
+    assert_eq!(levels[7][10], emission - 3);
+    // Light goes round corners, one step at a time.
+    assert_eq!(levels[12][12], emission - 4);
+    assert_eq!(levels[10][10 + emission as usize], 0);
+}
+
+#[test]
+fn solid_tiles_dim_light_by_their_opacity() {
+    let mut tiles = empty();
+    tiles[10][10] = tile(TileKind::Torch);
+    for row in tiles.iter_mut() {
+        row[12] = tile(TileKind::Stone);
+    }
+    let levels = compute_light(&tiles, &empty(), &NO_LIGHT, 0);
+    let emission = TileKind::Torch.def().emission;
+    let opacity = TileKind::Stone.def().opacity;
+    assert_eq!(levels[10][12], emission - 1 - opacity);
+    assert_eq!(levels[10][13], emission - 2 - opacity);
+    // The torch's side of the wall is lit as if it were not there.
+    assert_eq!(levels[10][8], emission - 2);
+}
+
+#[test]
+fn sky_light_stops_at_opaque_tiles_and_walls() {
+    let mut tiles = empty();
+    for cell in tiles[5].iter_mut() {
+        *cell = tile(TileKind::Stone);
+    }
+    let mut walls = empty();
+    walls[2][3] = tile(TileKind::Dirt);
+    let levels = compute_light(&tiles, &walls, &NO_LIGHT, MAX_LIGHT);
+
+    assert_eq!(levels[0][0], MAX_LIGHT);
+    assert_eq!(levels[4][0], MAX_LIGHT);
+    let opacity = TileKind::Stone.def().opacity;
+    assert_eq!(levels[5][0], MAX_LIGHT - opacity);
+    assert_eq!(levels[6][0], MAX_LIGHT - opacity - 1);
+    // Under the wall only light from the lit columns next to it gets in.
+    assert_eq!(levels[2][3], MAX_LIGHT - 1);
+    assert_eq!(levels[3][3], MAX_LIGHT - 1);
+}
+
+#[test]
+fn light_from_emitters_spreads_like_tile_light() {
+    let mut emitters = NO_LIGHT;
+    emitters[8][8] = 9;
+    let levels = compute_light(&empty(), &empty(), &emitters, 0);
+    assert_eq!(levels[8][8], 9);
+    assert_eq!(levels[8][5], 6);
+}
+
+/// Edits cells one at a time, checking relighting each against a full
+/// recompute.
+fn check_relight(sky: u8, seed: u64) {
+    let mut rng = StdRng::seed_from_u64(seed);
+    let mut tiles = empty();
+    let mut walls = empty();
+    let mut emitters = NO_LIGHT;
+    let mut levels = compute_light(&tiles, &walls, &emitters, sky);
+
+    for _ in 0..400 {
+        let (x, y) = (rng.gen_range(0..TCOLS), rng.gen_range(0..TROWS));
+        match rng.gen_range(0..6) {
+            0 | 1 => tiles[y][x] = tile(TileKind::Stone),
+            2 => tiles[y][x] = tile(TileKind::Torch),
+            3 => tiles[y][x] = None,
+            4 => walls[y][x] = if walls[y][x].is_some() { None } else { tile(TileKind::Dirt) },
+            _ => emitters[y][x] = rng.gen_range(0..=MAX_LIGHT),
+        }
+        relight(&mut levels, &tiles, &walls, &emitters, sky, &[(x, y)]);
+        assert_eq!(levels, compute_light(&tiles, &walls, &emitters, sky), "after editing ({x}, {y})");
+    }
+}
+
+#[test]
+fn relighting_matches_a_full_recompute_underground() {
+    check_relight(0, 1);
+}
+
+#[test]
+fn relighting_matches_a_full_recompute_under_the_sky() {
+    check_relight(MAX_LIGHT, 2);
+}
@@ -0,0 +1,109 @@
+//! Routes over hand-built layouts, with a set of solid cells standing in for
+//! the world.
+
+use std::collections::HashSet;
+
+use bevy::prelude::*;
+use terra::game::{
+    pathfinding::{AgentParams, NavAction, NavCache, NavStep},
+    tilemap::CHUNK_ROWS,
+};
+
+const AGENT: AgentParams = AgentParams { height: 1, jump_height: 3, jump_distance: 3, max_fall: 60 };
+
+/// Solid cells, filled in by rows and blocks.
+#[derive(Default)]
+struct Layout(HashSet<IVec2>);
+
+impl Layout {
+    /// Fills the inclusive rectangle from `from` to `to`.
+    fn block(mut self, from: IVec2, to: IVec2) -> Self {
+        for y in from.y..=to.y {
+            for x in from.x..=to.x {
+                self.0.insert(IVec2::new(x, y));
+            }
+        }
+        self
+    }
+
+    fn find_path(&self, cache: &mut NavCache, start: IVec2, goal: IVec2) -> Option<Vec<NavStep>> {
+        cache.find_path(&|g| self.0.contains(&g), &AGENT, start, goal)
+    }
+}
+
+/// A floor along row 10 from column 0 to 20.
+fn floor() -> Layout {
+    Layout::default().block(IVec2::new(0, 10), IVec2::new(20, 10))
+}
+
+#[test]
+fn walks_along_a_floor() {
+    let path = floor().find_path(&mut NavCache::default(), IVec2::new(2, 9), IVec2::new(6, 9)).unwrap();
+    assert_eq!(path.len(), 4);
+    assert!(path.iter().all(|step| step.action == NavAction::Walk));
+    assert_eq!(path.last().unwrap().to, IVec2::new(6, 9));
+}
+
+#[test]
+fn already_there_is_an_empty_route() {
+    let path = floor().find_path(&mut NavCache::default(), IVec2::new(2, 9), IVec2::new(2, 9));
+    assert_eq!(path, Some(Vec::new()));
+}
+
+#[test]
+fn jumps_onto_a_ledge_at_full_jump_height() {
+    let top = 9 - AGENT.jump_height;
+    let layout = floor().block(IVec2::new(5, top + 1), IVec2::new(8, 9));
+    let path = layout.find_path(&mut NavCache::default(), IVec2::new(2, 9), IVec2::new(6, top)).unwrap();
+    assert!(path.iter().any(|step| step.action == NavAction::Jump));
+    assert_eq!(path.last().unwrap().to, IVec2::new(6, top));
+}
+
+#[test]
+fn cannot_jump_higher_than_it_can() {
+    let top = 9 - AGENT.jump_height - 1;
+    let layout = floor().block(IVec2::new(5, top + 1), IVec2::new(8, 9));
+    assert_eq!(layout.find_path(&mut NavCache::default(), IVec2::new(2, 9), IVec2::new(6, top)), None);
+}
+
+#[test]
+fn jumps_over_a_gap() {
+    let layout = Layout::default().block(IVec2::new(0, 10), IVec2::new(4, 10)).block(IVec2::new(7, 10), IVec2::new(12, 10));
+    let path = layout.find_path(&mut NavCache::default(), IVec2::new(2, 9), IVec2::new(10, 9)).unwrap();
+    assert!(path.iter().any(|step| step.action == NavAction::Jump && step.to.x >= 7));
+}
+
+#[test]
+fn does_not_walk_through_walls() {
+    let layout = floor().block(IVec2::new(5, 0), IVec2::new(5, 9));
+    assert_eq!(layout.find_path(&mut NavCache::default(), IVec2::new(2, 9), IVec2::new(8, 9)), None);
+}
+
+#[test]
+fn jump_reach_follows_from_physics() {
+    // 220 px/s against 1000 px/s² rises 24.2 px and stays up 0.44 s.
+    let params = AgentParams::from_physics(2, 60.0, 220.0, -1000.0, 5);
+    assert_eq!(params, AgentParams { height: 2, jump_height: 3, jump_distance: 3, max_fall: 5 });
+}
+
+/// A fall longer than a chunk is tall must be dropped from the cache when
+/// something is built in its way, however far down.
+#[test]
+fn edits_far_down_a_fall_invalidate_it() {
+    let bottom = 2 * CHUNK_ROWS + 5;
+    let mut layout = Layout::default()
+        .block(IVec2::new(0, 1), IVec2::new(1, 1))
+        .block(IVec2::new(2, bottom), IVec2::new(10, bottom));
+    let (start, goal) = (IVec2::new(1, 0), IVec2::new(6, bottom - 1));
+    let mut cache = NavCache::default();
+
+    let path = layout.find_path(&mut cache, start, goal).unwrap();
+    assert_eq!(path[0], NavStep { action: NavAction::Fall, to: IVec2::new(2, bottom - 1) });
+
+    let block = IVec2::new(2, 2 * CHUNK_ROWS);
+    layout = layout.block(block, block);
+    cache.invalidate(block);
+    let path = layout.find_path(&mut cache, start, goal).unwrap();
+    assert_eq!(path[0], NavStep { action: NavAction::Fall, to: block - IVec2::Y });
+    assert_eq!(path.last().unwrap().to, goal);
+}
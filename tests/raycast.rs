@@ -0,0 +1,106 @@
+//! Ray, sight and box queries over hand-placed solid cells.
+
+use std::collections::HashSet;
+
+use bevy::prelude::*;
+use terra::game::{
+    raycast::{box_cast, cell_of, line_of_sight, raycast, Face},
+    tilemap::TileMap,
+};
+
+fn solid(cells: &[(i32, i32)]) -> impl Fn(IVec2) -> bool {
+    let cells: HashSet<IVec2> = cells.iter().map(|&(x, y)| IVec2::new(x, y)).collect();
+    move |cell| cells.contains(&cell)
+}
+
+fn assert_near(actual: f32, expected: f32) {
+    assert!((actual - expected).abs() < 1e-3, "expected {expected}, got {actual}");
+}
+
+#[test]
+fn cells_include_their_top_and_left_edges() {
+    assert_eq!(cell_of(Vec2::new(0.0, 0.0)), IVec2::new(0, -1));
+    assert_eq!(cell_of(Vec2::new(0.0, -0.5)), IVec2::new(0, 0));
+    assert_eq!(cell_of(Vec2::new(7.9, -8.0)), IVec2::new(0, 0));
+    assert_eq!(cell_of(Vec2::new(8.0, -8.1)), IVec2::new(1, 1));
+    assert_eq!(cell_of(Vec2::new(-0.1, -16.0)), IVec2::new(-1, 1));
+}
+
+#[test]
+fn the_tile_map_agrees_on_cells() {
+    let tilemap = TileMap::new(Handle::default(), Handle::default());
+    for x in -24..=24 {
+        for y in -24..=24 {
+            let point = Vec2::new(x as f32, y as f32) * 2.0;
+            assert_eq!(tilemap.cell_at(point), cell_of(point), "at {point}");
+        }
+    }
+}
+
+#[test]
+fn ray_stops_at_the_first_solid_cell() {
+    let hit = raycast(solid(&[(3, 0), (5, 0)]), Vec2::new(4.0, -4.0), Vec2::X, 100.0).unwrap();
+    assert_eq!(hit.cell, IVec2::new(3, 0));
+    assert_eq!(hit.face, Some(Face::Left));
+    assert_near(hit.distance, 20.0);
+    assert!(hit.point.abs_diff_eq(Vec2::new(24.0, -4.0), 1e-3));
+}
+
+#[test]
+fn ray_down_enters_through_the_top() {
+    let hit = raycast(solid(&[(0, 2)]), Vec2::new(4.0, -4.0), Vec2::NEG_Y, 100.0).unwrap();
+    assert_eq!(hit.cell, IVec2::new(0, 2));
+    assert_eq!(hit.face, Some(Face::Top));
+    assert_near(hit.distance, 12.0);
+}
+
+#[test]
+fn diagonal_ray_finds_the_cell_it_enters() {
+    let hit = raycast(solid(&[(2, 2)]), Vec2::new(4.0, -2.0), Vec2::new(1.0, -1.0), 100.0).unwrap();
+    assert_eq!(hit.cell, IVec2::new(2, 2));
+    assert_eq!(hit.face, Some(Face::Top));
+    assert!(hit.point.abs_diff_eq(Vec2::new(18.0, -16.0), 1e-3));
+    assert_near(hit.distance, 14.0 * 2f32.sqrt());
+}
+
+#[test]
+fn ray_starting_inside_a_solid_hits_at_once() {
+    let hit = raycast(solid(&[(0, 0)]), Vec2::new(4.0, -4.0), Vec2::X, 100.0).unwrap();
+    assert_eq!(hit.face, None);
+    assert_eq!(hit.distance, 0.0);
+}
+
+#[test]
+fn ray_stops_at_its_length() {
+    assert_eq!(raycast(solid(&[(3, 0)]), Vec2::new(4.0, -4.0), Vec2::X, 19.0), None);
+}
+
+#[test]
+fn endless_ray_through_nothing_gives_up() {
+    assert_eq!(raycast(solid(&[]), Vec2::new(4.0, -4.0), Vec2::new(1.0, -0.3), f32::INFINITY), None);
+    assert_eq!(raycast(solid(&[]), Vec2::new(4.0, -4.0), Vec2::X, f32::NAN), None);
+}
+
+#[test]
+fn sight_is_blocked_by_solids_between() {
+    let walls = solid(&[(3, 0)]);
+    assert!(!line_of_sight(&walls, Vec2::new(4.0, -4.0), Vec2::new(44.0, -4.0)));
+    assert!(line_of_sight(&walls, Vec2::new(4.0, -4.0), Vec2::new(20.0, -4.0)));
+    // Passing under the wall.
+    assert!(line_of_sight(&walls, Vec2::new(4.0, -12.0), Vec2::new(44.0, -12.0)));
+}
+
+#[test]
+fn box_cast_hits_with_its_leading_edge() {
+    let walls = solid(&[(3, 1)]);
+    // An 8 by 8 box over cells (0, 0) and (0, 1), overlapping the wall's row.
+    let hit = box_cast(&walls, Vec2::new(0.0, -4.0), Vec2::splat(8.0), Vec2::X, 100.0).unwrap();
+    assert_eq!(hit.cell, IVec2::new(3, 1));
+    assert!((hit.distance - 16.0).abs() < 0.05, "hit after {}", hit.distance);
+}
+
+#[test]
+fn box_cast_slides_along_a_floor_it_rests_on() {
+    let floor = solid(&[(0, 1), (1, 1), (2, 1), (3, 1), (4, 1), (5, 1)]);
+    assert_eq!(box_cast(&floor, Vec2::new(0.0, 0.0), Vec2::splat(8.0), Vec2::X, 32.0), None);
+}
@@ -0,0 +1,77 @@
+//! Recorded sessions play back to the same world.
+
+use bevy::prelude::*;
+use terra::game::{
+    headless::Simulation,
+    replay::{read_replay, write_replay, Divergence},
+    settings::Action,
+};
+
+const SEED: u32 = 77;
+
+/// Walks, jumps and knocks a wall down on generated terrain.
+fn record_session() -> Simulation {
+    let mut sim = Simulation::recording(SEED);
+    sim.run(30);
+    sim.press(Action::Right);
+    sim.run(90);
+    sim.press(Action::Jump);
+    sim.run(20);
+    sim.release(Action::Jump);
+    sim.release(Action::Right);
+
+    let (_, position) = sim.player();
+    sim.aim(Some(position + Vec2::new(4.0, -4.0)));
+    sim.press_button(MouseButton::Right);
+    sim.step();
+    sim.release_button(MouseButton::Right);
+    sim.aim(None);
+
+    sim.press(Action::Left);
+    for _ in 0..5 {
+        sim.run(40);
+        sim.press(Action::Jump);
+        sim.step();
+        sim.release(Action::Jump);
+    }
+    sim.release(Action::Left);
+    sim.run(60);
+    sim
+}
+
+#[test]
+fn replay_reproduces_the_session() {
+    let mut recorded = record_session();
+    let replay = recorded.recorded();
+    assert_eq!(replay.seed, SEED);
+    assert!(replay.checksums.len() >= 6, "only {} checksums", replay.checksums.len());
+
+    let mut played = Simulation::playing(replay.clone());
+    assert_eq!(played.play_to_end(), Ok(()));
+    assert_eq!(played.player().1, recorded.player().1);
+    assert_eq!(played.screen(), recorded.screen());
+    assert_eq!(played.tilemap().edits, recorded.tilemap().edits);
+}
+
+#[test]
+fn replay_survives_a_round_trip_through_a_file() {
+    let replay = record_session().recorded();
+    let path = std::env::temp_dir().join(format!("terra-replay-{}.ron", std::process::id()));
+    write_replay(&path, &replay).unwrap();
+    let read = read_replay(&path).unwrap();
+    std::fs::remove_file(&path).ok();
+    assert_eq!(read, replay);
+    assert_eq!(Simulation::playing(read).play_to_end(), Ok(()));
+}
+
+#[test]
+fn playback_reports_the_first_divergence() {
+    let mut replay = record_session().recorded();
+    let every = replay.checksum_every;
+    replay.checksums[2] ^= 1;
+    replay.checksums[4] ^= 1;
+    let Err(divergence) = Simulation::playing(replay.clone()).play_to_end() else {
+        panic!("a wrong checksum went unnoticed");
+    };
+    assert_eq!(divergence, Divergence { tick: 3 * every, expected: replay.checksums[2], actual: replay.checksums[2] ^ 1 });
+}